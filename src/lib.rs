@@ -0,0 +1,872 @@
+//! Bang语言编译器的库接口
+//!
+//! 使用[`compile`]按照给定的模式编译源码,
+//! 所有的错误都将以[`Diagnostic`]的形式返回, 而不会结束进程
+
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use display_source::DisplaySource;
+use syntax::{
+    propagate_consts,
+    rebuild_control_flow,
+    CompileMeta,
+    Error,
+    Expand,
+    Location,
+    LogicLine,
+    Meta,
+    SourceFile,
+};
+use parser::{
+    parse_with_imports,
+    SourceError,
+    lalrpop_util::ParseError,
+};
+use tag_code::{CompileTagCodesError, Peephole, TagCodes};
+use utils::{diagnostic::get_locations, msg};
+
+mod testing;
+
+pub use utils::diagnostic::{Diagnostic, Severity, Source};
+pub use utils::message::Message;
+pub use testing::{run_tests, TestResult, TEST_MAX_STEPS};
+
+const MAX_INVALID_TOKEN_VIEW: usize = 5;
+
+/// 大小报告中最多列出的项数, 其余的项将被合并
+const SIZE_REPORT_MAX_ENTRIES: usize = 20;
+
+/// 解析错误的退出码
+const PARSE_EXIT_CODE: i32 = 4;
+
+/// 语句数超出限制的退出码
+const INSTRUCTION_LIMIT_EXIT_CODE: i32 = 7;
+
+/// 游戏中逻辑处理器所能容纳的最大语句数, 可用作[`CompileOptions::max_instructions`]
+pub const MAX_INSTRUCTIONS: usize = 1000;
+
+/// 编译选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileOptions {
+    /// 依次应用到源码上的模式, 每个模式的输出是下一个模式的输入
+    pub modes: Vec<CompileMode>,
+    /// 源码所在的文件, 被导入的路径相对于它所在的目录
+    pub path: Option<PathBuf>,
+    /// 编译为逻辑代码时是否进行常量传播, 窥孔优化与临时变量的复用,
+    /// 见[`propagate_consts`], [`Peephole`]与[`TagCodes::reuse_tmp_vars`]
+    pub optimize: bool,
+    /// 编译为逻辑代码时是否生成源码映射, 见[`Output::source_map`]
+    pub source_map: bool,
+    /// 编译为逻辑代码时允许的最大语句数, 超出时产生错误, 为空时不限制,
+    /// 默认不限制
+    pub max_instructions: Option<usize>,
+    /// 编译为逻辑代码时是否报告各顶层const与顶层语句所生成的语句数
+    pub size_report: bool,
+}
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            modes: vec![CompileMode::BangToMdtLogic],
+            path: None,
+            optimize: false,
+            source_map: false,
+            max_instructions: None,
+            size_report: false,
+        }
+    }
+}
+
+/// 编译成功时的输出
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    /// 最后一个模式的输出
+    pub code: String,
+    /// 编译期间产生的不致命的诊断信息, 例如`Builtin.Info`的输出
+    pub diagnostics: Vec<Diagnostic>,
+    /// 开启[`CompileOptions::source_map`]且最后一个模式编译为逻辑代码时,
+    /// 输出的每一行逻辑代码的来源
+    ///
+    /// 每行形如`3 main.mdtlbl:5:9 via const Foo > Bar`,
+    /// 依次为从0开始的逻辑行号, 来源的文件与从1开始的行列号,
+    /// 以及生成该行时所处的const展开, 没有来源的行为`3 ?`
+    pub source_map: Option<String>,
+}
+
+/// 编译失败时的错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    /// 编译期间产生的所有诊断信息, 至少包含一个错误
+    pub diagnostics: Vec<Diagnostic>,
+    /// 命令行程序所使用的退出码
+    pub exit_code: i32,
+}
+
+/// 按照选项编译源码
+///
+/// # Examples
+/// ```
+/// # use mindustry_logic_bang_lang::{compile, CompileOptions};
+/// let output = compile("print 1;", &CompileOptions::default()).unwrap();
+/// assert_eq!(output.code, "print 1");
+///
+/// let diagnostics = compile("print 1", &CompileOptions::default()).unwrap_err();
+/// assert_eq!(diagnostics[0].span, Some((7, 7)));
+/// ```
+pub fn compile(
+    source: &str,
+    options: &CompileOptions,
+) -> Result<Output, Vec<Diagnostic>> {
+    compile_with_exit_code(source, options)
+        .map_err(|failure| failure.diagnostics)
+}
+
+/// 与[`compile`]相同, 但是失败时额外给出命令行程序所使用的退出码
+pub fn compile_with_exit_code(
+    source: &str,
+    options: &CompileOptions,
+) -> Result<Output, Failure> {
+    let mut code = source.to_owned();
+    let mut diagnostics = Vec::new();
+    let mut source_map = None;
+    for mode in &options.modes {
+        source_map = None;
+        match mode.compile_mapped(&code, options, &mut diagnostics, &mut source_map) {
+            Ok(out) => code = out,
+            Err(exit_code) => return Err(Failure { diagnostics, exit_code }),
+        }
+    }
+    Ok(Output { code, diagnostics, source_map })
+}
+
+/// 编译模式, 决定了输入与输出的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompileMode {
+    BangToMdtLogic,
+    BangToASTDebug,
+    BangToASTDisplay,
+    BangToMdtTagCode { tag_down: bool },
+    MdtLogicToMdtTagCode { tag_down: bool },
+    MdtLogicToBang { tag_down: bool },
+    MdtTagCodeToMdtLogic,
+}
+impl CompileMode {
+    /// 按照选项编译源码, 产生的诊断信息将被放入`diagnostics`, 失败时返回退出码
+    pub fn compile(
+        &self,
+        src: &str,
+        options: &CompileOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<String, i32> {
+        self.compile_mapped(src, options, diagnostics, &mut None)
+    }
+
+    /// 与[`Self::compile`]相同, 但是生成了源码映射时将其放入`source_map`
+    fn compile_mapped(
+        &self,
+        src: &str,
+        options: &CompileOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+        source_map: &mut Option<String>,
+    ) -> Result<String, i32> {
+        let path = options.path.as_deref();
+        let source = || Source::new(
+            path.map(|path| path.display().to_string()),
+            src,
+        );
+        Ok(match *self {
+            Self::BangToMdtLogic => {
+                let mut parse_meta = Meta::new();
+                // 来源也用于给编译时的警告附上所在的源码
+                parse_meta.set_record_origin(true);
+                let ast = parse(src, path, &mut parse_meta)
+                    .map_err(|e| fail(diagnostics, *e))?;
+                let mut statements = Vec::new();
+                if options.size_report {
+                    collect_statements(&ast, &mut statements);
+                }
+                let mut meta = CompileMeta::new();
+                meta.set_source_files(parse_meta.source_files().to_vec());
+                let mut meta = compile_ast(meta, ast, diagnostics)?;
+                if options.optimize {
+                    meta.tag_codes_mut().eliminate_dead_code()
+                        .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                    propagate_consts(meta.tag_codes_mut())
+                        .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                    // 被化简的跳转可能产生新的无法执行到的行
+                    meta.tag_codes_mut().eliminate_dead_code()
+                        .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                    Peephole::default().run(meta.tag_codes_mut())
+                        .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                    let saved = meta.tag_codes_mut().reuse_tmp_vars()
+                        .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                    let vars = meta.tag_codes().count_vars();
+                    diagnostics.push(Diagnostic::info(
+                        msg!(VarsReused, vars, saved),
+                    ).with_kind(Message::VarsReused));
+                }
+                let logic_lines = meta.tag_codes_mut().compile()
+                    .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                if options.source_map {
+                    *source_map = Some(build_source_map(
+                        meta.tag_codes(),
+                        parse_meta.source_files(),
+                    ));
+                }
+                if options.size_report {
+                    diagnostics.push(size_report(
+                        meta.tag_codes(),
+                        &statements,
+                        parse_meta.source_files(),
+                    ));
+                }
+                let count = meta.tag_codes().count_no_tag();
+                if let Some(max) = options.max_instructions.filter(|&max| count > max) {
+                    diagnostics.push(Diagnostic::error(
+                        msg!(InstructionLimitExceeded, count, max),
+                    ).with_kind(Message::InstructionLimitExceeded));
+                    return Err(INSTRUCTION_LIMIT_EXIT_CODE);
+                }
+                logic_lines.join("\n")
+            },
+            Self::BangToASTDebug => {
+                let ast = build_ast(src, path)
+                    .map_err(|e| fail(diagnostics, *e))?;
+                format!("{ast:#?}")
+            },
+            Self::BangToASTDisplay => {
+                let ast = build_ast(src, path)
+                    .map_err(|e| fail(diagnostics, *e))?;
+                display_ast(&ast)
+            },
+            Self::BangToMdtTagCode { tag_down } => {
+                let ast = build_ast(src, path)
+                    .map_err(|e| fail(diagnostics, *e))?;
+                let mut meta = compile_ast(CompileMeta::new(), ast, diagnostics)?;
+                if tag_down {
+                    build_tag_down(&mut meta)
+                        .map_err(|e| fail(diagnostics, *e))?;
+                }
+                meta.tag_codes().to_string()
+            },
+            Self::MdtLogicToMdtTagCode { tag_down } => {
+                let mut lines = TagCodes::from_str(src).map_err(|(line, e)| {
+                    let kind = e.message_kind();
+                    fail(diagnostics, logic_err(e, kind, line, source()))
+                })?;
+                if tag_down {
+                    lines.build_tagdown().map_err(|(line, tag)| {
+                        let e = CompileTagCodesError::DuplicateTag(line, tag);
+                        let kind = e.message_kind();
+                        fail(diagnostics, logic_err(e, kind, line, source()))
+                    })?;
+                    lines.tag_up();
+                }
+                lines.to_string()
+            },
+            Self::MdtLogicToBang { tag_down } => {
+                let mut lines = TagCodes::from_str(src).map_err(|(line, e)| {
+                    let kind = e.message_kind();
+                    fail(diagnostics, logic_err(e, kind, line, source()))
+                })?;
+                if tag_down {
+                    lines.build_tagdown().map_err(|(line, tag)| {
+                        let e = CompileTagCodesError::DuplicateTag(line, tag);
+                        let kind = e.message_kind();
+                        fail(diagnostics, logic_err(e, kind, line, source()))
+                    })?;
+                    lines.tag_up();
+                }
+                let ast = Expand::try_from(&lines).map_err(|(idx, e)| {
+                    let mut diagnostic = Diagnostic::error(
+                        msg!(LogicLineBuildFailed, idx + 1, e),
+                    ).with_kind(Message::LogicLineBuildFailed);
+                    if let Some(line) = lines.lines().get(idx) {
+                        diagnostic = diagnostic.with_note(msg!(LineNote, line));
+                    }
+                    fail(diagnostics, diagnostic)
+                })?;
+                display_ast(&rebuild_control_flow(&ast))
+            },
+            Self::MdtTagCodeToMdtLogic => {
+                let tag_codes = TagCodes::from_tag_lines(src);
+                let mut meta = CompileMeta::with_tag_codes(tag_codes);
+                let logic_lines = meta.tag_codes_mut().compile()
+                    .map_err(|e| fail(diagnostics, tag_codes_err(&meta, e)))?;
+                logic_lines.join("\n")
+            },
+        })
+    }
+
+    /// 从长模式名构建, 例如`bang-to-logic`
+    pub fn from_long_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "bang-to-logic" => Self::BangToMdtLogic,
+            "bang-to-ast" => Self::BangToASTDebug,
+            "bang-to-bang" => Self::BangToASTDisplay,
+            "bang-to-tag" => Self::BangToMdtTagCode { tag_down: false },
+            "bang-to-tag-builded" => Self::BangToMdtTagCode { tag_down: true },
+            "logic-to-tag" => Self::MdtLogicToMdtTagCode { tag_down: false },
+            "logic-to-tag-builded" => Self::MdtLogicToMdtTagCode { tag_down: true },
+            "logic-to-bang" => Self::MdtLogicToBang { tag_down: false },
+            "logic-to-bang-builded" => Self::MdtLogicToBang { tag_down: true },
+            "tag-to-logic" => Self::MdtTagCodeToMdtLogic,
+            _ => return None,
+        })
+    }
+
+    /// 该模式输出内容所使用的文件扩展名
+    pub fn output_extension(&self) -> &'static str {
+        match self {
+            Self::BangToMdtLogic | Self::MdtTagCodeToMdtLogic => "logic",
+            Self::BangToASTDebug => "ast",
+            Self::BangToASTDisplay | Self::MdtLogicToBang { .. } => "mdtlbl",
+            Self::BangToMdtTagCode { .. }
+            | Self::MdtLogicToMdtTagCode { .. } => "tag",
+        }
+    }
+}
+impl TryFrom<char> for CompileMode {
+    type Error = char;
+
+    fn try_from(mode: char) -> Result<Self, Self::Error> {
+        Ok(match mode {
+            'c' => Self::BangToMdtLogic,
+            'a' => Self::BangToASTDebug,
+            'A' => Self::BangToASTDisplay,
+            't' => Self::BangToMdtTagCode { tag_down: false },
+            'T' => Self::BangToMdtTagCode { tag_down: true },
+            'f' => Self::MdtLogicToMdtTagCode { tag_down: false },
+            'F' => Self::MdtLogicToMdtTagCode { tag_down: true },
+            'r' => Self::MdtLogicToBang { tag_down: false },
+            'R' => Self::MdtLogicToBang { tag_down: true },
+            'C' => Self::MdtTagCodeToMdtLogic,
+            mode => return Err(mode),
+        })
+    }
+}
+
+/// 记录一个解析阶段的错误, 并返回对应的退出码
+fn fail(diagnostics: &mut Vec<Diagnostic>, diagnostic: Diagnostic) -> i32 {
+    diagnostics.push(diagnostic);
+    PARSE_EXIT_CODE
+}
+
+fn display_ast(ast: &Expand) -> String {
+    let mut meta = Default::default();
+    ast.display_source(&mut meta);
+    let _ = meta.pop_lf();
+    meta.buffer().into()
+}
+
+fn build_tag_down(meta: &mut CompileMeta) -> Result<(), Box<Diagnostic>> {
+    meta.tag_codes_mut().build_tagdown().map_err(|(line, tag)| {
+        tag_codes_err(meta, CompileTagCodesError::DuplicateTag(line, tag))
+            .into()
+    })
+}
+
+/// 将[`TagCodes`]编译时的错误转换为诊断信息, 并尽可能的使用标记的名称
+fn tag_codes_err(meta: &CompileMeta, e: CompileTagCodesError) -> Diagnostic {
+    let tag = e.tag();
+    let tag_str = meta.tags_map()
+        .iter()
+        .find(|&(_k, v)| *v == tag)
+        .map_or_else(|| tag.to_string(), |(k, _v)| k.clone());
+    let diagnostic = Diagnostic::error(match e {
+        CompileTagCodesError::DuplicateTag(..) =>
+            msg!(DuplicateTag, format!("{tag_str:?}")),
+        CompileTagCodesError::TagOutOfRange(..) =>
+            msg!(TagOutOfRange, format!("{tag_str:?}")),
+    }).with_kind(e.message_kind());
+    match meta.tag_codes().lines().get(e.line()) {
+        Some(line) => diagnostic.with_note(msg!(LineNote, line)),
+        None => diagnostic,
+    }
+}
+
+/// 解析源码并处理其中的导入, 导入的文件从文件系统中读取
+///
+/// `path`为源码所在的文件
+pub fn build_ast(
+    src: &str,
+    path: Option<&Path>,
+) -> Result<Expand, Box<Diagnostic>> {
+    parse(src, path, &mut Meta::new())
+}
+
+/// 与[`build_ast`]相同, 但是使用给定的`meta`解析
+fn parse(
+    src: &str,
+    path: Option<&Path>,
+    meta: &mut Meta,
+) -> Result<Expand, Box<Diagnostic>> {
+    parse_with_imports(
+        meta,
+        src,
+        path,
+        |path| fs::read_to_string(path),
+    ).map_err(|e| parse_err_diagnostic(&e).into())
+}
+
+/// 收集顶层语句在源码中的位置, 包括被导入的文件中的顶层语句
+fn collect_statements(lines: &[LogicLine], statements: &mut Vec<(usize, [Location; 2])>) {
+    for line in lines {
+        let LogicLine::Spanned(spanned) = line else { continue };
+        statements.push((spanned.file, spanned.span));
+        if let LogicLine::Import(import) = spanned.line.unspanned() {
+            collect_statements(&import.lines, statements)
+        }
+    }
+}
+
+/// 按照已编译的代码中每行的来源, 统计各顶层const与顶层语句生成的语句数
+///
+/// 在const展开中生成的行计入最外层被展开的const, 其余的行计入包含其来源的顶层语句
+fn size_report(
+    tag_codes: &TagCodes,
+    statements: &[(usize, [Location; 2])],
+    files: &[SourceFile],
+) -> Diagnostic {
+    let unknown = || msg!(Unknown);
+    let mut sizes: Vec<(String, usize)> = Vec::new();
+    for line in tag_codes.lines() {
+        let name = match line.origin() {
+            Some(origin) if !origin.expand_stack.is_empty() => {
+                format!("const {}", origin.expand_stack[0])
+            },
+            Some(origin) => statements.iter()
+                .find(|&&(file, [l, r])| {
+                    file == origin.file
+                        && l <= origin.span.0
+                        && origin.span.1 <= r
+                })
+                .and_then(|&(file, span)| {
+                    describe_statement(files.get(file)?, span).into()
+                })
+                .unwrap_or_else(unknown),
+            None => unknown(),
+        };
+        match sizes.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, size)) => *size += 1,
+            None => sizes.push((name, 1)),
+        }
+    }
+    sizes.sort_by_key(|&(_, size)| Reverse(size));
+
+    let total = tag_codes.count_no_tag();
+    let mut diagnostic = Diagnostic::info(msg!(SizeReportTotal, total))
+        .with_kind(Message::SizeReportTotal);
+    for (name, size) in sizes.iter().take(SIZE_REPORT_MAX_ENTRIES) {
+        diagnostic = diagnostic.with_note(format!("{size:>5} {name}"));
+    }
+    let rest = sizes.get(SIZE_REPORT_MAX_ENTRIES..).unwrap_or_default();
+    if !rest.is_empty() {
+        let (count, size) = (rest.len(), rest.iter().map(|x| x.1).sum::<usize>());
+        diagnostic = diagnostic.with_note(
+            msg!(SizeReportRest, format!("{size:>5}"), count),
+        );
+    }
+    diagnostic
+}
+
+/// 以位置与首行源码描述一个顶层语句
+fn describe_statement(file: &SourceFile, [l, r]: [Location; 2]) -> String {
+    const MAX_VIEW: usize = 32;
+    let [[line, column]] = get_locations(&file.src, [l]);
+    let path = file.path.as_deref().unwrap_or("<stdin>");
+    let text = file.src.get(l..r).unwrap_or_default();
+    let first = text.lines().next().unwrap_or_default().trim_end();
+    let mut view = first.chars().take(MAX_VIEW).collect::<String>();
+    if view.len() < text.trim_end().len() {
+        view.push_str("...");
+    }
+    format!("{path}:{line}:{column} `{view}`")
+}
+
+/// 按照已编译的代码中每行的来源生成源码映射, 格式见[`Output::source_map`]
+fn build_source_map(tag_codes: &TagCodes, files: &[SourceFile]) -> String {
+    let lines = tag_codes.lines().iter().enumerate().map(|(i, line)| {
+        let Some((origin, file)) = line.origin()
+            .and_then(|origin| Some((origin, files.get(origin.file)?)))
+        else {
+            return format!("{i} ?");
+        };
+        let [[line, column]] = get_locations(&file.src, [origin.span.0]);
+        let path = file.path.as_deref().unwrap_or("<stdin>");
+        let mut res = format!("{i} {path}:{line}:{column}");
+        if !origin.expand_stack.is_empty() {
+            res.push_str(" via const ");
+            res.push_str(&origin.expand_stack.join(" > "));
+        }
+        res
+    });
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+fn compile_ast(
+    meta: CompileMeta,
+    ast: Expand,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<CompileMeta, i32> {
+    let mut meta = meta.compile_res_self(ast);
+    diagnostics.extend(meta.take_diagnostics());
+    match meta.errors().first() {
+        Some(err) => Err(err.exit_code()),
+        None => Ok(meta),
+    }
+}
+
+/// 将解析错误转换为诊断信息, 导入链将作为说明附加在末尾
+fn parse_err_diagnostic(e: &SourceError) -> Diagnostic {
+    fn fmt_expected(expected: &[String]) -> String {
+        let names = expected.iter()
+            .map(|s| get_token_name(s).unwrap_or(s))
+            .collect::<Vec<_>>()
+            .join(", ");
+        msg!(ExpectedNote, names)
+    }
+    let src = &*e.src;
+    let diagnostic = match &*e.error {
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected
+        } => {
+            Diagnostic::error(msg!(UnexpectedToken, format!("{:?}", token.1)))
+                .with_kind(Message::UnexpectedToken)
+                .with_span(*start, *end)
+                .with_note(fmt_expected(expected))
+        },
+        ParseError::ExtraToken { token: (start, token, end) } => {
+            let name = get_token_name(&token.1).unwrap_or(&token.1);
+            Diagnostic::error(msg!(ExtraToken, format!("{name:?}")))
+                .with_kind(Message::ExtraToken)
+                .with_span(*start, *end)
+        },
+        ParseError::InvalidToken { location } => {
+            let view = src[*location..].chars()
+                .take(MAX_INVALID_TOKEN_VIEW)
+                .collect::<String>();
+            let len = view.chars().next().map_or(0, char::len_utf8);
+            Diagnostic::error(
+                msg!(InvalidToken, format!("{:?}", view.trim_end())),
+            )
+                .with_kind(Message::InvalidToken)
+                .with_span(*location, location + len)
+        },
+        ParseError::UnrecognizedEof {
+            location,
+            expected
+        } => {
+            Diagnostic::error(msg!(UnexpectedEof))
+                .with_kind(Message::UnexpectedEof)
+                .with_span(*location, *location)
+                .with_note(fmt_expected(expected))
+        },
+        ParseError::User {
+            error: Error {
+                start,
+                end,
+                err
+            }
+        } => {
+            Diagnostic::error(err.to_string())
+                .with_kind(err.message_kind())
+                .with_span(*start, *end)
+        },
+    };
+    let source = Source::new(
+        e.path.as_ref().map(|path| path.display().to_string()),
+        e.src.as_str(),
+    );
+    let diagnostic = diagnostic.with_source(source);
+    e.import_chain.iter().rev().fold(diagnostic, |diagnostic, file| {
+        diagnostic.with_note(msg!(ImportedByNote, file.display()))
+    })
+}
+
+/// 逻辑代码中第`index`个非空行在源码中的范围
+fn logic_line_span(src: &str, index: usize) -> Option<(usize, usize)> {
+    src.split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end()))
+        })
+        .filter(|(_, line)| !line.trim().is_empty())
+        .nth(index)
+        .map(|(start, line)| (start, start + line.len()))
+}
+
+/// 从逻辑代码构建时在第`index`个非空行产生的错误
+fn logic_err(
+    msg: impl Display,
+    kind: Message,
+    index: usize,
+    source: Source,
+) -> Diagnostic {
+    let mut diagnostic = Diagnostic::error(msg.to_string()).with_kind(kind);
+    if let Some((start, end)) = logic_line_span(&source.src, index) {
+        diagnostic = diagnostic.with_span(start, end);
+    }
+    diagnostic.with_source(source)
+}
+
+fn get_token_name(s: &str) -> Option<&'static str> {
+    match s {
+        r###"r#"[_\\p{XID_Start}]\\p{XID_Continue}*"#"###
+            => "Identify",
+        r###"r#"@[_\\p{XID_Start}][\\p{XID_Continue}\\-]*"#"###
+            => "OIdentify",
+        r###"r#"(?:0(?:x-?[\\da-fA-F][_\\da-fA-F]*|b-?[01][_01]*)|-?\\d[_\\d]*(?:\\.\\d[\\d_]*|e[+\\-]?\\d[\\d_]*)?)"#"###
+            => "Number",
+        r###"r#"\"(?:\\\\\\r?\\n\\s*(?:\\\\ )?|\\r?\\n|\\\\[n\\\\\\[]|[^\"\\r\\n\\\\])*\""#"###
+            => "String",
+        r###"r#"'[^'\\s]+'"#"###
+            => "OtherVariable",
+        _ => return None,
+    }.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(modes: &str) -> CompileOptions {
+        CompileOptions {
+            modes: modes.chars()
+                .map(|mode| mode.try_into().unwrap())
+                .collect(),
+            path: None,
+            optimize: false,
+            source_map: false,
+            max_instructions: Some(MAX_INSTRUCTIONS),
+            size_report: false,
+        }
+    }
+
+    #[test]
+    fn compile_test() {
+        let output = compile(r#"
+            take Builtin.Info["hi"];
+            print 1;
+        "#, &options("c")).unwrap();
+        assert_eq!(output.code, "print 1");
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.diagnostics[0].severity, Severity::Info);
+        assert_eq!(output.diagnostics[0].message, "\"hi\"");
+    }
+
+    #[test]
+    fn dead_code_test() {
+        // 优化时无法执行到的行会被删除
+        let src = r#"
+            print 1;
+            goto :x;
+            print 2;
+            :x
+            select 1 { print 3; print 4; }
+        "#;
+        let output = compile(src, &options("c")).unwrap();
+        assert_eq!(output.code, "print 1\njump 3 always 0 0\nprint 2\n\
+            op add @counter @counter 1\nprint 3\nprint 4");
+        let output = compile(src, &CompileOptions {
+            optimize: true,
+            ..options("c")
+        }).unwrap();
+        assert_eq!(output.code, "print 1\nprint 4");
+
+        // 值未知的select的跳转表与填充不会被删除
+        let output = compile(r#"
+            select n {
+                print 1;
+                { print 2; print 3; }
+            }
+            print 4;
+        "#, &CompileOptions { optimize: true, ..options("c") }).unwrap();
+        assert_eq!(output.code, "op mul __0 n 2\nop add @counter @counter __0\n\
+            print 1\njump 4 always 0 0\nprint 2\nprint 3\nprint 4");
+
+        // 没有生成任何语句的源码
+        for src in ["", "const A = 1;"] {
+            let output = compile(src, &CompileOptions {
+                optimize: true,
+                ..options("c")
+            }).unwrap();
+            assert_eq!(output.code, "", "{src:?}");
+        }
+    }
+
+    #[test]
+    fn peephole_test() {
+        let output = compile(r#"
+            a = b;
+            b = a;
+            print a;
+        "#, &CompileOptions { optimize: true, ..options("c") }).unwrap();
+        assert_eq!(output.code, "set a b\nprint a");
+    }
+
+    #[test]
+    fn propagate_consts_test() {
+        let output = compile(r#"
+            i = 2;
+            n = i * 3;
+            if n > 5 { print n; } else { print 0; }
+        "#, &CompileOptions { optimize: true, ..options("c") }).unwrap();
+        assert_eq!(output.code, "set i 2\nset n 6\nprint n");
+
+        // 超出范围的移位与运行时相同
+        let output = compile(
+            "i = 70; x = 1 << i; print x;",
+            &CompileOptions { optimize: true, ..options("c") },
+        ).unwrap();
+        assert_eq!(output.code, "set i 70\nset x 64\nprint x");
+    }
+
+    #[test]
+    fn reuse_tmp_vars_test() {
+        let output = compile(r#"
+            print (op $ a + 1;);
+            print (op $ b + 2;);
+        "#, &CompileOptions { optimize: true, ..options("c") }).unwrap();
+        assert_eq!(output.code, "op add __0 a 1\nprint __0\nop add __0 b 2\nprint __0");
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.diagnostics[0].severity, Severity::Info);
+    }
+
+    #[test]
+    fn instruction_lint_test() {
+        // 未知的语句与错误的参数数量
+        let src = "sensr a b @x;\nucontrol move x;\nucontrol move x y;";
+        let output = compile(src, &options("c")).unwrap();
+        assert_eq!(output.diagnostics.len(), 2);
+        assert!(output.diagnostics.iter()
+            .all(|diagnostic| diagnostic.severity == Severity::Warning));
+        assert_eq!(output.diagnostics[0].kind, Some(Message::UnknownInstruction));
+        assert_eq!(output.diagnostics[1].kind, Some(Message::TooFewArgs));
+        assert_eq!(output.diagnostics[0].span, Some((0, 13)));
+        assert_eq!(output.diagnostics[1].span, Some((14, 30)));
+        assert!(output.diagnostics.iter().all(|diagnostic| {
+            diagnostic.source == Some(Source::new(None, src))
+        }));
+    }
+
+    #[test]
+    fn rebuild_control_flow_test() {
+        let output = compile("print 1\nprint 2", &options("rA")).unwrap();
+        assert_eq!(output.code, "'print' 1;\n'print' 2;");
+
+        // 重建跳转中的控制流
+        let output = compile(
+            "jump 2 greaterThanEq a b\nprint 1\nprint 2",
+            &options("r"),
+        ).unwrap();
+        assert_eq!(output.code, "if a < b {\n    'print' 1;\n}\n'print' 2;");
+    }
+
+    #[test]
+    fn source_map_test() {
+        let src = "const Foo = (\n    print 1;\n);\nprint 0;\ntake Foo;\n";
+        let output = compile(src, &options("c")).unwrap();
+        assert_eq!(output.source_map, None);
+        let mapped = compile(src, &CompileOptions {
+            source_map: true,
+            ..options("c")
+        }).unwrap();
+        assert_eq!(mapped.code, output.code);
+        assert_eq!(mapped.source_map.as_deref(), Some("\
+            0 <stdin>:4:1\n\
+            1 <stdin>:2:5 via const Foo"));
+
+        // 只有最后一个模式编译为逻辑代码时才有源码映射
+        let output = compile(src, &CompileOptions {
+            source_map: true,
+            ..options("cr")
+        }).unwrap();
+        assert_eq!(output.source_map, None);
+    }
+
+    #[test]
+    fn instruction_limit_test() {
+        let src = "print 1;\nprint 2;\nconst F = (print 3; print 4;);\ntake F;\n";
+        let failure = compile_with_exit_code(src, &CompileOptions {
+            max_instructions: Some(3),
+            ..options("c")
+        }).unwrap_err();
+        assert_eq!(failure.exit_code, 7);
+        assert_eq!(failure.diagnostics.len(), 1);
+        assert_eq!(failure.diagnostics[0].severity, Severity::Error);
+
+        let output = compile(src, &CompileOptions {
+            max_instructions: Some(4),
+            size_report: true,
+            ..options("c")
+        }).unwrap();
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.diagnostics[0].severity, Severity::Info);
+        assert_eq!(output.diagnostics[0].notes, [
+            "    2 const F",
+            "    1 <stdin>:1:1 `print 1;`",
+            "    1 <stdin>:2:1 `print 2;`",
+        ]);
+
+        // 默认不限制语句数
+        let src = "print 1;\n".repeat(MAX_INSTRUCTIONS + 1);
+        let output = compile(&src, &CompileOptions::default()).unwrap();
+        assert_eq!(output.code.lines().count(), MAX_INSTRUCTIONS + 1);
+        assert!(output.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn compile_error_test() {
+        let failure = compile_with_exit_code(
+            "print a b c",
+            &options("c"),
+        ).unwrap_err();
+        assert_eq!(failure.exit_code, 4);
+        assert_eq!(failure.diagnostics.len(), 1);
+        assert_eq!(failure.diagnostics[0].severity, Severity::Error);
+        assert_eq!(failure.diagnostics[0].kind, Some(Message::UnexpectedEof));
+        assert_eq!(failure.diagnostics[0].span, Some((11, 11)));
+        assert_eq!(
+            failure.diagnostics[0].source,
+            Some(Source::new(None, "print a b c")),
+        );
+
+        let failure = compile_with_exit_code(
+            "setres 2; print $;",
+            &options("c"),
+        ).unwrap_err();
+        assert_eq!(failure.exit_code, 6);
+        assert_eq!(failure.diagnostics.len(), 2);
+        assert!(failure.diagnostics.iter().all(|diagnostic| {
+            diagnostic.kind == Some(Message::OutOfDExp)
+        }));
+
+        let failure = compile_with_exit_code(
+            "print 1; take Builtin.Exit[3]; print 2;",
+            &options("c"),
+        ).unwrap_err();
+        assert_eq!(failure.exit_code, 3);
+
+        let failure = compile_with_exit_code(
+            "jump 3 always 0 0\nprint 1",
+            &options("C"),
+        ).unwrap_err();
+        assert_eq!(failure.exit_code, 4);
+        assert_eq!(failure.diagnostics.len(), 1);
+        assert_eq!(failure.diagnostics[0].kind, Some(Message::TagOutOfRange));
+
+        let failure = compile_with_exit_code(
+            "print 1\nprint \"a",
+            &options("r"),
+        ).unwrap_err();
+        assert_eq!(failure.exit_code, 4);
+    }
+}
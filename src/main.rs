@@ -1,32 +1,41 @@
 use std::{
+    collections::HashMap,
     env::args,
+    fs,
     io::{
         stdin,
         Read
     },
+    path::{Path, PathBuf},
     process::exit,
-    str::FromStr,
-    collections::HashMap,
-    ops::Deref, fmt::Display,
+    fmt::Display,
 };
 
-use display_source::DisplaySource;
+use emulator::Emulator;
+use mindustry_logic_bang_lang::{
+    build_ast,
+    compile_with_exit_code,
+    run_tests,
+    CompileMode,
+    CompileOptions,
+    Failure,
+    Output,
+};
 use syntax::{
-    CompileMeta,
-    Error,
-    Errors,
-    Expand,
-    Meta,
-    line_first_add,
+    Const,
+    ConstKey,
+    LogicLine,
+    Var,
 };
 use parser::{
-    TopLevelParser,
-    lalrpop_util::{
-        lexer::Token,
-        ParseError
-    },
+    STD_MODULES,
+    STD_PREFIX,
+};
+use utils::{
+    diagnostic::{set_message_format, MessageFormat},
+    locale::{set_locale, Locale},
+    msg,
 };
-use tag_code::TagCodes;
 
 /// 带有错误前缀, 并且文本为红色的eprintln
 macro_rules! err {
@@ -47,215 +56,385 @@ macro_rules! concat_lines {
 }
 
 pub const HELP_MSG: &str = concat_lines! {
-    "<MODE...>";
+    "<MODE...> [OPTIONS] [FILE...]";
     "Author: A4-Tacks A4的钉子";
     "Version: ", env!("CARGO_PKG_VERSION");
     "https://github.com/A4-Tacks/mindustry_logic_bang_lang";
     "MODE:";
-    "\t", "c: compile MdtBangLang to MdtLogicCode";
-    "\t", "a: compile MdtBangLang to AST Debug";
-    "\t", "A: compile MdtBangLang to MdtBangLang";
-    "\t", "t: compile MdtBangLang to MdtTagCode";
-    "\t", "T: compile MdtBangLang to MdtTagCode (Builded TagDown)";
-    "\t", "f: compile MdtLogicCode to MdtTagCode";
-    "\t", "F: compile MdtLogicCode to MdtTagCode (Builded TagDown)";
-    "\t", "r: compile MdtLogicCode to MdtBangLang";
-    "\t", "R: compile MdtLogicCode to MdtBangLang (Builded TagDown)";
-    "\t", "C: compile MdtTagCode to MdtLogicCode";
+    "\t", "c, --bang-to-logic: compile MdtBangLang to MdtLogicCode";
+    "\t", "a, --bang-to-ast: compile MdtBangLang to AST Debug";
+    "\t", "A, --bang-to-bang: compile MdtBangLang to MdtBangLang";
+    "\t", "t, --bang-to-tag: compile MdtBangLang to MdtTagCode";
+    "\t", "T, --bang-to-tag-builded: compile MdtBangLang to MdtTagCode (Builded TagDown)";
+    "\t", "f, --logic-to-tag: compile MdtLogicCode to MdtTagCode";
+    "\t", "F, --logic-to-tag-builded: compile MdtLogicCode to MdtTagCode (Builded TagDown)";
+    "\t", "r, --logic-to-bang: compile MdtLogicCode to MdtBangLang";
+    "\t", "R, --logic-to-bang-builded: compile MdtLogicCode to MdtBangLang (Builded TagDown)";
+    "\t", "    r and R rebuild if, while, do-while, gwhile and select from the jumps";
+    "\t", "C, --tag-to-logic: compile MdtTagCode to MdtLogicCode";
+    ;
+    "MODE can be a string of single-char modes, such as `cT`,";
+    "or some long-form mode flags, such as `--bang-to-logic`.";
+    "Modes are executed in order, the output of each mode is the input of the next";
+    ;
+    "OPTIONS:";
+    "\t", "-o, --output <PATH>: output to PATH,";
+    "\t", "    if there are multiple FILE, PATH is a directory,";
+    "\t", "    and FILEs with the same file name are an error";
+    "\t", "-O, --optimize: apply constant propagation and peephole optimizations";
+    "\t", "    to the output MdtLogicCode, such as removing jumps to the next line,";
+    "\t", "    remove lines that can never be executed,";
+    "\t", "    and reuse temporary variables whose live ranges do not overlap";
+    "\t", "--source-map: also write `<PATH>.map` next to each output,";
+    "\t", "    each line is `N file:line:col [via const A > B]` for logic line N,";
+    "\t", "    requires `--output` and the last MODE to be `c`";
+    "\t", "--max-instructions <N>: error if the output MdtLogicCode has more than N";
+    "\t", "    instructions, a logic processor holds at most 1000 instructions,";
+    "\t", "    0 means no limit, no limit by default";
+    "\t", "--size-report: report the instructions generated by each top-level const";
+    "\t", "    and top-level statement when compiling to MdtLogicCode";
+    "\t", "--list-std: list the const names exported by each std module";
+    "\t", "--test: run the blocks marked with `#[test]` in each FILE instead of compiling,";
+    "\t", "    MODE is not required, exit with 1 if any test failed";
+    "\t", "--run <STEPS>: run the output MdtLogicCode for STEPS instructions,";
+    "\t", "    and output the variables and message buffers instead of the code";
+    "\t", "--message-format=<FMT>: format of diagnostics, human or json,";
+    "\t", "    json prints one object per line to stderr";
+    "\t", "--lang <LOCALE>: language of messages, zh_CN or en_US,";
+    "\t", "    default from the LC_ALL, LC_MESSAGES or LANG environment variable";
+    "\t", "-h, --help: show this help";
     ;
-    "input from stdin";
-    "output to stdout";
+    "input from FILE..., or stdin if no FILE or FILE is `-`";
+    "output to stdout, or PATH of `--output`";
     "error to stderr";
 };
 
-const MAX_INVALID_TOKEN_VIEW: usize = 5;
-
 fn help() {
     eprint!("{} {}", args().next().unwrap(), HELP_MSG);
 }
 
-fn main() {
-    let mut args = args();
-    args.next().unwrap();
-    let Some(mode) = args.next() else {
-        err!("no MODE");
-        help();
-        exit(1)
-    };
-    if let Some(arg) = args.next() {
-        err!("多余的参数: {:?}", arg);
-        exit(2);
-    }
-    let modes = Vec::from_iter(
-        mode.chars()
-            .map(|char| {
+/// 从命令行参数中解析出的选项
+#[derive(Default)]
+struct Options {
+    modes: Vec<CompileMode>,
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    /// 运行输出的逻辑代码的语句数
+    run: Option<usize>,
+    /// 运行测试而不是编译
+    test: bool,
+    /// 进行窥孔优化
+    optimize: bool,
+    /// 在输出旁写出源码映射
+    source_map: bool,
+    /// 输出的逻辑代码的最大语句数
+    max_instructions: Option<usize>,
+    /// 报告各部分生成的语句数
+    size_report: bool,
+}
+impl Options {
+    /// 解析命令行参数, 参数中不包含程序名
+    ///
+    /// 如果没有使用长模式名, 那么第一个位置参数将作为单字符模式串
+    fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut this = Self::default();
+        let mut positionals = Vec::new();
+        let mut args = args.into_iter();
+        let mut only_positional = false;
+
+        while let Some(arg) = args.next() {
+            if only_positional || arg == "-" || !arg.starts_with('-') {
+                positionals.push(arg);
+                continue;
+            }
+            match &*arg {
+                "--" => only_positional = true,
+                "-h" | "--help" => {
+                    help();
+                    exit(0)
+                },
+                "--list-std" => {
+                    list_std();
+                    exit(0)
+                },
+                "-o" | "--output" => {
+                    let Some(path) = args.next() else {
+                        err!("{}", msg!(OptionRequiresPath, arg));
+                        exit(2)
+                    };
+                    if this.output.replace(path.into()).is_some() {
+                        err!("{}", msg!(OptionGivenTwice, arg));
+                        exit(2)
+                    }
+                },
+                "--test" => this.test = true,
+                "-O" | "--optimize" => this.optimize = true,
+                "--source-map" => this.source_map = true,
+                "--size-report" => this.size_report = true,
+                "--max-instructions" => {
+                    let Some(max) = args.next()
+                        .and_then(|max| max.parse().ok())
+                    else {
+                        err!("{}", msg!(OptionRequiresNumber, arg));
+                        exit(2)
+                    };
+                    this.max_instructions = (max != 0).then_some(max)
+                },
+                "--run" => {
+                    let Some(steps) = args.next()
+                        .and_then(|steps| steps.parse().ok())
+                    else {
+                        err!("{}", msg!(OptionRequiresSteps, arg));
+                        exit(2)
+                    };
+                    this.run = Some(steps)
+                },
+                "--lang" => {
+                    let Some(name) = args.next() else {
+                        err!("{}", msg!(OptionRequiresLocale, arg));
+                        exit(2)
+                    };
+                    let Some(locale) = Locale::from_name(&name) else {
+                        err!("{}", msg!(UnknownLocale, format!("{name:?}")));
+                        exit(2)
+                    };
+                    set_locale(locale)
+                },
+                fmt if fmt.starts_with("--message-format=") => {
+                    let name = &fmt["--message-format=".len()..];
+                    let Some(format) = MessageFormat::from_name(name) else {
+                        err!("{}", msg!(UnknownMessageFormat, format!("{name:?}")));
+                        exit(2)
+                    };
+                    set_message_format(format)
+                },
+                long if long.starts_with("--") => {
+                    let Some(mode) = CompileMode::from_long_name(&long[2..])
+                    else {
+                        err!("{}", msg!(UnknownOptionOrMode, format!("{long:?}")));
+                        help();
+                        exit(2)
+                    };
+                    this.modes.push(mode)
+                },
+                other => {
+                    err!("{}", msg!(UnknownOption, format!("{other:?}")));
+                    help();
+                    exit(2)
+                },
+            }
+        }
+
+        let mut positionals = positionals.into_iter();
+        if this.modes.is_empty() && !this.test {
+            let Some(mode) = positionals.next() else {
+                err!("{}", msg!(NoMode));
+                help();
+                exit(1)
+            };
+            this.modes.extend(mode.chars().map(|char| {
                 CompileMode::try_from(char).unwrap_or_else(|mode| {
-                    err!("mode {mode:?} no pattern");
+                    err!("{}", msg!(UnknownMode, format!("{mode:?}")));
                     help();
                     exit(2)
                 })
-            })
-    );
-    let mut src = read_stdin();
-    for mode in modes {
-        src = mode.compile(&src)
+            }));
+        }
+        this.inputs.extend(positionals.map(PathBuf::from));
+        if this.run.is_some()
+            && this.modes.last() != Some(&CompileMode::BangToMdtLogic)
+            && this.modes.last() != Some(&CompileMode::MdtTagCodeToMdtLogic)
+        {
+            err!("{}", msg!(RunRequiresLogicOutput));
+            exit(2)
+        }
+        if this.source_map {
+            if this.output.is_none() || this.run.is_some() {
+                err!("{}", msg!(SourceMapRequiresOutput));
+                exit(2)
+            }
+            if this.modes.last() != Some(&CompileMode::BangToMdtLogic) {
+                err!("{}", msg!(SourceMapRequiresLogicMode));
+                exit(2)
+            }
+        }
+        this
     }
-    println!("{src}")
-}
 
-enum CompileMode {
-    BangToMdtLogic,
-    BangToASTDebug,
-    BangToASTDisplay,
-    BangToMdtTagCode { tag_down: bool },
-    MdtLogicToMdtTagCode { tag_down: bool },
-    MdtLogicToBang { tag_down: bool },
-    MdtTagCodeToMdtLogic,
-}
-impl CompileMode {
-    fn compile(&self, src: &str) -> String {
-        match *self {
-            Self::BangToMdtLogic => {
-                let ast = build_ast(src);
-                let mut meta = compile_ast(ast);
-                build_tag_down(&mut meta);
-                let logic_lines = meta.tag_codes_mut().compile().unwrap();
-                logic_lines.join("\n")
-            },
-            Self::BangToASTDebug => {
-                let ast = build_ast(src);
-                format!("{ast:#?}")
-            },
-            Self::BangToASTDisplay => {
-                let ast = build_ast(src);
-                display_ast(&ast)
-            },
-            Self::BangToMdtTagCode { tag_down } => {
-                let ast = build_ast(src);
-                let mut meta = compile_ast(ast);
-                if tag_down { build_tag_down(&mut meta); }
-                meta.tag_codes().to_string()
-            },
-            Self::MdtLogicToMdtTagCode { tag_down } => {
-                match TagCodes::from_str(src) {
-                    Ok(mut lines) => {
-                        if tag_down {
-                            lines.build_tagdown().unwrap();
-                            lines.tag_up();
-                        }
-                        lines.to_string()
-                    },
-                    Err((line, e)) => {
-                        err!("line: {}, {:?}", line, e);
-                        exit(4);
-                    },
-                }
-            },
-            Self::MdtLogicToBang { tag_down } => {
-                match TagCodes::from_str(src) {
-                    Ok(mut lines) => {
-                        if tag_down {
-                            lines.build_tagdown().unwrap();
-                            lines.tag_up();
-                        }
-                        let ast = Expand::try_from(&lines)
-                            .unwrap_or_else(|(idx, e)| {
-                                let mut lines_str = lines.iter()
-                                    .map(ToString::to_string)
-                                    .collect::<Vec<_>>();
-                                line_first_add(&mut lines_str, "    ");
-                                err!(
-                                    "在构建第{}行时出错: {}\n\
-                                    已构建的行:\n\
-                                    {}",
-                                    lines_str.join("\n"),
-                                    idx + 1,
-                                    e
-                                );
-                                exit(4);
-                            });
-                        display_ast(&ast)
-                    },
-                    Err((line, e)) => {
-                        err!("line: {}, {:?}", line, e);
-                        exit(4);
-                    },
-                }
+    /// 将所有模式依次应用到源码上, `path`为源码所在文件,
+    /// 返回输出与可能的源码映射
+    ///
+    /// 输出所有的诊断信息, 失败时以对应的退出码结束进程
+    fn compile(&self, src: String, path: Option<&Path>) -> (String, Option<String>) {
+        let options = CompileOptions {
+            modes: self.modes.clone(),
+            path: path.map(Path::to_path_buf),
+            optimize: self.optimize,
+            source_map: self.source_map,
+            max_instructions: self.max_instructions,
+            size_report: self.size_report,
+        };
+        match compile_with_exit_code(&src, &options) {
+            Ok(Output { code, diagnostics, source_map }) => {
+                diagnostics.iter().for_each(|diagnostic| diagnostic.emit());
+                let code = match self.run {
+                    Some(steps) => run_logic(&code, steps),
+                    None => code,
+                };
+                (code, source_map)
             },
-            Self::MdtTagCodeToMdtLogic => {
-                let tag_codes = TagCodes::from_tag_lines(src);
-                let mut meta = CompileMeta::with_tag_codes(tag_codes);
-                build_tag_down(&mut meta);
-                let logic_lines = meta.tag_codes_mut().compile().unwrap();
-                logic_lines.join("\n")
+            Err(Failure { diagnostics, exit_code }) => {
+                diagnostics.iter().for_each(|diagnostic| diagnostic.emit());
+                exit(exit_code)
             },
         }
     }
-}
-impl TryFrom<char> for CompileMode {
-    type Error = char;
 
-    fn try_from(mode: char) -> Result<Self, Self::Error> {
-        Ok(match mode {
-            'c' => Self::BangToMdtLogic,
-            'a' => Self::BangToASTDebug,
-            'A' => Self::BangToASTDisplay,
-            't' => Self::BangToMdtTagCode { tag_down: false },
-            'T' => Self::BangToMdtTagCode { tag_down: true },
-            'f' => Self::MdtLogicToMdtTagCode { tag_down: false },
-            'F' => Self::MdtLogicToMdtTagCode { tag_down: true },
-            'r' => Self::MdtLogicToBang { tag_down: false },
-            'R' => Self::MdtLogicToBang { tag_down: true },
-            'C' => Self::MdtTagCodeToMdtLogic,
-            mode => return Err(mode),
-        })
+    /// 多个输入时, 输出到`--output`所给目录中的对应文件路径
+    fn output_path_in_dir(&self, dir: &Path, input: &Path) -> PathBuf {
+        let ext = self.modes.last()
+            .map(CompileMode::output_extension)
+            .unwrap_or("txt");
+        let name = input.file_name()
+            .map(Path::new)
+            .unwrap_or_else(|| Path::new("stdin"));
+        dir.join(name).with_extension(ext)
     }
 }
 
-fn display_ast(ast: &Expand) -> String {
-    let mut meta = Default::default();
-    ast.display_source(&mut meta);
-    let _ = meta.pop_lf();
-    meta.buffer().into()
+fn main() {
+    let mut args = args();
+    args.next().unwrap();
+    let options = Options::parse(args);
+    if options.test {
+        test_inputs(&options.inputs)
+    }
+
+    match &options.inputs[..] {
+        [] => {
+            let out = options.compile(read_stdin(), None);
+            write_outputs(options.output.as_deref(), out)
+        },
+        [input] => {
+            let out = options.compile(read_input(input), input_path(input));
+            write_outputs(options.output.as_deref(), out)
+        },
+        inputs => {
+            let paths = inputs.iter()
+                .map(|input| options.output.as_deref()
+                    .map(|dir| options.output_path_in_dir(dir, input)))
+                .collect::<Vec<_>>();
+            let mut outputs = HashMap::new();
+            for (input, path) in inputs.iter().zip(&paths) {
+                let Some(path) = path else { continue };
+                if let Some(other) = outputs.insert(path, input) {
+                    err!("{}", msg!(
+                        SameOutputPath,
+                        format!("{other:?}"),
+                        format!("{input:?}"),
+                        format!("{path:?}"),
+                    ));
+                    exit(2)
+                }
+            }
+            if let Some(dir) = &options.output {
+                if let Err(e) = fs::create_dir_all(dir) {
+                    err!("{}", msg!(CreateOutputDirFailed, format!("{dir:?}"), e));
+                    exit(3)
+                }
+            }
+            for (input, path) in inputs.iter().zip(paths) {
+                let out = options.compile(
+                    read_input(input),
+                    input_path(input),
+                );
+                write_outputs(path.as_deref(), out)
+            }
+        },
+    }
 }
 
-fn build_tag_down(meta: &mut CompileMeta) {
-    let result = meta.tag_codes_mut().build_tagdown();
-    result.unwrap_or_else(|(_line, tag)| {
-        let (tag_str, _id) = meta.tags_map()
-            .iter()
-            .filter(|&(_k, v)| *v == tag)
-            .take(1)
-            .last()
-            .unwrap();
-        let mut tags_map = meta.debug_tags_map();
-        let mut tag_codes = meta.tag_codes().iter().map(ToString::to_string).collect();
-        line_first_add(&mut tags_map, "    ");
-        line_first_add(&mut tag_codes, "    ");
-        err!(
-            "重复的标记: {:?}\n\
-            TagCode:\n\
-            {}\n\
-            TagsMap:\n\
-            {}",
-            tag_codes.join("\n"),
-            tags_map.join("\n"),
-            tag_str,
-        );
+/// 在模拟器中运行逻辑代码`steps`条语句, 返回运行后的状态
+fn run_logic(code: &str, steps: usize) -> String {
+    let lines = code.lines().collect::<Vec<_>>();
+    let mut emulator = Emulator::new(&lines).unwrap_or_else(|(line, e)| {
+        err!("{}", msg!(LogicParseFailed, line + 1, e));
         exit(4)
-    })
+    });
+    emulator.run(steps);
+    emulator.dump()
 }
 
-type ParseResult<'a> = Result<Expand, ParseError<usize, Token<'a>, Error>>;
+/// 运行每个输入中的测试, 有测试失败时以退出码1结束
+fn test_inputs(inputs: &[PathBuf]) -> ! {
+    let stdin = [PathBuf::from("-")];
+    let inputs = if inputs.is_empty() { &stdin[..] } else { inputs };
+    let (mut passed, mut failed) = (0, 0);
+    for input in inputs {
+        let src = read_input(input);
+        let results = run_tests(&src, input_path(input))
+            .unwrap_or_else(|diagnostic| {
+                diagnostic.emit();
+                exit(4)
+            });
+        println!("{}", msg!(TestsRunning, results.len(), input.display()));
+        for result in results {
+            if result.passed() {
+                passed += 1;
+                println!("test {} ... ok", result.name);
+                continue;
+            }
+            failed += 1;
+            println!("test {} ... FAILED", result.name);
+            for failure in &result.failures {
+                println!("    {failure}");
+            }
+            result.diagnostics.iter().for_each(|diagnostic| diagnostic.emit());
+        }
+    }
+    let status = if failed == 0 { "ok" } else { "FAILED" };
+    println!("{}", msg!(TestsResult, status, passed, failed));
+    exit(if failed == 0 { 0 } else { 1 })
+}
 
-fn build_ast(src: &str) -> Expand {
-    let parser = TopLevelParser::new();
-    let mut meta = Meta::new();
-    unwrap_parse_err(parser.parse(&mut meta, src), src)
+/// 输出每个标准库模块所导出的const名称
+fn list_std() {
+    for &(name, src) in STD_MODULES {
+        let path = [STD_PREFIX, name].concat();
+        let ast = build_ast(src, Some(Path::new(&path)))
+            .unwrap_or_else(|diagnostic| {
+                diagnostic.emit();
+                exit(4)
+            });
+        println!("{path}");
+        for name in exported_consts(&ast) {
+            println!("    {name}");
+        }
+    }
+}
+
+/// 获取顶层定义的const名称, 包括被导入的
+fn exported_consts(lines: &[LogicLine]) -> Vec<&Var> {
+    let mut names = Vec::new();
+    for line in lines {
+        match line {
+            LogicLine::Const(Const(ConstKey::Var(name), ..))
+                if !names.contains(&name) => names.push(name),
+            LogicLine::Import(import) => {
+                for name in exported_consts(&import.lines) {
+                    if !names.contains(&name) {
+                        names.push(name)
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+    names
 }
 
 fn read_stdin_unwrapper(e: impl Display) -> ! {
-    err!("read from stdin error: {}", e);
+    err!("{}", msg!(ReadStdinFailed, e));
     exit(3)
 }
 
@@ -267,161 +446,40 @@ fn read_stdin() -> String {
     buf
 }
 
-/// 给定位置与源码, 返回行列号, 行列都从1开始<br/>
-/// 如果没有找到, 则返回`[0, 0]`
-fn get_locations<const N: usize>(src: &str, indexs: [usize; N]) -> [[usize; 2]; N] {
-    const CR: char = '\n';
-
-    let mut index_maps: HashMap<usize, Vec<usize>> = HashMap::with_capacity(indexs.len());
-    for (i, loc) in indexs.into_iter().enumerate() {
-        index_maps.entry(loc).or_default().push(i)
-    }
-    let mut res = [[0, 0]; N];
-    let [mut line, mut column] = [1, 1];
-    macro_rules! set {
-        ($i:expr) => {
-            if let Some(idxs) = index_maps.get(&$i) {
-                for &idx in idxs {
-                    res[idx] = [line, column]
-                }
-            };
-        };
-    }
-    for (i, ch) in src.char_indices() {
-        set!(i);
-        if ch == CR {
-            line += 1;
-            column = 1;
-        } else {
-            column += 1;
-        }
+/// 读取一个输入文件, 如果路径为`-`则从stdin读取
+fn read_input(path: &Path) -> String {
+    if path == Path::new("-") {
+        return read_stdin();
     }
-    set!(src.len());
-    res
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        err!("{}", msg!(ReadFileFailed, format!("{path:?}"), e));
+        exit(3)
+    })
 }
 
-fn unwrap_parse_err(result: ParseResult<'_>, src: &str) -> Expand {
-    match result {
-        Ok(ast) => ast,
-        Err(e) => {
-            fn fmt_token<'a>(i: impl IntoIterator<Item = &'a str>)
-            -> impl Iterator<Item = &'a str> {
-                i.into_iter()
-                    .map(|s| get_token_name(s).unwrap_or(s))
-            }
-            match e {
-                ParseError::UnrecognizedToken {
-                    token: (start, token, end),
-                    expected
-                } => {
-                    let [start, end] = get_locations(src, [start, end]);
-                    err!(
-                        "在位置 {:?} 至 {:?} 处找到不应出现的令牌: {:?}\n\
-                        预期: [{}]",
-                        start, end,
-                        token.1,
-                        fmt_token(expected.iter().map(Deref::deref))
-                            .collect::<Vec<_>>()
-                            .join(", "),
-                    );
-                },
-                ParseError::ExtraToken { token: (start, token, end) } => {
-                    let [start, end] = get_locations(src, [start, end]);
-                    err!(
-                        "在位置 {:?} 至 {:?} 处找到多余的令牌: {:?}",
-                        start, end,
-                        fmt_token(Some(token.1)).next().unwrap(),
-                    );
-                },
-                ParseError::InvalidToken { location } => {
-                    let [loc] = get_locations(src, [location]);
-                    let view = &src[
-                        location
-                            ..src.len().min(location+MAX_INVALID_TOKEN_VIEW)
-                    ];
-                    err!(
-                        "在位置 {:?} 处找到无效的令牌: {:?}",
-                        loc,
-                        view.trim_end(),
-                    );
-                },
-                ParseError::UnrecognizedEof {
-                    location,
-                    expected
-                } => {
-                    let [start] = get_locations(src, [location]);
-                    err!(
-                        "在位置 {:?} 处意外的结束\n\
-                        预期: [{}]",
-                        start,
-                        fmt_token(expected.iter().map(Deref::deref))
-                            .collect::<Vec<_>>()
-                            .join(", "),
-                    );
-                },
-                ParseError::User {
-                    error: Error {
-                        start,
-                        end,
-                        err
-                    }
-                } => {
-                    let [start, end]
-                        = get_locations(src, [start, end]);
-                    let out = |msg| err!(
-                        "在位置 {:?} 至 {:?} 处的错误:\n{}",
-                        start,
-                        end,
-                        msg
-                    );
-                    match err {
-                        Errors::NotALiteralUInteger(str, err) => {
-                            out(format_args!(
-                                "{:?} 不是一个有效的无符号整数, 错误: {}",
-                                str,
-                                err,
-                            ));
-                        },
-                        Errors::SetVarNoPatternValue(var_count, val_count) => {
-                            out(format_args!(
-                                "sets两侧值数量不匹配, {} != {}",
-                                var_count,
-                                val_count,
-                            ));
-                        },
-                        Errors::ArgsRepeatChunkByZero => {
-                            out(format_args!(
-                                "重复块的迭代数不能为0",
-                            ));
-                        },
-                        #[allow(unreachable_patterns)]
-                        e => {
-                            out(format_args!("未被枚举的错误: {:?}", e));
-                        },
-                    }
-                },
-            }
-            exit(4)
-        }
-    }
+/// 输入文件的路径, 从stdin输入时为空
+fn input_path(path: &Path) -> Option<&Path> {
+    (path != Path::new("-")).then_some(path)
 }
 
-fn compile_ast(ast: Expand) -> CompileMeta {
-    CompileMeta::new().compile_res_self(ast)
+/// 写出结果, 源码映射被写入到输出路径后追加`.map`的文件中
+fn write_outputs(path: Option<&Path>, (out, source_map): (String, Option<String>)) {
+    if let (Some(path), Some(source_map)) = (path, source_map) {
+        let mut map_path = path.as_os_str().to_owned();
+        map_path.push(".map");
+        write_output(Some(Path::new(&map_path)), source_map)
+    }
+    write_output(path, out)
 }
 
-fn get_token_name(s: &str) -> Option<&'static str> {
-    match s {
-        r###"r#"[_\\p{XID_Start}]\\p{XID_Continue}*"#"###
-            => "Identify",
-        r###"r#"@[_\\p{XID_Start}][\\p{XID_Continue}\\-]*"#"###
-            => "OIdentify",
-        r###"r#"(?:0(?:x-?[\\da-fA-F][_\\da-fA-F]*|b-?[01][_01]*)|-?\\d[_\\d]*(?:\\.\\d[\\d_]*|e[+\\-]?\\d[\\d_]*)?)"#"###
-            => "Number",
-        r###"r#"\"(?:\\\\\\r?\\n\\s*(?:\\\\ )?|\\r?\\n|\\\\[n\\\\\\[]|[^\"\\r\\n\\\\])*\""#"###
-            => "String",
-        r###"r#"'[^'\\s]+'"#"###
-            => "OtherVariable",
-        _ => return None,
-    }.into()
+/// 写出结果, 如果没有给定路径则输出到stdout
+fn write_output(path: Option<&Path>, out: String) {
+    let Some(path) = path else {
+        println!("{out}");
+        return;
+    };
+    if let Err(e) = fs::write(path, out + "\n") {
+        err!("{}", msg!(WriteFileFailed, format!("{path:?}"), e));
+        exit(3)
+    }
 }
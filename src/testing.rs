@@ -0,0 +1,336 @@
+//! 运行源码中带有测试属性的块
+//!
+//! 测试属性的写法见[`find_test_blocks`],
+//! 每个测试会单独编译, 编译时源码中的其它测试块将被剔除,
+//! 然后在模拟的逻辑处理器中运行, 直到程序结束一次
+
+use std::path::Path;
+
+use emulator::{Emulator, Value};
+use parser::{blank_out, find_test_blocks, TestAssert, TestBlock};
+use utils::msg;
+
+use crate::{compile, CompileOptions, Diagnostic, Source};
+
+/// 每个测试最多运行的语句数, 超过时视为失败
+pub const TEST_MAX_STEPS: usize = 100_000;
+
+/// 断言数字时所允许的误差
+const TEST_EPSILON: f64 = 0.000001;
+
+/// 一个测试的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// 测试的名称, 没有给定时使用其所在的行
+    pub name: String,
+    /// 编译测试时产生的诊断信息
+    pub diagnostics: Vec<Diagnostic>,
+    /// 失败的原因, 为空时表示测试通过
+    pub failures: Vec<String>,
+}
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// 运行源码中的所有测试, `path`为源码所在的文件
+///
+/// 测试属性有误时返回对应的诊断信息
+///
+/// # Examples
+/// ```
+/// # use mindustry_logic_bang_lang::run_tests;
+/// let src = r#"
+/// #[test add]
+/// #[assert a = 3]
+/// { a = 1; op a a + 2; }
+/// "#;
+/// let results = run_tests(src, None).unwrap();
+/// assert_eq!(results[0].name, "add");
+/// assert!(results[0].passed());
+/// ```
+pub fn run_tests(
+    source: &str,
+    path: Option<&Path>,
+) -> Result<Vec<TestResult>, Box<Diagnostic>> {
+    let blocks = find_test_blocks(source).map_err(|e| {
+        Box::new(Diagnostic::error(e.to_string())
+            .with_kind(e.message_kind())
+            .with_span(e.span.0, e.span.1)
+            .with_source(Source::new(
+                path.map(|path| path.display().to_string()),
+                source,
+            )))
+    })?;
+    Ok(blocks.iter()
+        .map(|block| run_test(source, path, block, &blocks))
+        .collect())
+}
+
+fn run_test(
+    source: &str,
+    path: Option<&Path>,
+    block: &TestBlock,
+    blocks: &[TestBlock],
+) -> TestResult {
+    let name = block.name.clone().unwrap_or_else(|| {
+        let line = source[..block.attr.0].matches('\n').count() + 1;
+        format!("line {line}")
+    });
+    let mut result = TestResult {
+        name,
+        diagnostics: Vec::new(),
+        failures: Vec::new(),
+    };
+
+    // 剔除其它的测试块, 并去掉这个测试的属性使其作为普通的块编译
+    let mut src = source.to_owned();
+    for other in blocks.iter().filter(|other| *other != block) {
+        blank_out(&mut src, other.span());
+    }
+    for &attr in &block.attrs {
+        blank_out(&mut src, attr);
+    }
+    // 跳转到结尾的标记会被编译为跳转到第一条语句, 这与回到开头的循环无法区分,
+    // 所以在结尾加上end使其跳转到end
+    src.push_str("\nend;\n");
+
+    let options = CompileOptions {
+        path: path.map(Path::to_path_buf),
+        ..Default::default()
+    };
+    let code = match compile(&src, &options) {
+        Ok(output) => {
+            result.diagnostics = output.diagnostics;
+            output.code
+        },
+        Err(diagnostics) => {
+            result.diagnostics = diagnostics;
+            result.failures.push(msg!(TestCompileFailed));
+            return result;
+        },
+    };
+
+    let lines = code.lines().collect::<Vec<_>>();
+    let mut emulator = match Emulator::new(&lines) {
+        Ok(emulator) => emulator,
+        Err((line, e)) => {
+            result.failures.push(msg!(TestLogicInvalid, line + 1, e));
+            return result;
+        },
+    };
+    if emulator.run_until_end(TEST_MAX_STEPS).is_none() {
+        result.failures.push(msg!(TestStepsExceeded, TEST_MAX_STEPS));
+        return result;
+    }
+
+    result.failures.extend(block.asserts.iter()
+        .filter_map(|assert| check_assert(&emulator, assert)));
+    result
+}
+
+/// 检查一个断言, 失败时返回原因
+fn check_assert(emulator: &Emulator, assert: &TestAssert) -> Option<String> {
+    let TestAssert { name, expected, .. } = assert;
+    if name == "print" {
+        let Some(text) = expected.strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+        else {
+            return Some(msg!(TestPrintNotString, expected));
+        };
+        let output = emulator.output();
+        return (output != text).then(|| {
+            msg!(TestPrintAssertFailed, expected, format!("{output:?}"))
+        });
+    }
+    let actual = emulator.var(name);
+    let equal = match (&actual, Value::from_literal(expected)) {
+        (Value::Num(a), Value::Num(b)) => (a - b).abs() < TEST_EPSILON,
+        (a, b) => *a == b,
+    };
+    (!equal).then(|| msg!(TestAssertFailed, name, expected, actual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_tests_test() {
+        let src = r#"
+        import "std::fmt";
+        const Add = (op $ _0 + _1;);
+
+        #[test]
+        #[assert a = 3]
+        #[assert print = "3"]
+        { a = Add[1 2]; print a; }
+
+        #[test hex]
+        #[assert print = "af"]
+        {
+            take PrintLowerHexChar[10];
+            take PrintLowerHexChar[15];
+        }
+
+        #[test failed]
+        #[assert b = "x"]
+        #[assert print = "1"]
+        # 其它测试中的a不会被定义
+        { b = "y"; print a; }
+
+        #[test loop]
+        { a = 1; :x goto :x; }
+
+        #[test error]
+        { setres a; $ = 1; }
+
+        #[test head_loop]
+        #[assert i = 3]
+        #[assert print = "3"]
+        { :x op i i + 1; goto :x i < 3; print i; }
+
+        #[test head_loop_to_end]
+        #[assert i = 3]
+        { :x op i i + 1; goto :x i < 3; }
+        "#;
+        let results = run_tests(src, None).unwrap();
+        let names = results.iter()
+            .map(|result| &*result.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, [
+            "line 5", "hex", "failed", "loop", "error",
+            "head_loop", "head_loop_to_end",
+        ]);
+        let passed = results.iter()
+            .map(TestResult::passed)
+            .collect::<Vec<_>>();
+        assert_eq!(passed, [true, true, false, false, false, true, true]);
+        assert_eq!(results[2].failures.len(), 2);
+        assert_eq!(results[4].failures.len(), 1);
+        assert!(!results[4].diagnostics.is_empty());
+
+        let err = run_tests("#[test]\nprint 1;", None).unwrap_err();
+        assert_eq!(err.span, Some((0, 7)));
+        let err = run_tests("#[assert a = 1]\n{}", None).unwrap_err();
+        assert_eq!(err.span, Some((0, 15)));
+        assert!(run_tests("#[test a b]\n{}", None).is_err());
+        assert_eq!(run_tests("#[other]\n{}", None).unwrap(), []);
+    }
+
+    #[test]
+    fn fn_call_test() {
+        let src = r#"
+        fn Sum[N] {
+            $ = 0;
+            i = 0;
+            while i < N { op $ $ + i; op i i + 1; }
+        }
+
+        fn Sub[A B] { op $ A - B; }
+
+        #[test call]
+        #[assert a = 10]
+        #[assert b = 3]
+        #[assert c = 6]
+        {
+            a = call Sum[5];
+            b = call Sum[call Sum[3]];
+            take C = Sum[4];
+            c = C;
+            end;
+        }
+
+        #[test nested_call]
+        #[assert r = 93]
+        #[assert s = 4]
+        {
+            a = 10;
+            r = call Sub[100 call Sub[a 3]];
+            s = call Sub[call Sub[a 1] call Sub[a 5]];
+        }
+        "#;
+        let results = run_tests(src, None).unwrap();
+        assert!(results[0].passed(), "{:?}", results[0].failures);
+        assert!(results[1].passed(), "{:?}", results[1].failures);
+    }
+
+    #[test]
+    fn array_test() {
+        let src = r#"
+        array! Buf[4] = cell1[2];
+
+        #[test array]
+        #[assert a = 16]
+        #[assert b = null]
+        #[assert c = 31]
+        {
+            i = 0;
+            while i < 4 {
+                Buf[i] = i * 10 + 1;
+                op i i + 1;
+            }
+            op Buf[1] Buf[1] + 5;
+            a = Buf[1];
+            b = Buf[i];
+            read c cell1 5;
+            end;
+        }
+        "#;
+        let results = run_tests(src, None).unwrap();
+        assert!(results[0].passed(), "{:?}", results[0].failures);
+    }
+
+    #[test]
+    fn record_test() {
+        let src = r#"
+        record Vec2[x y];
+        record Pos: Vec2 = cell1[2];
+        record Old: Vec2 = cell1[4];
+
+        #[test record]
+        #[assert a = 4]
+        #[assert b = 3]
+        #[assert c = 5]
+        #[assert size = 2]
+        {
+            Pos.x = 1;
+            Pos.y = 5;
+            Old = Pos;
+            op Pos.x Pos.x + 3;
+            a = Pos.x;
+            b = Pos.x - Old.x;
+            c = Old.y;
+            size = Vec2;
+            end;
+        }
+        "#;
+        let results = run_tests(src, None).unwrap();
+        assert!(results[0].passed(), "{:?}", results[0].failures);
+    }
+
+    #[test]
+    fn for_test() {
+        let src = r#"
+        #[test for]
+        #[assert s = 12]
+        #[assert t = 10]
+        {
+            s = 0;
+            for i = 0; i < 10; op i i + 1; {
+                if i == 3 { continue; }
+                if i == 6 { break; }
+                op s s + i;
+            }
+            t = 0;
+            for i in 0..5 {
+                op t t + i;
+            }
+            end;
+        }
+        "#;
+        let results = run_tests(src, None).unwrap();
+        assert!(results[0].passed(), "{:?}", results[0].failures);
+    }
+}
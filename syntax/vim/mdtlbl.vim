@@ -26,7 +26,8 @@ syn case match
 " 一些关键字 {{{1
 syn keyword mdtlblKeyword
             \ while gwhile do skip goto if elif else switch case break continue
-            \ const take setres select match
+            \ for in
+            \ const take setres select match fn call array record
             \ inline
             \ op set noop print
 
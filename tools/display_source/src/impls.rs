@@ -36,6 +36,8 @@ impl DisplaySource for Value {
                 meta.push(builtin_func.name());
                 meta.push("*#)");
             },
+            Self::Array(_) => meta.push("(#*Array*#)"),
+            Self::Record(_) => meta.push("(#*Record*#)"),
         }
     }
 }
@@ -244,8 +246,101 @@ impl DisplaySource for Select {
         meta.push("}");
     }
 }
+/// 显示一个带花括号的块
+fn display_block(expand: &Expand, meta: &mut DisplaySourceMeta) {
+    meta.push("{");
+    if !expand.is_empty() {
+        meta.add_lf();
+        meta.do_block(|meta| {
+            expand.display_source(meta);
+        });
+    }
+    meta.push("}");
+}
+impl DisplaySource for ControlFlow {
+    fn display_source(&self, meta: &mut DisplaySourceMeta) {
+        match self {
+            Self::If(cmp, body, else_body) => {
+                meta.push("if");
+                meta.add_space();
+                cmp.display_source(meta);
+                meta.add_space();
+                display_block(body, meta);
+                if let Some(else_body) = else_body {
+                    meta.add_space();
+                    meta.push("else");
+                    meta.add_space();
+                    display_block(else_body, meta);
+                }
+            },
+            Self::While(cmp, body) | Self::Gwhile(cmp, body) => {
+                meta.push(match self {
+                    Self::While(..) => "while",
+                    _ => "gwhile",
+                });
+                meta.add_space();
+                cmp.display_source(meta);
+                meta.add_space();
+                display_block(body, meta);
+            },
+            Self::DoWhile(body, cmp) => {
+                meta.push("do");
+                meta.add_space();
+                display_block(body, meta);
+                meta.add_space();
+                meta.push("while");
+                meta.add_space();
+                cmp.display_source(meta);
+                meta.push(";");
+            },
+        }
+    }
+}
 impl DisplaySource for Const {
     fn display_source(&self, meta: &mut DisplaySourceMeta) {
+        if let (ConstKey::Var(name), Value::Array(array)) = (&self.0, &self.1) {
+            meta.push("array");
+            if array.guard {
+                meta.push("!");
+            }
+            meta.add_space();
+            name.display_source(meta);
+            meta.push("[");
+            array.len.display_source(meta);
+            meta.push("]");
+            meta.add_space();
+            meta.push("=");
+            meta.add_space();
+            array.cell.display_source(meta);
+            meta.push("[");
+            array.offset.display_source(meta);
+            meta.push("];");
+            return;
+        }
+        if let (ConstKey::Var(name), Value::Record(record)) = (&self.0, &self.1) {
+            meta.push("record");
+            meta.add_space();
+            name.display_source(meta);
+            meta.push("[");
+            for (i, field) in record.fields.iter().enumerate() {
+                if i != 0 {
+                    meta.add_space();
+                }
+                field.display_source(meta);
+            }
+            meta.push("]");
+            if let Some((cell, base)) = &record.place {
+                meta.add_space();
+                meta.push("=");
+                meta.add_space();
+                cell.display_source(meta);
+                meta.push("[");
+                base.display_source(meta);
+                meta.push("]");
+            }
+            meta.push(";");
+            return;
+        }
         meta.push("const");
         meta.add_space();
 
@@ -294,24 +389,31 @@ impl DisplaySource for ConstKey {
 impl DisplaySource for LogicLine {
     fn display_source(&self, meta: &mut DisplaySourceMeta) {
         match self {
-            Self::Expand(expand) => {
+            Self::Expand(expand) => display_block(expand, meta),
+            Self::InlineBlock(block) => {
+                meta.push("inline");
+                meta.add_space();
                 meta.push("{");
-                if !expand.is_empty() {
+                if !block.is_empty() {
                     meta.add_lf();
                     meta.do_block(|meta| {
-                        expand.display_source(meta);
+                        block.display_source(meta);
                     });
                 }
                 meta.push("}");
             },
-            Self::InlineBlock(block) => {
+            Self::Import(import) => {
+                meta.push("# import");
+                meta.add_space();
+                meta.push(&import.key);
+                meta.add_lf();
                 meta.push("inline");
                 meta.add_space();
                 meta.push("{");
-                if !block.is_empty() {
+                if !import.lines.is_empty() {
                     meta.add_lf();
                     meta.do_block(|meta| {
-                        block.display_source(meta);
+                        import.lines.display_source(meta);
                     });
                 }
                 meta.push("}");
@@ -334,6 +436,8 @@ impl DisplaySource for LogicLine {
             Self::Goto(goto) => goto.display_source(meta),
             Self::Op(op) => op.display_source(meta),
             Self::Select(select) => select.display_source(meta),
+            Self::ControlFlow(control) => control.display_source(meta),
+            Self::Spanned(spanned) => spanned.line.display_source(meta),
             Self::Take(take) => take.display_source(meta),
             Self::Const(r#const) => r#const.display_source(meta),
             Self::ConstLeak(var) => {
@@ -350,6 +454,10 @@ impl DisplaySource for LogicLine {
             },
             Self::ArgsRepeat(args_repeat) => args_repeat.display_source(meta),
             Self::Match(r#match) => r#match.display_source(meta),
+            Self::ConstFor(const_for) => const_for.display_source(meta),
+            Self::ConstIf(const_if) => const_if.display_source(meta),
+            Self::CallArgs(call_args) => call_args.display_source(meta),
+            Self::Instruction(inst) => inst.display_source(meta),
             Self::Other(args) => {
                 if let Some(args) = args.as_normal() {
                     assert_ne!(args.len(), 0);
@@ -360,6 +468,16 @@ impl DisplaySource for LogicLine {
         }
     }
 }
+impl DisplaySource for Instruction {
+    fn display_source(&self, meta: &mut DisplaySourceMeta) {
+        self.name().display_source(meta);
+        self.args().iter().for_each(|arg| {
+            meta.add_space();
+            arg.display_source(meta);
+        });
+        meta.push(";");
+    }
+}
 impl DisplaySource for Args {
     fn display_source(&self, meta: &mut DisplaySourceMeta) {
         match self {
@@ -399,6 +517,83 @@ impl DisplaySource for ArgsRepeat {
         meta.push("}");
     }
 }
+impl DisplaySource for ConstFor {
+    fn display_source(&self, meta: &mut DisplaySourceMeta) {
+        meta.push("const");
+        meta.add_space();
+        meta.push("for");
+        meta.add_space();
+        self.var().display_source(meta);
+        meta.add_space();
+        meta.push("in");
+        meta.add_space();
+        match self.iter() {
+            ConstForIter::Range(start, end) => {
+                start.display_source(meta);
+                meta.push("..");
+                end.display_source(meta);
+            },
+            ConstForIter::List(values) => {
+                meta.push("[");
+                meta.display_source_iter_by_splitter(
+                    DisplaySourceMeta::add_space,
+                    values,
+                );
+                meta.push("]");
+            },
+        }
+        meta.add_space();
+        display_block(self.block(), meta);
+    }
+}
+impl DisplaySource for CallArgs {
+    fn display_source(&self, meta: &mut DisplaySourceMeta) {
+        meta.display_source_iter_by_splitter(
+            DisplaySourceMeta::add_space,
+            self.params(),
+        );
+        meta.add_space();
+        meta.push("=");
+        meta.add_space();
+        meta.display_source_iter_by_splitter(
+            DisplaySourceMeta::add_space,
+            self.args(),
+        );
+        meta.push(";");
+    }
+}
+impl DisplaySource for ConstIf {
+    fn display_source(&self, meta: &mut DisplaySourceMeta) {
+        fn display_body(body: &InlineBlock, meta: &mut DisplaySourceMeta) {
+            meta.push("{");
+            if !body.is_empty() {
+                meta.add_lf();
+                meta.do_block(|meta| body.display_source(meta));
+            }
+            meta.push("}");
+        }
+        meta.push("const");
+        meta.add_space();
+        for (i, (cmp, body)) in self.cases().iter().enumerate() {
+            if i != 0 {
+                meta.add_space();
+                meta.push("elif");
+            } else {
+                meta.push("if");
+            }
+            meta.add_space();
+            cmp.display_source(meta);
+            meta.add_space();
+            display_body(body, meta);
+        }
+        if let Some(else_body) = self.else_body() {
+            meta.add_space();
+            meta.push("else");
+            meta.add_space();
+            display_body(else_body, meta);
+        }
+    }
+}
 impl DisplaySource for MatchPatAtom {
     fn display_source(&self, meta: &mut DisplaySourceMeta) {
         let show_name = !self.name().is_empty();
@@ -0,0 +1,901 @@
+//! 在本地执行编译所得逻辑代码的模拟器
+//!
+//! 模拟单个逻辑处理器, 支持的语句有:
+//! `set`, `op`, `jump`, `print`, `printflush`, `read`, `write`,
+//! `wait`, `end`, `stop`与`noop`,
+//! 其它的语句将被跳过, 并记录在[`Emulator::unsupported`]中
+//!
+//! 名称形如`cell1`或`bank1`的未赋值变量被视为已连接的内存元,
+//! 形如`message1`的则被视为信息板
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display},
+    rc::Rc,
+    str::FromStr,
+};
+
+use tag_code::mdt_logic_split;
+use utils::msg;
+
+/// 未赋值时被视为已连接建筑的名称前缀
+const LINK_PREFIXES: &[&str] = &[
+    "cell",
+    "bank",
+    "message",
+    "display",
+    "switch",
+    "processor",
+];
+
+/// 比较时视为相等的最大误差
+const EPSILON: f64 = 0.000001;
+
+/// 逻辑变量的值
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Null,
+    Num(f64),
+    Str(Rc<str>),
+    /// 建筑或者内容等对象, 使用名称表示
+    Obj(Rc<str>),
+}
+impl Value {
+    /// 作为数字使用时的值, 非空对象为1, 空值为0
+    pub fn num(&self) -> f64 {
+        match self {
+            Self::Null => 0.0,
+            Self::Num(n) => *n,
+            Self::Str(_) | Self::Obj(_) => 1.0,
+        }
+    }
+
+    /// 从逻辑代码中的字面量构建, 其它的名称被视为对象
+    pub fn from_literal(s: &str) -> Self {
+        match Arg::from(s) {
+            Arg::Const(value) => value,
+            Arg::Var(name) => Self::Obj(name),
+            Arg::Counter => Self::Obj(s.into()),
+        }
+    }
+
+    fn is_obj(&self) -> bool {
+        !matches!(self, Self::Num(_))
+    }
+
+    /// 运算结果为非有限数时, 与游戏中相同的存储为0
+    fn from_num(n: f64) -> Self {
+        Self::Num(if n.is_finite() { n } else { 0.0 })
+    }
+
+    fn from_bool(b: bool) -> Self {
+        Self::Num(if b { 1.0 } else { 0.0 })
+    }
+
+    /// 以`print`的方式格式化
+    pub fn print_str(&self) -> String {
+        match self {
+            Self::Null => "null".into(),
+            Self::Num(n) => fmt_num(*n),
+            Self::Str(s) | Self::Obj(s) => s.to_string(),
+        }
+    }
+
+    fn equal(&self, other: &Self) -> bool {
+        if self.is_obj() && other.is_obj() {
+            self == other
+        } else {
+            (self.num() - other.num()).abs() < EPSILON
+        }
+    }
+
+    fn strict_equal(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Num(a), Self::Num(b)) => a == b,
+            (a, b) => a == b,
+        }
+    }
+}
+impl Display for Value {
+    /// 显示时字符串带有引号, 以便与对象区分
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Str(s) => write!(f, "{s:?}"),
+            other => f.write_str(&other.print_str()),
+        }
+    }
+}
+
+/// 与游戏中相同, 接近整数时显示为整数
+fn fmt_num(n: f64) -> String {
+    if (n - n.round()).abs() < 0.00001 && n.abs() < i64::MAX as f64 {
+        (n.round() as i64).to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+/// 解析逻辑代码中的数字字面量, 包括`0x`与`0b`形式
+fn parse_num(s: &str) -> Option<f64> {
+    let radix = |s: &str, radix| {
+        let (neg, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        // 与游戏中相同, 超出范围的64位数将按补码回绕
+        let n = u64::from_str_radix(digits, radix).ok()? as i64 as f64;
+        Some(if neg { -n } else { n })
+    };
+    if let Some(hex) = s.strip_prefix("0x") {
+        radix(hex, 16)
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        radix(bin, 2)
+    } else if s.starts_with(|ch: char| ch.is_ascii_digit() || ch == '-' || ch == '.') {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Var(Rc<str>),
+    Const(Value),
+    Counter,
+}
+impl From<&str> for Arg {
+    fn from(s: &str) -> Self {
+        if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+            return Self::Const(Value::Str(s[1..s.len()-1].into()));
+        }
+        if let Some(n) = parse_num(s) {
+            return Self::Const(Value::Num(n));
+        }
+        match s {
+            "@counter" => Self::Counter,
+            "null" => Self::Const(Value::Null),
+            "true" => Self::Const(Value::Num(1.0)),
+            "false" => Self::Const(Value::Num(0.0)),
+            "@pi" => Self::Const(Value::Num(std::f64::consts::PI)),
+            "@e" => Self::Const(Value::Num(std::f64::consts::E)),
+            "@degToRad" => Self::Const(Value::Num(1f64.to_radians())),
+            "@radToDeg" => Self::Const(Value::Num(1f64.to_degrees())),
+            _ => Self::Var(s.into()),
+        }
+    }
+}
+
+/// `op`的运算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Oper {
+    Add, Sub, Mul, Div, Idiv, Mod, Pow,
+    Equal, NotEqual, Land,
+    LessThan, LessThanEq, GreaterThan, GreaterThanEq, StrictEqual,
+    Shl, Shr, Or, And, Xor,
+    Max, Min, Angle, Len, Noise,
+    Not, Abs, Log, Log10, Floor, Ceil, Sqrt, Rand,
+    Sin, Cos, Tan, Asin, Acos, Atan,
+}
+impl FromStr for Oper {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Oper::*;
+        Ok(match s {
+            "add" => Add,
+            "sub" => Sub,
+            "mul" => Mul,
+            "div" => Div,
+            "idiv" => Idiv,
+            "mod" => Mod,
+            "pow" => Pow,
+            "equal" => Equal,
+            "notEqual" => NotEqual,
+            "land" => Land,
+            "lessThan" => LessThan,
+            "lessThanEq" => LessThanEq,
+            "greaterThan" => GreaterThan,
+            "greaterThanEq" => GreaterThanEq,
+            "strictEqual" => StrictEqual,
+            "shl" => Shl,
+            "shr" => Shr,
+            "or" => Or,
+            "and" => And,
+            "xor" => Xor,
+            "max" => Max,
+            "min" => Min,
+            "angle" => Angle,
+            "len" => Len,
+            "noise" => Noise,
+            "not" => Not,
+            "abs" => Abs,
+            "log" => Log,
+            "log10" => Log10,
+            "floor" => Floor,
+            "ceil" => Ceil,
+            "sqrt" => Sqrt,
+            "rand" => Rand,
+            "sin" => Sin,
+            "cos" => Cos,
+            "tan" => Tan,
+            "asin" => Asin,
+            "acos" => Acos,
+            "atan" => Atan,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// `jump`的比较
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanEq,
+    GreaterThan,
+    GreaterThanEq,
+    StrictEqual,
+    Always,
+}
+impl FromStr for Cmp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "equal" => Self::Equal,
+            "notEqual" => Self::NotEqual,
+            "lessThan" => Self::LessThan,
+            "lessThanEq" => Self::LessThanEq,
+            "greaterThan" => Self::GreaterThan,
+            "greaterThanEq" => Self::GreaterThanEq,
+            "strictEqual" => Self::StrictEqual,
+            "always" => Self::Always,
+            _ => return Err(()),
+        })
+    }
+}
+impl Cmp {
+    pub fn test(&self, a: &Value, b: &Value) -> bool {
+        match self {
+            Self::Equal => a.equal(b),
+            Self::NotEqual => !a.equal(b),
+            Self::LessThan => a.num() < b.num(),
+            Self::LessThanEq => a.num() <= b.num(),
+            Self::GreaterThan => a.num() > b.num(),
+            Self::GreaterThanEq => a.num() >= b.num(),
+            Self::StrictEqual => a.strict_equal(b),
+            Self::Always => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    Set(Arg, Arg),
+    Op(Oper, Arg, Arg, Arg),
+    Jump(usize, Cmp, Arg, Arg),
+    Print(Arg),
+    PrintFlush(Arg),
+    Read(Arg, Arg, Arg),
+    Write(Arg, Arg, Arg),
+    Wait(Arg),
+    End,
+    Stop,
+    Noop,
+    Unsupported(String),
+}
+
+/// 解析逻辑代码时的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// 未闭合的字符串, 包含其开始的字符位置
+    UnclosedString(usize),
+    UnknownOper(String),
+    UnknownCmp(String),
+    InvalidTarget(String),
+}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Self::UnclosedString(idx) => msg!(EmulatorUnclosedString, idx),
+            Self::UnknownOper(oper) => msg!(EmulatorUnknownOper, oper),
+            Self::UnknownCmp(cmp) => msg!(EmulatorUnknownCmp, cmp),
+            Self::InvalidTarget(target) => msg!(InvalidJumpTarget, target),
+        };
+        f.write_str(&msg)
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let args = mdt_logic_split(line)
+            .map_err(ParseError::UnclosedString)?;
+        // 与游戏中相同, 缺少的参数视为`0`
+        let arg = |i: usize| Arg::from(args.get(i).copied().unwrap_or("0"));
+        let Some(&name) = args.first() else { return Ok(Self::Noop) };
+        Ok(match name {
+            "set" => Self::Set(arg(1), arg(2)),
+            "op" => {
+                let oper = args.get(1).copied().unwrap_or_default();
+                let oper = oper.parse()
+                    .map_err(|()| ParseError::UnknownOper(oper.into()))?;
+                Self::Op(oper, arg(2), arg(3), arg(4))
+            },
+            "jump" => {
+                let target = args.get(1).copied().unwrap_or_default();
+                let target = target.parse()
+                    .map_err(|_| ParseError::InvalidTarget(target.into()))?;
+                let cmp = args.get(2).copied().unwrap_or_default();
+                let cmp = cmp.parse()
+                    .map_err(|()| ParseError::UnknownCmp(cmp.into()))?;
+                Self::Jump(target, cmp, arg(3), arg(4))
+            },
+            "print" => Self::Print(arg(1)),
+            "printflush" => Self::PrintFlush(arg(1)),
+            "read" => Self::Read(arg(1), arg(2), arg(3)),
+            "write" => Self::Write(arg(1), arg(2), arg(3)),
+            "wait" => Self::Wait(arg(1)),
+            "end" => Self::End,
+            "stop" => Self::Stop,
+            "noop" => Self::Noop,
+            other => Self::Unsupported(other.into()),
+        })
+    }
+}
+
+/// 执行一条语句后的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Continue,
+    /// 执行了`end`, 或者执行完了最后一条语句, 下一步将从头开始
+    ///
+    /// 跳转到第一条语句并不视为结束, 因为它可能是一个以第一条语句开头的循环
+    End,
+    /// 执行了`stop`, 之后不再执行任何语句
+    Stop,
+}
+
+/// 一个逻辑处理器的模拟
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    code: Vec<Instruction>,
+    vars: HashMap<Rc<str>, Value>,
+    counter: usize,
+    steps: usize,
+    /// 经过的时间, 单位为秒
+    time: f64,
+    rng: u64,
+    stopped: bool,
+    print_buffer: String,
+    /// 运行中打印的所有文本
+    output: String,
+    messages: BTreeMap<String, String>,
+    cells: BTreeMap<String, Vec<f64>>,
+    unsupported: BTreeSet<String>,
+}
+impl Emulator {
+    /// 从逻辑代码的每一行构建, 例如[`TagCodes::compile`]的结果
+    ///
+    /// 出错时返回出错的行下标
+    ///
+    /// [`TagCodes::compile`]: tag_code::TagCodes::compile
+    pub fn new<S: AsRef<str>>(lines: &[S]) -> Result<Self, (usize, ParseError)> {
+        let code = lines.iter()
+            .enumerate()
+            .map(|(i, line)| line.as_ref().parse().map_err(|e| (i, e)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            code,
+            vars: HashMap::new(),
+            counter: 0,
+            steps: 0,
+            time: 0.0,
+            rng: 0x2545F4914F6CDD1D,
+            stopped: false,
+            print_buffer: String::new(),
+            output: String::new(),
+            messages: BTreeMap::new(),
+            cells: BTreeMap::new(),
+            unsupported: BTreeSet::new(),
+        })
+    }
+
+    /// 获取变量的值, 包括未赋值的已连接建筑
+    pub fn var(&self, name: &str) -> Value {
+        match name {
+            "@counter" => return Value::Num(self.counter as f64),
+            "@time" => return Value::Num(self.time * 1000.0),
+            "@tick" => return Value::Num(self.time * 60.0),
+            "@second" => return Value::Num(self.time),
+            _ => (),
+        }
+        if let Some(value) = self.vars.get(name) {
+            return value.clone();
+        }
+        let is_link = LINK_PREFIXES.iter().any(|prefix| {
+            name.strip_prefix(prefix).is_some_and(|n| {
+                !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())
+            })
+        });
+        if is_link || name.starts_with('@') {
+            Value::Obj(name.into())
+        } else {
+            Value::Null
+        }
+    }
+
+    /// 所有被赋值过的变量, 按名称排序
+    pub fn vars(&self) -> Vec<(&str, &Value)> {
+        let mut vars = self.vars.iter()
+            .map(|(name, value)| (&**name, value))
+            .collect::<Vec<_>>();
+        vars.sort_unstable_by_key(|&(name, _)| name);
+        vars
+    }
+
+    pub fn counter(&self) -> usize {
+        self.counter
+    }
+
+    /// 已经执行的语句数
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// 还未被`printflush`的文本
+    pub fn print_buffer(&self) -> &str {
+        &self.print_buffer
+    }
+
+    /// 运行中打印的所有文本, 不论是否被`printflush`
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// 每个信息板最后被`printflush`的文本
+    pub fn messages(&self) -> &BTreeMap<String, String> {
+        &self.messages
+    }
+
+    /// 被读写过的内存元
+    pub fn cells(&self) -> &BTreeMap<String, Vec<f64>> {
+        &self.cells
+    }
+
+    /// 遇到的不支持的语句名称
+    pub fn unsupported(&self) -> &BTreeSet<String> {
+        &self.unsupported
+    }
+
+    fn get(&self, arg: &Arg) -> Value {
+        match arg {
+            Arg::Var(name) => self.var(name),
+            Arg::Const(value) => value.clone(),
+            Arg::Counter => Value::Num(self.counter as f64),
+        }
+    }
+
+    fn set(&mut self, arg: &Arg, value: Value) {
+        match arg {
+            Arg::Var(name) => { self.vars.insert(name.clone(), value); },
+            Arg::Const(_) => (),
+            Arg::Counter => {
+                let n = value.num();
+                self.counter = if n > 0.0 { n as usize } else { 0 };
+            },
+        }
+    }
+
+    /// 获取对象所对应的内存元, 不是内存元时返回空
+    fn cell(&mut self, cell: &Value) -> Option<&mut Vec<f64>> {
+        let Value::Obj(name) = cell else { return None };
+        let size = if name.starts_with("cell") {
+            64
+        } else if name.starts_with("bank") {
+            512
+        } else {
+            return None;
+        };
+        Some(self.cells.entry(name.to_string())
+            .or_insert_with(|| vec![0.0; size]))
+    }
+
+    /// 范围为`[0, 1)`的伪随机数, 使用固定的种子以便于复现
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn op(&mut self, oper: Oper, a: &Value, b: &Value) -> Value {
+        use Oper::*;
+        let (x, y) = (a.num(), b.num());
+        let (xl, yl) = (x as i64, y as i64);
+        Value::from_num(match oper {
+            Add => x + y,
+            Sub => x - y,
+            Mul => x * y,
+            Div => x / y,
+            Idiv => (x / y).floor(),
+            Mod => x % y,
+            Pow => x.powf(y),
+            Equal => return Value::from_bool(a.equal(b)),
+            NotEqual => return Value::from_bool(!a.equal(b)),
+            Land => return Value::from_bool(x != 0.0 && y != 0.0),
+            LessThan => return Value::from_bool(x < y),
+            LessThanEq => return Value::from_bool(x <= y),
+            GreaterThan => return Value::from_bool(x > y),
+            GreaterThanEq => return Value::from_bool(x >= y),
+            StrictEqual => return Value::from_bool(a.strict_equal(b)),
+            Shl => xl.wrapping_shl(yl as u32) as f64,
+            Shr => xl.wrapping_shr(yl as u32) as f64,
+            Or => (xl | yl) as f64,
+            And => (xl & yl) as f64,
+            Xor => (xl ^ yl) as f64,
+            Max => x.max(y),
+            Min => x.min(y),
+            Angle => y.atan2(x).to_degrees().rem_euclid(360.0),
+            Len => x.hypot(y),
+            Noise => noise(x, y),
+            Not => !xl as f64,
+            Abs => x.abs(),
+            Log => x.ln(),
+            Log10 => x.log10(),
+            Floor => x.floor(),
+            Ceil => x.ceil(),
+            Sqrt => x.sqrt(),
+            Rand => self.random() * x,
+            Sin => x.to_radians().sin(),
+            Cos => x.to_radians().cos(),
+            Tan => x.to_radians().tan(),
+            Asin => x.asin().to_degrees(),
+            Acos => x.acos().to_degrees(),
+            Atan => x.atan().to_degrees(),
+        })
+    }
+
+    /// 执行一条语句
+    pub fn step(&mut self) -> Step {
+        if self.stopped {
+            return Step::Stop;
+        }
+        if self.counter >= self.code.len() {
+            self.counter = 0;
+        }
+        let Some(instruction) = self.code.get(self.counter).cloned() else {
+            // 空的程序
+            return Step::End;
+        };
+        self.counter += 1;
+        self.steps += 1;
+
+        match &instruction {
+            Instruction::Set(result, value) => {
+                let value = self.get(value);
+                self.set(result, value)
+            },
+            Instruction::Op(oper, result, a, b) => {
+                let (a, b) = (self.get(a), self.get(b));
+                let value = self.op(*oper, &a, &b);
+                self.set(result, value)
+            },
+            Instruction::Jump(target, cmp, a, b) => {
+                if cmp.test(&self.get(a), &self.get(b)) {
+                    self.counter = *target
+                }
+            },
+            Instruction::Print(value) => {
+                let text = self.get(value).print_str();
+                self.print_buffer.push_str(&text);
+                self.output.push_str(&text)
+            },
+            Instruction::PrintFlush(target) => {
+                let text = std::mem::take(&mut self.print_buffer);
+                if let Value::Obj(name) = self.get(target) {
+                    self.messages.insert(name.to_string(), text);
+                }
+            },
+            Instruction::Read(result, cell, addr) => {
+                let (cell, addr) = (self.get(cell), self.get(addr).num());
+                if let Some(memory) = self.cell(&cell) {
+                    let value = memory.get(addr as usize)
+                        .filter(|_| addr >= 0.0)
+                        .copied()
+                        .unwrap_or_default();
+                    self.set(result, Value::Num(value))
+                }
+            },
+            Instruction::Write(value, cell, addr) => {
+                let (value, cell) = (self.get(value).num(), self.get(cell));
+                let addr = self.get(addr).num();
+                if let Some(slot) = self.cell(&cell)
+                    .and_then(|memory| memory.get_mut(addr as usize))
+                    .filter(|_| addr >= 0.0)
+                {
+                    *slot = value
+                }
+            },
+            Instruction::Wait(secs) => {
+                self.time += self.get(secs).num().max(0.0)
+            },
+            Instruction::End => {
+                self.counter = 0;
+                return Step::End;
+            },
+            Instruction::Stop => {
+                self.counter -= 1;
+                self.stopped = true;
+                return Step::Stop;
+            },
+            Instruction::Noop => (),
+            Instruction::Unsupported(name) => {
+                self.unsupported.insert(name.clone());
+            },
+        }
+
+        // 执行完了最后一条语句, 或者跳转到了最后一条语句之后
+        if self.counter >= self.code.len() {
+            Step::End
+        } else {
+            Step::Continue
+        }
+    }
+
+    /// 执行至多`max_steps`条语句, 遇到`end`时从头继续执行
+    ///
+    /// 遇到`stop`时提前结束, 返回实际执行的语句数
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        let start = self.steps;
+        for _ in 0..max_steps {
+            if self.step() == Step::Stop { break }
+        }
+        self.steps - start
+    }
+
+    /// 执行直到程序结束一次, 即[`Step::End`]或者[`Step::Stop`]
+    ///
+    /// 如果在`max_steps`条语句内没有结束, 返回空
+    pub fn run_until_end(&mut self, max_steps: usize) -> Option<Step> {
+        (0..max_steps)
+            .map(|_| self.step())
+            .find(|step| *step != Step::Continue)
+    }
+
+    /// 以文本形式输出所有状态
+    pub fn dump(&self) -> String {
+        use std::fmt::Write;
+
+        let mut out = String::new();
+        let _ = writeln!(out, "steps: {}", self.steps);
+        let _ = writeln!(out, "counter: {}", self.counter);
+        if self.stopped {
+            out.push_str("stopped\n");
+        }
+        out.push_str("vars:\n");
+        for (name, value) in self.vars() {
+            let _ = writeln!(out, "    {name} = {value}");
+        }
+        let _ = writeln!(out, "print buffer: {:?}", self.print_buffer);
+        out.push_str("messages:\n");
+        for (name, text) in &self.messages {
+            let _ = writeln!(out, "    {name}: {text:?}");
+        }
+        if !self.cells.is_empty() {
+            out.push_str("cells:\n");
+        }
+        for (name, memory) in &self.cells {
+            let used = memory.iter()
+                .rposition(|&n| n != 0.0)
+                .map_or(0, |i| i + 1);
+            let values = memory[..used].iter()
+                .map(|&n| fmt_num(n))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(out, "    {name}: [{values}]");
+        }
+        if !self.unsupported.is_empty() {
+            let names = self.unsupported.iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = writeln!(out, "unsupported: {names}");
+        }
+        out.pop();
+        out
+    }
+}
+
+/// 二维的值噪声, 范围为`[-1, 1]`
+///
+/// 游戏中使用的是单纯形噪声, 这里仅保证结果的范围与连续性
+fn noise(x: f64, y: f64) -> f64 {
+    fn hash(x: i64, y: i64) -> f64 {
+        let mut h = (x as u64).wrapping_mul(0x9E3779B97F4A7C15)
+            ^ (y as u64).wrapping_mul(0xC2B2AE3D27D4EB4F);
+        h ^= h >> 29;
+        h = h.wrapping_mul(0xBF58476D1CE4E5B9);
+        h ^= h >> 32;
+        (h >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (sx, sy) = (fx * fx * (3.0 - 2.0 * fx), fy * fy * (3.0 - 2.0 * fy));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+    lerp(
+        lerp(hash(x0, y0), hash(x0 + 1, y0), sx),
+        lerp(hash(x0, y0 + 1), hash(x0 + 1, y0 + 1), sx),
+        sy,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src: &str, steps: usize) -> Emulator {
+        let lines = src.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        let mut emulator = Emulator::new(&lines).unwrap();
+        emulator.run(steps);
+        emulator
+    }
+
+    #[test]
+    fn op_test() {
+        let emulator = run(r#"
+            op add a 1 2
+            op idiv b 7 2
+            op mod c -7 3
+            op shl d 1 4
+            op not e 0
+            op angle f 0 1
+            op div g 1 0
+            op equal h null 0
+            op strictEqual i null 0
+            op equal j "a" "a"
+            op sin k 90 0
+            op land l 2 0
+            op len m 3 4
+            op xor n 0b110 0x3
+            op and q 7 0xFFFFFFFFFFFFFFFC
+            op pow o 2 10
+            op noise p 0.5 0.5
+        "#, 17);
+        let num = |name| emulator.var(name).num();
+        assert_eq!(num("a"), 3.0);
+        assert_eq!(num("b"), 3.0);
+        assert_eq!(num("c"), -1.0);
+        assert_eq!(num("d"), 16.0);
+        assert_eq!(num("e"), -1.0);
+        assert_eq!(num("f"), 90.0);
+        assert_eq!(num("g"), 0.0);
+        assert_eq!(num("h"), 1.0);
+        assert_eq!(num("i"), 0.0);
+        assert_eq!(num("j"), 1.0);
+        assert!((num("k") - 1.0).abs() < EPSILON);
+        assert_eq!(num("l"), 0.0);
+        assert_eq!(num("m"), 5.0);
+        assert_eq!(num("n"), 5.0);
+        assert_eq!(num("o"), 1024.0);
+        assert!((-1.0..=1.0).contains(&num("p")));
+        assert_eq!(num("q"), 4.0);
+    }
+
+    #[test]
+    fn control_test() {
+        let mut emulator = run(r#"
+            set i 0
+            jump 4 greaterThanEq i 3
+            op add i i 1
+            jump 1 always 0 0
+            print "i="
+            print i
+            printflush message1
+            op add @counter @counter 1
+            print "skipped"
+            print 1.5
+            end
+        "#, 0);
+        assert_eq!(emulator.run_until_end(100), Some(Step::End));
+        assert_eq!(emulator.var("i"), Value::Num(3.0));
+        assert_eq!(emulator.messages()["message1"], "i=3");
+        assert_eq!(emulator.print_buffer(), "1.5");
+        assert_eq!(emulator.counter(), 0);
+
+        // 未被刷新的文本将保留到下一次运行
+        assert_eq!(emulator.run_until_end(100), Some(Step::End));
+        assert_eq!(emulator.messages()["message1"], "1.5i=3");
+        assert_eq!(emulator.output(), "i=31.5i=31.5");
+        assert_eq!(emulator.run_until_end(3), None);
+
+        // 跳转到第一条语句的循环并不会结束
+        let mut emulator = Emulator::new(&["noop", "jump 0 always"]).unwrap();
+        assert_eq!(emulator.run_until_end(100), None);
+        assert_eq!(emulator.steps(), 100);
+
+        // 跳转到最后一条语句之后视为结束
+        let mut emulator = Emulator::new(&["jump 3 always", "noop"]).unwrap();
+        assert_eq!(emulator.run_until_end(100), Some(Step::End));
+        assert_eq!(emulator.steps(), 1);
+    }
+
+    #[test]
+    fn loop_at_first_line_test() {
+        let mut emulator = run(r#"
+            op add i i 1
+            jump 0 lessThan i 3
+            print i
+        "#, 0);
+        assert_eq!(emulator.run_until_end(100), Some(Step::End));
+        assert_eq!(emulator.var("i"), Value::Num(3.0));
+        assert_eq!(emulator.output(), "3");
+        assert_eq!(emulator.steps(), 7);
+        assert_eq!(emulator.counter(), 3);
+    }
+
+    #[test]
+    fn memory_test() {
+        let mut emulator = run(r#"
+            write 3 cell1 2
+            write 4 bank1 600
+            read a cell1 2
+            read b cell1 -1
+            read c message1 0
+            wait 0.5
+            set t @time
+            sensor x @unit @x
+            stop
+            set d 1
+        "#, 100);
+        assert_eq!(emulator.var("a"), Value::Num(3.0));
+        assert_eq!(emulator.var("b"), Value::Num(0.0));
+        assert_eq!(emulator.var("c"), Value::Null);
+        assert_eq!(emulator.var("d"), Value::Null);
+        assert_eq!(emulator.var("t"), Value::Num(500.0));
+        assert_eq!(emulator.cells()["cell1"][..3], [0.0, 0.0, 3.0]);
+        assert!(emulator.is_stopped());
+        assert_eq!(emulator.step(), Step::Stop);
+        assert_eq!(emulator.steps(), 9);
+        assert_eq!(emulator.dump(), r#"steps: 9
+counter: 8
+stopped
+vars:
+    a = 3
+    b = 0
+    t = 500
+print buffer: ""
+messages:
+cells:
+    bank1: []
+    cell1: [0, 0, 3]
+unsupported: sensor"#);
+    }
+
+    #[test]
+    fn parse_error_test() {
+        assert_eq!(
+            Emulator::new(&["set a 1", "op foo a 1 2"]).unwrap_err(),
+            (1, ParseError::UnknownOper("foo".into())),
+        );
+        assert_eq!(
+            Emulator::new(&["jump x always"]).unwrap_err(),
+            (0, ParseError::InvalidTarget("x".into())),
+        );
+        assert_eq!(
+            Emulator::new(&["print \"a"]).unwrap_err(),
+            (0, ParseError::UnclosedString(7)),
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! 对单个文档进行的词法层面的分析, 用于查找const与标记的定义
+//!
+//! 语法树中没有保留位置, 所以定义的位置由扫描令牌得到,
+//! 而const的值则交由[`ValueParser`]解析
+
+use display_source::DisplaySource;
+use parser::ValueParser;
+use syntax::Meta;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// 标识符或者`'...'`形式的变量
+    Name,
+    Number,
+    String,
+    Punct,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+impl Token {
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.start..self.end]
+    }
+}
+
+/// 一个const或者标记的定义
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Definition {
+    pub name: String,
+    /// 名称在源码中的范围
+    pub span: (usize, usize),
+    /// const的值在源码中的范围, 标记没有值
+    pub value: Option<(usize, usize)>,
+    /// 紧邻在定义前的`#** *#`文档注释
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    tokens: Vec<Token>,
+    consts: Vec<Definition>,
+    labels: Vec<Definition>,
+}
+impl Analysis {
+    pub fn new(src: &str) -> Self {
+        let (tokens, docs) = tokenize(src);
+        let doc_before = |start: usize| {
+            docs.iter()
+                .find(|(_, end, _)| {
+                    *end <= start && src[*end..start].trim().is_empty()
+                })
+                .map(|(_, _, doc)| doc.clone())
+        };
+        let mut consts = Vec::new();
+        let mut labels = Vec::new();
+
+        for (i, token) in tokens.iter().enumerate() {
+            match token.text(src) {
+                "const" => {
+                    let [name, eq] = [i + 1, i + 2].map(|i| tokens.get(i));
+                    let (Some(name), Some(eq)) = (name, eq) else { continue };
+                    if name.kind != TokenKind::Name || eq.text(src) != "=" {
+                        continue;
+                    }
+                    let value = value_span(src, &tokens[i + 3..])
+                        .unwrap_or((eq.end, eq.end));
+                    consts.push(Definition {
+                        name: name.text(src).into(),
+                        span: (name.start, name.end),
+                        value: Some(value),
+                        doc: doc_before(token.start),
+                    });
+                },
+                ":" if is_label_define(src, &tokens, i) => {
+                    let name = &tokens[i + 1];
+                    labels.push(Definition {
+                        name: name.text(src).into(),
+                        span: (name.start, name.end),
+                        value: None,
+                        doc: doc_before(token.start),
+                    });
+                },
+                _ => (),
+            }
+        }
+
+        Self { tokens, consts, labels }
+    }
+
+    /// 获取位于`offset`处的名称令牌的下标, 光标在名称末尾时也视作位于其中
+    fn name_at(&self, offset: usize) -> Option<usize> {
+        self.tokens.iter().position(|token| {
+            token.kind == TokenKind::Name
+                && token.start <= offset
+                && offset <= token.end
+        })
+    }
+
+    /// 查找`offset`处的名称所引用的定义
+    ///
+    /// 优先选择在其之前最近的定义, 因为const可以被遮蔽
+    pub fn definition(&self, src: &str, offset: usize) -> Option<&Definition> {
+        let i = self.name_at(offset)?;
+        let token = self.tokens[i];
+        let name = token.text(src);
+        let is_label = i.checked_sub(1)
+            .is_some_and(|prev| self.tokens[prev].text(src) == ":");
+        let defs = if is_label { &self.labels } else { &self.consts };
+        defs.iter()
+            .rev()
+            .find(|def| def.name == name && def.span.0 <= token.start)
+            .or_else(|| defs.iter().find(|def| def.name == name))
+    }
+}
+
+impl Definition {
+    /// 悬停时所显示的Markdown文本
+    pub fn hover(&self, src: &str) -> String {
+        let mut out = String::from("```mdtlbl\n");
+        match self.value {
+            Some((start, end)) => {
+                let text = &src[start..end];
+                let mut meta = Meta::new();
+                let value = match ValueParser::new().parse(&mut meta, text) {
+                    Ok(value) => {
+                        let mut meta = Default::default();
+                        value.display_source_and_get(&mut meta).to_owned()
+                    },
+                    Err(_) => text.trim().to_owned(),
+                };
+                out.push_str(&format!("const {} = {value};", self.name));
+            },
+            None => out.push_str(&format!(":{}", self.name)),
+        }
+        out.push_str("\n```");
+        if let Some(doc) = &self.doc {
+            out.push_str("\n\n");
+            out.push_str(doc);
+        }
+        out
+    }
+}
+
+/// 从const的`=`后开始, 到深度为零的`;`为止
+fn value_span(src: &str, tokens: &[Token]) -> Option<(usize, usize)> {
+    let first = tokens.first()?;
+    let mut depth = 0usize;
+    let mut end = first.start;
+    for token in tokens {
+        match token.text(src) {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => match depth.checked_sub(1) {
+                Some(new_depth) => depth = new_depth,
+                None => break,
+            },
+            ";" if depth == 0 => break,
+            _ => (),
+        }
+        end = token.end;
+    }
+    Some((first.start, end))
+}
+
+/// `:`是否开始了一个标记的定义, 而不是`goto`的目标或者其它语法中的冒号
+fn is_label_define(src: &str, tokens: &[Token], i: usize) -> bool {
+    if tokens.get(i + 1).map(|token| token.kind) != Some(TokenKind::Name) {
+        return false;
+    }
+    let Some(prev) = i.checked_sub(1).map(|prev| tokens[prev]) else {
+        return true;
+    };
+    match prev.kind {
+        // 连续的标记, 例如`:a :b`
+        TokenKind::Name => i >= 2 && tokens[i - 2].text(src) == ":"
+            && prev.text(src) != "goto",
+        TokenKind::Number | TokenKind::String => false,
+        TokenKind::Punct => matches!(prev.text(src), ";" | "{" | "}" | "("),
+    }
+}
+
+/// 将源码切分为令牌, 并另外返回所有文档注释的起止位置及其内容
+#[allow(clippy::type_complexity)]
+fn tokenize(src: &str) -> (Vec<Token>, Vec<(usize, usize, String)>) {
+    let mut tokens = Vec::new();
+    let mut docs = Vec::new();
+    let mut chars = src.char_indices().peekable();
+    let end_of = |i: usize| src[i..].chars().next().map_or(i, |ch| i + ch.len_utf8());
+
+    while let Some((start, ch)) = chars.next() {
+        let mut end = end_of(start);
+        let kind = match ch {
+            ch if ch.is_whitespace() => continue,
+            '#' if src[end..].starts_with('*') => {
+                // 长注释
+                let body_end = src[end + 1..].find("*#")
+                    .map_or(src.len(), |i| end + 1 + i);
+                let end = (body_end + 2).min(src.len());
+                if src[start..].starts_with("#**") && body_end > start + 2 {
+                    docs.push((start, end, clean_doc(&src[start + 3..body_end])));
+                }
+                while chars.next_if(|&(i, _)| i < end).is_some() {}
+                continue;
+            },
+            '#' => {
+                while chars.next_if(|&(_, ch)| ch != '\n').is_some() {}
+                continue;
+            },
+            '"' => {
+                while let Some((i, ch)) = chars.next() {
+                    end = end_of(i);
+                    match ch {
+                        '"' => break,
+                        '\\' => if let Some((i, _)) = chars.next() {
+                            end = end_of(i)
+                        },
+                        _ => (),
+                    }
+                }
+                TokenKind::String
+            },
+            '\'' => {
+                while let Some((i, ch)) = chars.next_if(|&(_, ch)| {
+                    !ch.is_whitespace()
+                }) {
+                    end = end_of(i);
+                    if ch == '\'' { break }
+                }
+                TokenKind::Name
+            },
+            '@' => {
+                while let Some((i, _)) = chars.next_if(|&(_, ch)| {
+                    ch.is_alphanumeric() || ch == '_' || ch == '-'
+                }) {
+                    end = end_of(i)
+                }
+                TokenKind::Name
+            },
+            ch if ch.is_ascii_digit() => {
+                while let Some((i, _)) = chars.next_if(|&(_, ch)| {
+                    ch.is_alphanumeric() || ch == '_' || ch == '.'
+                }) {
+                    end = end_of(i)
+                }
+                TokenKind::Number
+            },
+            ch if ch.is_alphabetic() || ch == '_' => {
+                while let Some((i, _)) = chars.next_if(|&(_, ch)| {
+                    ch.is_alphanumeric() || ch == '_'
+                }) {
+                    end = end_of(i)
+                }
+                TokenKind::Name
+            },
+            _ => TokenKind::Punct,
+        };
+        tokens.push(Token { kind, start, end })
+    }
+
+    (tokens, docs)
+}
+
+/// 去除文档注释每行开头的`*`以及首尾的空行
+fn clean_doc(body: &str) -> String {
+    let lines = body.lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect::<Vec<_>>();
+    lines.join("\n").trim_matches('\n').to_owned()
+}
+
+/// 将字节偏移转换为LSP所使用的行号及UTF-16列号
+pub fn position(src: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(src.len());
+    let before = &src[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count();
+    let column = before[line_start..].encode_utf16().count();
+    (line, column)
+}
+
+/// 将LSP的行号及UTF-16列号转换为字节偏移, 超出范围时将被限制在行或文本末尾
+pub fn offset(src: &str, line: usize, column: usize) -> usize {
+    let Some(line_start) = src.split_inclusive('\n')
+        .scan(0, |start, line| {
+            let res = *start;
+            *start += line.len();
+            Some(res)
+        })
+        .chain([src.len()])
+        .nth(line)
+    else {
+        return src.len();
+    };
+    let line_text = src[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (i, ch) in line_text.char_indices() {
+        if units >= column {
+            return line_start + i;
+        }
+        units += ch.len_utf16();
+    }
+    line_start + line_text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definition_test() {
+        let src = r#"
+            #** 打印一个值
+            * 第二行 *#
+            const Print = (
+                print _0;
+            );
+            const Print = 2;
+            :a
+            goto :a; # const X = 1;
+            take Print[1];
+            do { :b :c } while x > 1;
+            goto :c 'x' > (a: 1);
+            print "const Y = 2;";
+        "#;
+        let analysis = Analysis::new(src);
+        let names = |defs: &[Definition]| defs.iter()
+            .map(|def| def.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names(&analysis.consts), ["Print", "Print"]);
+        assert_eq!(names(&analysis.labels), ["a", "b", "c"]);
+
+        let first = &analysis.consts[0];
+        assert_eq!(first.doc.as_deref(), Some("打印一个值\n第二行"));
+        assert_eq!(
+            first.value.map(|(start, end)| &src[start..end]),
+            Some("(\n                print _0;\n            )"),
+        );
+        assert_eq!(
+            first.hover(src),
+            "```mdtlbl\nconst Print = (`'print'` _0;);\n```\n\n打印一个值\n第二行",
+        );
+        assert_eq!(analysis.consts[1].doc, None);
+
+        let take = src.find("take Print").unwrap() + 6;
+        let def = analysis.definition(src, take).unwrap();
+        assert_eq!(def.span.0, src.rfind("Print = 2").unwrap());
+
+        let goto_a = src.find("goto :a").unwrap() + 6;
+        let def = analysis.definition(src, goto_a).unwrap();
+        assert_eq!(def.span.0, src.find(":a").unwrap() + 1);
+
+        let goto_c = src.find("goto :c").unwrap() + 6;
+        assert_eq!(analysis.definition(src, goto_c).unwrap().name, "c");
+        assert_eq!(analysis.definition(src, src.find("(a:").unwrap() + 1), None);
+    }
+
+    #[test]
+    fn position_test() {
+        let src = "ab\n😀c\n\nd";
+        let c = src.find('c').unwrap();
+        assert_eq!(position(src, c), (1, 2));
+        assert_eq!(offset(src, 1, 2), c);
+        assert_eq!(position(src, src.len()), (3, 1));
+        assert_eq!(offset(src, 3, 1), src.len());
+        assert_eq!(offset(src, 0, 100), 2);
+        assert_eq!(offset(src, 100, 0), src.len());
+    }
+}
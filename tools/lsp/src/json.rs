@@ -0,0 +1,330 @@
+//! LSP消息所使用的最小JSON实现
+
+use std::{
+    fmt::{self, Display, Write},
+    iter::Peekable,
+    str::Chars,
+};
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Json {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// 保持插入顺序的对象
+    Object(Vec<(String, Json)>),
+}
+impl Json {
+    /// 获取对象中的一个成员, 不是对象或不存在时返回[`Json::Null`]
+    pub fn get(&self, key: &str) -> &Json {
+        const NULL: &Json = &Json::Null;
+        match self {
+            Self::Object(members) => members.iter()
+                .find(|(k, _)| k == key)
+                .map_or(NULL, |(_, v)| v),
+            _ => NULL,
+        }
+    }
+
+    /// 按照路径逐层获取成员
+    pub fn path(&self, keys: &[&str]) -> &Json {
+        keys.iter().fold(self, |json, key| json.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Self::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+
+    /// 解析一段JSON文本, 失败时返回出错的字符位置
+    pub fn parse(s: &str) -> Result<Self, usize> {
+        let mut parser = Parser { chars: s.chars().peekable(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_ws();
+        match parser.chars.peek() {
+            Some(_) => Err(parser.pos),
+            None => Ok(value),
+        }
+    }
+}
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write_str(f, s),
+            Self::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i != 0 { f.write_char(',')? }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            },
+            Self::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i != 0 { f.write_char(',')? }
+                    write_str(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            },
+        }
+    }
+}
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Self::Number(value as f64)
+    }
+}
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Self::String(value.into())
+    }
+}
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+impl From<Vec<Json>> for Json {
+    fn from(value: Vec<Json>) -> Self {
+        Self::Array(value)
+    }
+}
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+/// 构建一个[`Json::Object`]
+///
+/// # Examples
+/// ```ignore
+/// let json = object! { "a" => 1usize, "b" => "c" };
+/// assert_eq!(json.to_string(), r#"{"a":1,"b":"c"}"#);
+/// ```
+macro_rules! object {
+    ( $( $key:literal => $value:expr ),* $(,)? ) => {
+        $crate::json::Json::Object(vec![
+            $( ($key.into(), $crate::json::Json::from($value)) ),*
+        ])
+    };
+}
+pub(crate) use object;
+
+fn write_str(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if ch < ' ' => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// 已经读取的字符数
+    pos: usize,
+}
+impl Parser<'_> {
+    fn next(&mut self) -> Option<char> {
+        self.pos += 1;
+        self.chars.next()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.chars.next_if(|ch| ch.is_ascii_whitespace()).is_some() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), usize> {
+        for expected in s.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.pos);
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, usize> {
+        self.skip_ws();
+        Ok(match self.chars.peek() {
+            Some('n') => { self.expect("null")?; Json::Null },
+            Some('t') => { self.expect("true")?; Json::Bool(true) },
+            Some('f') => { self.expect("false")?; Json::Bool(false) },
+            Some('"') => Json::String(self.string()?),
+            Some('[') => {
+                self.next();
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.chars.next_if_eq(&']').is_some() {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_ws();
+                    match self.next() {
+                        Some(',') => (),
+                        Some(']') => break Json::Array(items),
+                        _ => return Err(self.pos),
+                    }
+                }
+            },
+            Some('{') => {
+                self.next();
+                let mut members = Vec::new();
+                self.skip_ws();
+                if self.chars.next_if_eq(&'}').is_some() {
+                    self.pos += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.skip_ws();
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    self.skip_ws();
+                    match self.next() {
+                        Some(',') => (),
+                        Some('}') => break Json::Object(members),
+                        _ => return Err(self.pos),
+                    }
+                }
+            },
+            Some(_) => {
+                let mut num = String::new();
+                while let Some(ch) = self.chars.next_if(|ch| {
+                    matches!(ch, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')
+                }) {
+                    self.pos += 1;
+                    num.push(ch)
+                }
+                Json::Number(num.parse().map_err(|_| self.pos)?)
+            },
+            None => return Err(self.pos),
+        })
+    }
+
+    fn string(&mut self) -> Result<String, usize> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            match self.next().ok_or(self.pos)? {
+                '"' => break Ok(s),
+                '\\' => s.push(match self.next().ok_or(self.pos)? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\x08',
+                    'f' => '\x0c',
+                    'u' => {
+                        let code = self.hex4()?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            // 代理对
+                            self.expect("\\u")?;
+                            let low = self.hex4()?;
+                            let code = 0x10000
+                                + ((code - 0xD800) << 10)
+                                + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            char::from_u32(code).unwrap_or('\u{FFFD}')
+                        } else {
+                            char::from_u32(code).unwrap_or('\u{FFFD}')
+                        }
+                    },
+                    ch => ch,
+                }),
+                ch => s.push(ch),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, usize> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next()
+                .and_then(|ch| ch.to_digit(16))
+                .ok_or(self.pos)?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let json = Json::parse(r#"
+            {"a": [1, -2.5e1, true, null], "b": {"c": "x\"A😀"}}
+        "#).unwrap();
+        assert_eq!(json.get("a"), &Json::Array(vec![
+            Json::Number(1.0),
+            Json::Number(-25.0),
+            Json::Bool(true),
+            Json::Null,
+        ]));
+        assert_eq!(json.path(&["b", "c"]).as_str(), Some("x\"A😀"));
+        assert!(json.path(&["b", "d", "e"]).is_null());
+        assert_eq!(Json::parse("[1,]"), Err(3));
+        assert_eq!(Json::parse("{} x"), Err(3));
+    }
+
+    #[test]
+    fn display_test() {
+        let json = object! {
+            "a" => 1usize,
+            "b" => "\"\n\x01",
+            "c" => vec![Json::Null, true.into()],
+            "d" => None::<usize>,
+        };
+        assert_eq!(
+            json.to_string(),
+            r#"{"a":1,"b":"\"\n\u0001","c":[null,true],"d":null}"#,
+        );
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+    }
+}
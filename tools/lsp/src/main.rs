@@ -0,0 +1,434 @@
+//! Bang语言的语言服务器, 通过标准输入输出与编辑器进行通信
+//!
+//! 支持的功能:
+//! - 编辑时的诊断信息
+//! - 跳转到const与标记的定义
+//! - 悬停显示const的值与其文档注释
+//! - 补全内建函数
+
+mod analysis;
+mod json;
+
+use std::{
+    collections::HashMap,
+    io::{self, stdin, stdout, BufRead, Write},
+    path::PathBuf,
+};
+
+use analysis::{offset, position, Analysis};
+use json::{object, Json};
+use mindustry_logic_bang_lang::{
+    compile,
+    CompileMode,
+    CompileOptions,
+    Diagnostic,
+    Severity,
+};
+use syntax::build_builtins;
+use utils::locale::{set_locale, Locale};
+
+/// JSON-RPC中方法不存在的错误码
+const METHOD_NOT_FOUND: f64 = -32601.0;
+
+/// 一个打开的文档
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+impl Document {
+    fn new(text: String) -> Self {
+        let analysis = Analysis::new(&text);
+        Self { text, analysis }
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    /// 收到`exit`通知后的退出码
+    exit_code: Option<i32>,
+}
+impl Server {
+    /// 处理一条消息, 返回需要发送给客户端的消息
+    fn handle(&mut self, msg: &Json) -> Vec<Json> {
+        let method = msg.get("method").as_str().unwrap_or_default();
+        let params = msg.get("params");
+        let id = msg.get("id");
+        if id.is_null() {
+            return self.notification(method, params);
+        }
+        let result = match method {
+            "initialize" => {
+                let locale = params.get("locale").as_str()
+                    .and_then(Locale::from_name);
+                if let Some(locale) = locale {
+                    set_locale(locale)
+                }
+                object! {
+                    "capabilities" => object! {
+                        "textDocumentSync" => 1usize,
+                        "hoverProvider" => true,
+                        "definitionProvider" => true,
+                        "completionProvider" => object! {
+                            "triggerCharacters" => vec![".".into()],
+                        },
+                    },
+                    "serverInfo" => object! {
+                        "name" => "mdtlbl-lsp",
+                        "version" => env!("CARGO_PKG_VERSION"),
+                    },
+                }
+            },
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            },
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/completion" => completion(),
+            _ => return vec![object! {
+                "jsonrpc" => "2.0",
+                "id" => id.clone(),
+                "error" => object! {
+                    "code" => Json::Number(METHOD_NOT_FOUND),
+                    "message" => format!("method not found: {method}"),
+                },
+            }],
+        };
+        vec![object! {
+            "jsonrpc" => "2.0",
+            "id" => id.clone(),
+            "result" => result,
+        }]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.path(&["textDocument", "uri"])
+            .as_str()
+            .unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.path(&["textDocument", "text"])
+                    .as_str()
+                    .unwrap_or_default();
+                self.documents.insert(uri.into(), Document::new(text.into()));
+                vec![self.publish_diagnostics(uri)]
+            },
+            "textDocument/didChange" => {
+                let text = params.get("contentChanges")
+                    .as_array()
+                    .and_then(<[Json]>::last)
+                    .and_then(|change| change.get("text").as_str());
+                let Some(text) = text else { return vec![] };
+                self.documents.insert(uri.into(), Document::new(text.into()));
+                vec![self.publish_diagnostics(uri)]
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![diagnostics_notification(uri, vec![])]
+            },
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                vec![]
+            },
+            _ => vec![],
+        }
+    }
+
+    /// 获取请求所指向的文档及光标的字节偏移
+    fn document_at(&self, params: &Json) -> Option<(&Document, usize)> {
+        let uri = params.path(&["textDocument", "uri"]).as_str()?;
+        let doc = self.documents.get(uri)?;
+        let line = params.path(&["position", "line"]).as_u64()?;
+        let column = params.path(&["position", "character"]).as_u64()?;
+        Some((doc, offset(&doc.text, line as usize, column as usize)))
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let Some((doc, offset)) = self.document_at(params) else {
+            return Json::Null;
+        };
+        let Some(def) = doc.analysis.definition(&doc.text, offset) else {
+            return Json::Null;
+        };
+        object! {
+            "contents" => object! {
+                "kind" => "markdown",
+                "value" => def.hover(&doc.text),
+            },
+        }
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let Some((doc, offset)) = self.document_at(params) else {
+            return Json::Null;
+        };
+        let Some(def) = doc.analysis.definition(&doc.text, offset) else {
+            return Json::Null;
+        };
+        object! {
+            "uri" => params.path(&["textDocument", "uri"]).clone(),
+            "range" => range(&doc.text, def.span),
+        }
+    }
+
+    /// 编译文档并发送其诊断信息
+    fn publish_diagnostics(&self, uri: &str) -> Json {
+        let Some(doc) = self.documents.get(uri) else {
+            return diagnostics_notification(uri, vec![]);
+        };
+        let path = uri_to_path(uri);
+        let options = CompileOptions {
+            modes: vec![CompileMode::BangToMdtLogic],
+            path: path.clone(),
+            ..Default::default()
+        };
+        let diagnostics = match compile(&doc.text, &options) {
+            Ok(output) => output.diagnostics,
+            Err(diagnostics) => diagnostics,
+        };
+        let path = path.map(|path| path.display().to_string());
+        let diagnostics = diagnostics.iter()
+            .map(|diagnostic| lsp_diagnostic(&doc.text, path.as_deref(), diagnostic))
+            .collect();
+        diagnostics_notification(uri, diagnostics)
+    }
+}
+
+fn completion() -> Json {
+    let items = build_builtins().into_iter()
+        .map(|func| {
+            let detail = format!("Builtin.{}[{}]", func.name(), func.args().join(" "));
+            object! {
+                "label" => func.name(),
+                // Function
+                "kind" => 3usize,
+                "detail" => detail,
+            }
+        })
+        .collect::<Vec<_>>();
+    items.into()
+}
+
+fn range(src: &str, (start, end): (usize, usize)) -> Json {
+    let pos = |offset| {
+        let (line, character) = position(src, offset);
+        object! { "line" => line, "character" => character }
+    };
+    object! { "start" => pos(start), "end" => pos(end) }
+}
+
+fn diagnostics_notification(uri: &str, diagnostics: Vec<Json>) -> Json {
+    object! {
+        "jsonrpc" => "2.0",
+        "method" => "textDocument/publishDiagnostics",
+        "params" => object! {
+            "uri" => uri,
+            "diagnostics" => diagnostics,
+        },
+    }
+}
+
+/// 将诊断信息转换为LSP的形式
+///
+/// 位于其它文件中的或者没有范围的诊断信息将被放置在文档开头
+fn lsp_diagnostic(src: &str, path: Option<&str>, diagnostic: &Diagnostic) -> Json {
+    let source_path = diagnostic.source.as_ref()
+        .and_then(|source| source.path.as_deref());
+    let mut message = diagnostic.message.clone();
+    let span = match diagnostic.span {
+        Some(span) if source_path == path => span,
+        _ => {
+            if let Some(source_path) = source_path.filter(|&p| Some(p) != path) {
+                message = format!("{source_path}: {message}");
+            }
+            (0, 0)
+        },
+    };
+    for note in &diagnostic.notes {
+        message.push('\n');
+        message.push_str(note);
+    }
+    if !diagnostic.expand_stack.is_empty() {
+        message.push('\n');
+        message.push_str(&diagnostic.expand_stack.join(" -> "));
+    }
+    let severity = match diagnostic.severity {
+        Severity::Error => 1usize,
+        Severity::Warning => 2,
+        Severity::Info => 3,
+    };
+    object! {
+        "range" => range(src, span),
+        "severity" => severity,
+        "source" => "mdtlbl",
+        "message" => message,
+    }
+}
+
+/// 将`file://`形式的URI转换为路径, 其它形式的URI没有路径
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+/// 读取一条带有`Content-Length`头的消息, 输入结束时返回空
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+    Json::parse(&body).map(Some).map_err(|pos| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid json at char {pos}"),
+        )
+    })
+}
+
+fn write_message(output: &mut impl Write, msg: &Json) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn main() -> io::Result<()> {
+    let mut input = stdin().lock();
+    let mut output = stdout().lock();
+    let mut server = Server::default();
+    while let Some(msg) = read_message(&mut input)? {
+        for reply in server.handle(&msg) {
+            write_message(&mut output, &reply)?;
+        }
+        if let Some(code) = server.exit_code {
+            std::process::exit(code)
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(id: usize, method: &str, params: Json) -> Json {
+        object! {
+            "jsonrpc" => "2.0",
+            "id" => id,
+            "method" => method,
+            "params" => params,
+        }
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        object! {
+            "jsonrpc" => "2.0",
+            "method" => method,
+            "params" => params,
+        }
+    }
+
+    fn position_params(line: usize, character: usize) -> Json {
+        object! {
+            "textDocument" => object! { "uri" => "untitled:a" },
+            "position" => object! {
+                "line" => line,
+                "character" => character,
+            },
+        }
+    }
+
+    #[test]
+    fn server_test() {
+        let mut server = Server::default();
+        let reply = server.handle(&request(1, "initialize", object! {}));
+        assert_eq!(reply[0].path(&["result", "capabilities", "hoverProvider"]),
+                   &Json::Bool(true));
+
+        let open = notification("textDocument/didOpen", object! {
+            "textDocument" => object! {
+                "uri" => "untitled:a",
+                "text" => "#** doc *#\nconst A = 1;\nprint A\n",
+            },
+        });
+        let reply = server.handle(&open);
+        let diagnostics = reply[0].path(&["params", "diagnostics"]);
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(
+            diagnostics.as_array().unwrap()[0].path(&["range", "start", "line"]),
+            &Json::from(2usize),
+        );
+
+        let reply = server.handle(&request(2, "textDocument/definition", position_params(2, 6)));
+        assert_eq!(reply[0].path(&["result", "range", "start"]), &object! {
+            "line" => 1usize,
+            "character" => 6usize,
+        });
+
+        let reply = server.handle(&request(3, "textDocument/hover", position_params(2, 6)));
+        assert_eq!(
+            reply[0].path(&["result", "contents", "value"]).as_str(),
+            Some("```mdtlbl\nconst A = 1;\n```\n\ndoc"),
+        );
+
+        let reply = server.handle(&request(4, "textDocument/completion", Json::Null));
+        let items = reply[0].get("result").as_array().unwrap();
+        assert!(items.iter().any(|item| item.get("label").as_str() == Some("Info")));
+
+        let reply = server.handle(&request(5, "unknown", Json::Null));
+        assert!(!reply[0].path(&["error", "code"]).is_null());
+
+        server.handle(&request(6, "shutdown", Json::Null));
+        server.handle(&notification("exit", Json::Null));
+        assert_eq!(server.exit_code, Some(0));
+    }
+
+    #[test]
+    fn uri_to_path_test() {
+        assert_eq!(
+            uri_to_path("file:///a%20b/c%2"),
+            Some(PathBuf::from("/a b/c%2")),
+        );
+        assert_eq!(uri_to_path("untitled:a"), None);
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env::current_dir,
+    io,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use ::lalrpop_util::{lexer::Token, ParseError};
+use ::syntax::{Error, Errors, Expand, Import, Importer, InlineBlock, Meta};
+
+use crate::{TopLevelParser, STD_PREFIX, std_module};
+
+/// 不借用源码的解析错误
+pub type OwnedParseError = ParseError<usize, (usize, String), Error>;
+
+/// 某个源码文件中的解析错误
+#[derive(Debug)]
+pub struct SourceError {
+    /// 出错的文件, 为空时表示没有路径的顶层源码
+    pub path: Option<PathBuf>,
+    /// 出错文件的源码
+    pub src: String,
+    pub error: Box<OwnedParseError>,
+    /// 从顶层开始的导入链, 不包含出错的文件本身
+    pub import_chain: Vec<PathBuf>,
+}
+
+struct State<F> {
+    read: F,
+    /// 正在解析的文件栈, 栈顶为当前文件
+    stack: Vec<PathBuf>,
+    /// 已经解析过的文件, 再次导入时直接使用
+    loaded: HashMap<PathBuf, InlineBlock>,
+    /// 最内层被导入文件的解析错误
+    error: Option<SourceError>,
+}
+
+struct FileImporter<F>(RefCell<State<F>>);
+impl<F> Importer for FileImporter<F>
+where F: FnMut(&Path) -> io::Result<String>,
+{
+    fn import(&self, meta: &mut Meta, path: &str) -> Result<Import, Errors> {
+        let (file, src) = {
+            let mut state = self.0.borrow_mut();
+            let base = state.stack.last()
+                .and_then(|file| file.parent())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            let std_src = path.strip_prefix(STD_PREFIX).map(|name| {
+                std_module(name).ok_or_else(|| {
+                    Errors::ImportStdNotFound(name.into())
+                })
+            }).transpose()?;
+            let file = if std_src.is_some() {
+                source_key(Path::new(path))
+            } else {
+                source_key(&base.join(path))
+            };
+
+            if let Some(i) = state.stack.iter().position(|f| *f == file) {
+                let mut chain: Vec<String> = state.stack[i..].iter()
+                    .map(|f| f.display().to_string())
+                    .collect();
+                chain.push(file.display().to_string());
+                return Err(Errors::ImportCycle(chain));
+            }
+            if let Some(lines) = state.loaded.get(&file) {
+                return Ok(Import {
+                    key: file.display().to_string(),
+                    lines: lines.clone(),
+                });
+            }
+            let src = match std_src {
+                Some(src) => src.into(),
+                None => (state.read)(&file).map_err(|e| {
+                    Errors::ImportReadFailed(
+                        file.display().to_string(),
+                        e.to_string(),
+                    )
+                })?,
+            };
+            state.stack.push(file.clone());
+            (file, src)
+        };
+
+        // 解析时会递归的调用导入器, 所以不能持有借用
+        let old_file = meta.set_source_file(
+            Some(file.display().to_string()),
+            &src,
+        );
+        let res = TopLevelParser::new().parse(meta, &src);
+        meta.restore_source_file(old_file);
+
+        let mut state = self.0.borrow_mut();
+        state.stack.pop();
+        res.map(|expand| {
+            let lines = InlineBlock(expand.0);
+            state.loaded.insert(file.clone(), lines.clone());
+            Import { key: file.display().to_string(), lines }
+        }).map_err(|e| {
+            if state.error.is_none() {
+                state.error = Some(SourceError {
+                    path: Some(file.clone()),
+                    src: src.clone(),
+                    error: into_owned(e),
+                    import_chain: state.stack.clone(),
+                });
+            }
+            Errors::ImportParseFailed(file.display().to_string())
+        })
+    }
+}
+
+/// 解析源码, 并处理其中的`import`
+///
+/// `path`为源码所在的文件, 被导入的路径相对于导入它的文件所在目录,
+/// 如果顶层源码没有路径, 那么相对于当前工作目录
+///
+/// `read`用于读取被导入的文件, 每个文件最多只会被读取并解析一次,
+/// 编译时在同一个作用域及其子作用域中最多只会展开一次, 见[`Import`],
+/// 循环导入将返回错误
+///
+/// 以[`STD_PREFIX`]开头的路径将从内置的标准库中导入, 而不会调用`read`
+///
+/// 所有文件中的测试块都会被剔除, 见[`find_test_blocks`]
+///
+/// [`find_test_blocks`]: crate::find_test_blocks
+pub fn parse_with_imports<F>(
+    meta: &mut Meta,
+    src: &str,
+    path: Option<&Path>,
+    read: F,
+) -> Result<Expand, SourceError>
+where F: FnMut(&Path) -> io::Result<String> + 'static,
+{
+    let mut state = State {
+        read,
+        stack: Vec::new(),
+        loaded: HashMap::new(),
+        error: None,
+    };
+    match path {
+        Some(path) => {
+            state.stack.push(source_key(path));
+        },
+        None => {
+            // 使用一个不存在的文件名来占据当前目录
+            state.stack.push(normalize(Path::new("-")));
+        },
+    }
+    let importer = Rc::new(FileImporter(RefCell::new(state)));
+
+    let dyn_importer: Rc<dyn Importer> = importer.clone();
+    let old_importer = meta.set_importer(Some(dyn_importer));
+    let old_file = meta.set_source_file(
+        path.map(|path| path.display().to_string()),
+        src,
+    );
+    let res = TopLevelParser::new().parse(meta, src);
+    meta.restore_source_file(old_file);
+    meta.set_importer(old_importer);
+
+    res.map_err(|e| {
+        if let ParseError::User {
+            error: Error { err: Errors::ImportParseFailed(_), .. }
+        } = e {
+            if let Some(error) = importer.0.borrow_mut().error.take() {
+                return error;
+            }
+        }
+        SourceError {
+            path: path.map(Path::to_path_buf),
+            src: src.into(),
+            error: into_owned(e),
+            import_chain: Vec::new(),
+        }
+    })
+}
+
+fn into_owned(e: ParseError<usize, Token<'_>, Error>) -> Box<OwnedParseError> {
+    e.map_token(|Token(i, s)| (i, s.into())).into()
+}
+
+/// 获取源码文件用于判断重复与循环导入的键
+///
+/// 标准库模块的名称例如`std::fmt`保持原样, 其它路径使用[`normalize`]
+fn source_key(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(name) if name.starts_with(STD_PREFIX) => path.to_path_buf(),
+        _ => normalize(path),
+    }
+}
+
+/// 将路径转换为绝对路径, 并且在不访问文件系统的情况下消除`.`与`..`
+fn normalize(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        current_dir().unwrap_or_default().join(path)
+    };
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => { res.pop(); },
+            component => res.push(component),
+        }
+    }
+    res
+}
@@ -1,3 +1,42 @@
 mod parser;
+mod import;
+mod std_lib;
+mod test_block;
 pub use crate::parser::*;
+pub use crate::import::*;
+pub use crate::std_lib::*;
+pub use crate::test_block::*;
 pub use ::lalrpop_util;
+
+use ::lalrpop_util::{lexer::Token, ParseError};
+use ::syntax::{Error, Expand, Meta};
+
+/// 顶层源码的解析器, 解析前会剔除源码中的测试块, 见[`strip_test_blocks`]
+///
+/// 测试属性有误时将作为解析错误返回, 而不是将测试块编译进去
+pub struct TopLevelParser(crate::parser::TopLevelParser);
+impl TopLevelParser {
+    pub fn new() -> Self {
+        Self(crate::parser::TopLevelParser::new())
+    }
+
+    pub fn parse<'input>(
+        &self,
+        meta: &mut Meta,
+        src: &'input str,
+    ) -> Result<Expand, ParseError<usize, Token<'input>, Error>> {
+        let stripped = strip_test_blocks(src)
+            .map_err(|e| ParseError::User { error: e.into() })?;
+        // 剔除时不会改变偏移, 所以可以将词法单元映射回原始源码
+        self.0.parse(meta, &stripped).map_err(|e| {
+            e.map_token(|Token(i, s)| {
+                Token(i, src.get(i..i+s.len()).unwrap_or(""))
+            })
+        })
+    }
+}
+impl Default for TopLevelParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
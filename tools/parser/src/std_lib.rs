@@ -0,0 +1,18 @@
+//! 内置于编译器中的标准库
+
+/// 导入标准库模块时使用的路径前缀, 例如`import "std::math";`
+pub const STD_PREFIX: &str = "std::";
+
+/// 标准库中各模块的名称与源码
+pub const STD_MODULES: &[(&str, &str)] = &[
+    ("fmt", include_str!("../../../examples/std/fmt.mdtlbl")),
+    ("math", include_str!("../../../examples/std/math.mdtlbl")),
+    ("mem", include_str!("../../../examples/std/mem.mdtlbl")),
+];
+
+/// 获取给定名称的标准库模块源码
+pub fn std_module(name: &str) -> Option<&'static str> {
+    STD_MODULES.iter()
+        .find(|&&(module, _)| module == name)
+        .map(|&(_, src)| src)
+}
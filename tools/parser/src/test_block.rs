@@ -0,0 +1,279 @@
+//! 查找与剔除源码中带有测试属性的块
+//!
+//! 测试属性是以`#[`开头的行注释, 所以不会影响正常的解析,
+//! 属性之后需要紧跟一个块, 中间可以有其它注释:
+//!
+//! ```text
+//! #[test print_hex]
+//! #[assert print = "a"]
+//! #[assert n = 10]
+//! {
+//!     n = 10;
+//!     take PrintLowerHexChar[n];
+//! }
+//! ```
+//!
+//! - `#[test]`或者`#[test NAME]`标记一个测试块
+//! - `#[assert print = "TEXT"]`断言运行中打印的所有文本
+//! - `#[assert VAR = VALUE]`断言运行结束时变量的值
+//!
+//! 正常编译时测试块将被剔除, 仅在测试时编译
+
+use std::{borrow::Cow, fmt::Display};
+
+pub use syntax::TestAttrErrorKind;
+use syntax::{Error, Errors};
+use utils::message::Message;
+
+/// 一个测试块
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestBlock {
+    pub name: Option<String>,
+    /// `#[test]`属性的范围
+    pub attr: (usize, usize),
+    /// 所有属性的范围
+    pub attrs: Vec<(usize, usize)>,
+    pub asserts: Vec<TestAssert>,
+    /// 从`{`到`}`的范围
+    pub block: (usize, usize),
+}
+impl TestBlock {
+    /// 从第一个属性到块结束的范围
+    pub fn span(&self) -> (usize, usize) {
+        let start = self.attrs.first().map_or(self.block.0, |attr| attr.0);
+        (start, self.block.1)
+    }
+}
+
+/// 一个断言属性, 例如`#[assert a = 1]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestAssert {
+    pub span: (usize, usize),
+    /// 断言的变量名, `print`表示打印的文本
+    pub name: String,
+    /// 原样的期望值, 例如`1`或者`"text"`
+    pub expected: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestAttrError {
+    pub span: (usize, usize),
+    pub kind: TestAttrErrorKind,
+}
+impl TestAttrError {
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        self.kind.message_kind()
+    }
+}
+impl Display for TestAttrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.kind, f)
+    }
+}
+impl From<TestAttrError> for Error {
+    fn from(TestAttrError { span: (start, end), kind }: TestAttrError) -> Self {
+        Self { start, end, err: Errors::TestAttr(kind) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lexeme<'a> {
+    /// `#[`与`]`之间的属性内容
+    Attr(&'a str),
+    Comment,
+    Open,
+    Close,
+    Other,
+}
+
+/// 粗略的词法分析, 只区分注释, 属性与花括号, 跳过字符串等
+fn lex(src: &str) -> Vec<(usize, usize, Lexeme<'_>)> {
+    let bytes = src.as_bytes();
+    let line_end = |i: usize| src[i..].find(['\r', '\n'])
+        .map_or(src.len(), |n| i + n);
+    let mut lexemes = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let lexeme = match bytes[i] {
+            b'#' if bytes.get(i+1) == Some(&b'*') => {
+                i = src[i+2..].find("*#").map_or(src.len(), |n| i + 2 + n + 2);
+                Lexeme::Comment
+            },
+            b'#' => {
+                i = line_end(i);
+                let text = src[start..i].trim_end();
+                match text.strip_prefix("#[").and_then(|s| s.strip_suffix(']')) {
+                    Some(attr) => Lexeme::Attr(attr),
+                    None => Lexeme::Comment,
+                }
+            },
+            b'"' => {
+                i = src[i+1..].find('"').map_or(src.len(), |n| i + 1 + n + 1);
+                Lexeme::Other
+            },
+            b'\'' => {
+                // 原始字面量, 例如`'@abc-def'`
+                let len = src[i+1..]
+                    .find(|ch: char| ch == '\'' || ch.is_whitespace())
+                    .filter(|&n| n != 0 && src[i+1+n..].starts_with('\''));
+                i += len.map_or(1, |n| n + 2);
+                Lexeme::Other
+            },
+            b'{' => { i += 1; Lexeme::Open },
+            b'}' => { i += 1; Lexeme::Close },
+            ch if ch.is_ascii_whitespace() => { i += 1; continue },
+            _ => {
+                i += src[i..].chars().next().map_or(1, char::len_utf8);
+                Lexeme::Other
+            },
+        };
+        lexemes.push((start, i, lexeme));
+    }
+    lexemes
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Attr<'a> {
+    Test(Option<&'a str>),
+    Assert(&'a str, &'a str),
+    /// 不是测试相关的属性, 作为普通注释
+    Unknown,
+}
+impl<'a> Attr<'a> {
+    fn parse(attr: &'a str) -> Result<Self, ()> {
+        let (name, rest) = attr.trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((attr.trim(), ""));
+        let rest = rest.trim();
+        Ok(match name {
+            "test" if rest.is_empty() => Self::Test(None),
+            "test" if !rest.contains(char::is_whitespace) => {
+                Self::Test(Some(rest))
+            },
+            "assert" => {
+                let (name, expected) = rest.split_once('=').ok_or(())?;
+                let (name, expected) = (name.trim(), expected.trim());
+                if name.is_empty()
+                    || name.contains(char::is_whitespace)
+                    || expected.is_empty()
+                {
+                    return Err(());
+                }
+                Self::Assert(name, expected)
+            },
+            "test" => return Err(()),
+            _ => Self::Unknown,
+        })
+    }
+}
+
+/// 由属性之后未紧跟块所产生的错误
+fn unused_attrs_err(attrs: &[(usize, usize, Attr<'_>)]) -> TestAttrError {
+    let test = attrs.iter().find(|(_, _, attr)| matches!(attr, Attr::Test(_)));
+    match test {
+        Some(&(start, end, _)) => TestAttrError {
+            span: (start, end),
+            kind: TestAttrErrorKind::ExpectedBlock,
+        },
+        None => TestAttrError {
+            span: (attrs[0].0, attrs[0].1),
+            kind: TestAttrErrorKind::AssertWithoutTest,
+        },
+    }
+}
+
+/// 查找源码中所有的测试块
+pub fn find_test_blocks(src: &str) -> Result<Vec<TestBlock>, TestAttrError> {
+    let lexemes = lex(src);
+    let mut blocks = Vec::new();
+    let mut attrs: Vec<(usize, usize, Attr<'_>)> = Vec::new();
+    let mut iter = lexemes.iter();
+    while let Some(&(start, end, lexeme)) = iter.next() {
+        match lexeme {
+            Lexeme::Attr(attr) => match Attr::parse(attr) {
+                Ok(Attr::Unknown) => (),
+                Ok(attr) => attrs.push((start, end, attr)),
+                Err(()) => return Err(TestAttrError {
+                    span: (start, end),
+                    kind: TestAttrErrorKind::InvalidAttr(attr.into()),
+                }),
+            },
+            Lexeme::Comment => (),
+            Lexeme::Open if !attrs.is_empty() => {
+                let mut tests = attrs.iter().filter_map(|&(start, end, attr)| {
+                    match attr {
+                        Attr::Test(name) => Some((start, end, name)),
+                        _ => None,
+                    }
+                });
+                let (Some(test), None) = (tests.next(), tests.next()) else {
+                    return Err(unused_attrs_err(&attrs));
+                };
+                let mut depth = 0usize;
+                let close = iter.by_ref().find(|&&(_, _, lexeme)| {
+                    match lexeme {
+                        Lexeme::Open => depth += 1,
+                        Lexeme::Close if depth == 0 => return true,
+                        Lexeme::Close => depth -= 1,
+                        _ => (),
+                    }
+                    false
+                }).ok_or(TestAttrError {
+                    span: (start, end),
+                    kind: TestAttrErrorKind::UnclosedBlock,
+                })?;
+                let asserts = attrs.iter()
+                    .filter_map(|&(start, end, attr)| match attr {
+                        Attr::Assert(name, expected) => Some(TestAssert {
+                            span: (start, end),
+                            name: name.into(),
+                            expected: expected.into(),
+                        }),
+                        _ => None,
+                    })
+                    .collect();
+                blocks.push(TestBlock {
+                    name: test.2.map(Into::into),
+                    attr: (test.0, test.1),
+                    attrs: attrs.drain(..)
+                        .map(|(start, end, _)| (start, end))
+                        .collect(),
+                    asserts,
+                    block: (start, close.1),
+                });
+            },
+            _ if !attrs.is_empty() => return Err(unused_attrs_err(&attrs)),
+            _ => (),
+        }
+    }
+    if !attrs.is_empty() {
+        return Err(unused_attrs_err(&attrs));
+    }
+    Ok(blocks)
+}
+
+/// 将源码中的一个范围替换为空格, 保留换行以使其它部分的位置不变
+pub fn blank_out(src: &mut String, (start, end): (usize, usize)) {
+    let blanked = src[start..end].chars()
+        .map(|ch| match ch {
+            '\r' | '\n' => ch.to_string(),
+            ch => " ".repeat(ch.len_utf8()),
+        })
+        .collect::<String>();
+    src.replace_range(start..end, &blanked);
+}
+
+/// 剔除源码中的测试块, 测试属性有误时返回错误
+pub fn strip_test_blocks(src: &str) -> Result<Cow<'_, str>, TestAttrError> {
+    let blocks = find_test_blocks(src)?;
+    if blocks.is_empty() {
+        return Ok(src.into());
+    }
+    let mut src = src.to_owned();
+    for block in blocks {
+        blank_out(&mut src, block.span());
+    }
+    Ok(src.into())
+}
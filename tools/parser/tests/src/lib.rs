@@ -5032,3 +5032,858 @@ fn builtin_func_test() {
         ],
     );
 }
+
+#[test]
+fn import_test() {
+    use std::{collections::HashMap, io, path::Path};
+    use lalrpop_util::ParseError;
+
+    let files: HashMap<&str, &str> = HashMap::from([
+        ("/lib/a.mdtlbl", r#"
+            import "b.mdtlbl";
+            const A = (print "a";);
+        "#),
+        ("/lib/b.mdtlbl", r#"
+            const B = (print "b";);
+            print "b loaded";
+        "#),
+        ("/lib/cycle1.mdtlbl", r#"import "cycle2.mdtlbl";"#),
+        ("/lib/cycle2.mdtlbl", r#"import "/lib/cycle1.mdtlbl";"#),
+        ("/lib/bad.mdtlbl", r#"print "ok"; print"#),
+    ]);
+    let read = move |path: &Path| {
+        files.get(path.to_str().unwrap())
+            .map(|s| s.to_string())
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    };
+    let parse = |src: &str| {
+        parse_with_imports(
+            &mut Meta::new(),
+            src,
+            Some(Path::new("/main.mdtlbl")),
+            read.clone(),
+        )
+    };
+
+    let ast = parse(r#"
+    import "lib/a.mdtlbl";
+    import "lib/b.mdtlbl";
+    import "./lib/sub/../b.mdtlbl"; # 不会被重复导入
+    {
+        take A;
+    }
+    take B;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            r#"print "b loaded""#,
+            r#"print "a""#,
+            r#"print "b""#,
+        ],
+    );
+
+    // 在互不包含的作用域中会分别展开, 而在子作用域中不会重复展开
+    let ast = parse(r#"
+    {
+        import "lib/b.mdtlbl";
+        take B;
+    }
+    {
+        import "lib/b.mdtlbl";
+        take B;
+        {
+            import "lib/b.mdtlbl";
+            take B;
+        }
+    }
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            r#"print "b loaded""#,
+            r#"print "b""#,
+            r#"print "b loaded""#,
+            r#"print "b""#,
+            r#"print "b""#,
+        ],
+    );
+
+    let err = parse(r#"import "lib/cycle1.mdtlbl";"#).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some(Path::new("/lib/cycle2.mdtlbl")));
+    assert_eq!(err.import_chain, vec![
+        Path::new("/main.mdtlbl"),
+        Path::new("/lib/cycle1.mdtlbl"),
+    ]);
+    let ParseError::User { error: Error { err, .. } } = *err.error else {
+        panic!()
+    };
+    assert_eq!(err, Errors::ImportCycle(vec![
+        "/lib/cycle1.mdtlbl".into(),
+        "/lib/cycle2.mdtlbl".into(),
+        "/lib/cycle1.mdtlbl".into(),
+    ]));
+
+    let err = parse(r#"import "lib/bad.mdtlbl";"#).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some(Path::new("/lib/bad.mdtlbl")));
+    assert!(matches!(*err.error, ParseError::UnrecognizedEof { .. }));
+
+    let err = parse(r#"import "lib/none.mdtlbl";"#).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some(Path::new("/main.mdtlbl")));
+    assert!(matches!(*err.error, ParseError::User {
+        error: Error { err: Errors::ImportReadFailed(..), .. }
+    }));
+
+    let ast = parse(r#"
+    import "std::math";
+    import "std::math";
+    print PI;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec!["print 3.1415926535897932"],
+    );
+
+    // 标准库模块作为顶层源码时, 与被导入时使用相同的路径
+    let err = parse_with_imports(
+        &mut Meta::new(),
+        r#"import "std::fmt";"#,
+        Some(Path::new("std::fmt")),
+        read.clone(),
+    ).unwrap_err();
+    assert_eq!(err.path.as_deref(), Some(Path::new("std::fmt")));
+    let ParseError::User { error: Error { err, .. } } = *err.error else {
+        panic!()
+    };
+    assert_eq!(err, Errors::ImportCycle(vec![
+        "std::fmt".into(),
+        "std::fmt".into(),
+    ]));
+
+    let err = parse(r#"import "std::none";"#).unwrap_err();
+    assert!(matches!(*err.error, ParseError::User {
+        error: Error { err: Errors::ImportStdNotFound(..), .. }
+    }));
+
+    for &(name, src) in STD_MODULES {
+        assert!(parse!(TopLevelParser::new(), src).is_ok(), "std::{name}");
+    }
+
+    let parser = TopLevelParser::new();
+    assert!(parse!(parser, r#"import "a.mdtlbl";"#).is_err());
+}
+
+#[test]
+fn test_block_test() {
+    let src = r#"
+    const A = (print "{";); # {
+    #[test a]
+    #[assert print = "}"]
+    # 普通的注释
+    #* { *#
+    {
+        print '{' "}";
+        { print 2; }
+    }
+    #[other]
+    { print 3; }
+    #[assert x = 1]
+    #[test]
+    { x = 1; }
+    print 4;
+    "#;
+    let blocks = find_test_blocks(src).unwrap();
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].name.as_deref(), Some("a"));
+    assert_eq!(blocks[0].asserts, vec![TestAssert {
+        span: blocks[0].attrs[1],
+        name: "print".into(),
+        expected: r#""}""#.into(),
+    }]);
+    let (start, end) = blocks[0].block;
+    assert!(src[start..end].starts_with('{'));
+    assert!(src[start..end].ends_with("{ print 2; }\n    }"));
+    assert_eq!(blocks[1].name, None);
+    assert_eq!(blocks[1].asserts[0].name, "x");
+    assert_eq!(&src[blocks[1].span().0..blocks[1].span().1],
+               "#[assert x = 1]\n    #[test]\n    { x = 1; }");
+
+    let stripped = strip_test_blocks(src).unwrap();
+    assert_eq!(stripped.len(), src.len());
+    assert_eq!(stripped.lines().count(), src.lines().count());
+    let ast = parse!(TopLevelParser::new(), src).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec!["print 3", "print 4"],
+    );
+
+    let err = |src| find_test_blocks(src).unwrap_err().kind;
+    assert_eq!(err("#[test]\nprint 1;"), TestAttrErrorKind::ExpectedBlock);
+    assert_eq!(err("#[test]"), TestAttrErrorKind::ExpectedBlock);
+    assert_eq!(err("#[test]\n#[test]\n{}"), TestAttrErrorKind::ExpectedBlock);
+    assert_eq!(err("#[assert a = 1]\n{}"),
+               TestAttrErrorKind::AssertWithoutTest);
+    assert_eq!(err("#[test]\n{ {}"), TestAttrErrorKind::UnclosedBlock);
+    assert_eq!(err("#[assert a]"),
+               TestAttrErrorKind::InvalidAttr("assert a".into()));
+    assert_eq!(strip_test_blocks("#[test]\nprint 1;").unwrap_err().kind,
+               TestAttrErrorKind::ExpectedBlock);
+
+    let Err(lalrpop_util::ParseError::User { error }) = parse!(
+        TopLevelParser::new(),
+        "print 0;\n#[test]\nprint 1;"
+    ) else { panic!() };
+    assert_eq!((error.start, error.end), (9, 16));
+    assert_eq!(error.err, Errors::TestAttr(TestAttrErrorKind::ExpectedBlock));
+}
+
+#[test]
+fn propagate_consts_test() {
+    let parser = TopLevelParser::new();
+
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    a = 2;
+    op b a + 3;
+    print b;
+    if b > 4 { print 1; }
+    op c a + 1;
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(2));
+    assert_eq!(tag_codes.compile().unwrap(), vec![
+        "set a 2",
+        "set b 5",
+        "print b",
+        "print 1",
+        "op add c a 1",
+    ]);
+
+    // 被跳转到的行之后不再已知
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    i = 0;
+    while i < 3 { op i i + 1; }
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(1));
+    assert_eq!(tag_codes.compile().unwrap(), vec![
+        "set i 0",
+        "op add i i 1",
+        "jump 1 lessThan i 3",
+    ]);
+
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    a = 1;
+    b = a;
+    if a != 1 { print 1; }
+    op x 0.1 + 0.2;
+    if x == 0.3 { print 2; }
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(4));
+    assert_eq!(tag_codes.compile().unwrap(), vec![
+        "set a 1",
+        "set b 1",
+        "jump 4 always 0 0",
+        "print 1",
+        "set x 0.30000000000000004",
+        "print 2",
+    ]);
+
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    a = 1;
+    read a cell1 0;
+    op b a + 1;
+    op c rand 1;
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(0));
+
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    a = 1;
+    op @counter @counter + n;
+    op b a + 1;
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(0));
+
+    // 移位的位数对64取模, 与运行时相同
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    i = 70;
+    j = -1;
+    op a 1 << i;
+    op b 8 >> i;
+    op c 256 >> j;
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(3));
+    assert_eq!(tag_codes.compile().unwrap(), vec![
+        "set i 70",
+        "set j -1",
+        "set a 64",
+        "set b 0",
+        "set c 0",
+    ]);
+
+    // null不视为已知的数字, 在游戏中`null == null`成立
+    let mut tag_codes = CompileMeta::new().compile(parse!(parser, r#"
+    x = null;
+    goto :a x == null;
+    print 2;
+    :a
+    print 1;
+    end;
+    "#).unwrap());
+    assert_eq!(propagate_consts(&mut tag_codes), Ok(0));
+    assert_eq!(tag_codes.compile().unwrap(), vec![
+        "set x null",
+        "jump 3 equal x null",
+        "print 2",
+        "print 1",
+        "end",
+    ]);
+}
+
+#[test]
+fn rebuild_control_flow_test() {
+    let parser = TopLevelParser::new();
+
+    let logic_lines = CompileMeta::new().compile(parse!(parser, r#"
+    if a < b { print 1; } else { print 2; }
+    while i < 3 { op i i + 1; }
+    do { op j j + 1; } while j < 3;
+    gwhile k < 3 { op k k + 1; }
+    if c { print 3; }
+    select n {
+        print 4;
+        { print 5; print 6; print 7; print 8; }
+    }
+    "#).unwrap()).compile().unwrap();
+    let tag_codes = TagCodes::from_str(&logic_lines.join("\n")).unwrap();
+    let lines = rebuild_control_flow(&Expand::try_from(&tag_codes).unwrap());
+    assert!(matches!(
+        lines[..],
+        [
+            LogicLine::ControlFlow(ControlFlow::If(_, _, Some(_))),
+            LogicLine::ControlFlow(ControlFlow::While(..)),
+            LogicLine::ControlFlow(ControlFlow::DoWhile(..)),
+            LogicLine::ControlFlow(ControlFlow::Gwhile(..)),
+            LogicLine::ControlFlow(ControlFlow::If(_, _, None)),
+            LogicLine::Select(_),
+        ]
+    ), "{lines:#?}");
+    // 重建后的编译结果与原本的相同
+    let rebuilt = CompileMeta::new().compile(lines).compile().unwrap();
+    assert_eq!(rebuilt, logic_lines);
+
+    // 被多次跳转的标记不会被结构所使用
+    let logic_lines = [
+        "jump 2 lessThan a b",
+        "print 1",
+        "print 2",
+        "jump 2 lessThan c d",
+    ];
+    let mut tag_codes = TagCodes::from_str(&logic_lines.join("\n")).unwrap();
+    tag_codes.build_tagdown().unwrap();
+    tag_codes.tag_up();
+    let lines = rebuild_control_flow(&Expand::try_from(&tag_codes).unwrap());
+    assert!(matches!(
+        lines[..],
+        [
+            LogicLine::Goto(_),
+            LogicLine::Instruction(_),
+            LogicLine::Label(_),
+            LogicLine::Instruction(_),
+            LogicLine::Goto(_),
+        ]
+    ), "{lines:#?}");
+}
+
+#[test]
+fn instruction_test() {
+    let logic_lines = [
+        "read x cell1 0",
+        "sensor y @unit @x",
+        "ubind @poly",
+        "write y cell1 1",
+        "packcolor c 1 0.5 0 1",
+        "read x cell1",
+        "end",
+        "draw clear 0 0 0",
+        "ucontrol move x y",
+    ];
+    let tag_codes = TagCodes::from_str(&logic_lines.join("\n")).unwrap();
+    let lines = Expand::try_from(&tag_codes).unwrap();
+    let name = |line: &LogicLine| match line {
+        LogicLine::Instruction(inst) => Some(inst.name()),
+        _ => None,
+    };
+    assert_eq!(name(&lines[0]), Some("read"));
+    let LogicLine::Instruction(read) = &lines[0] else { unreachable!() };
+    assert_eq!(read.args(), ["x".into(), "cell1".into(), "0".into()]);
+    assert_eq!(name(&lines[1]), Some("sensor"));
+    assert_eq!(name(&lines[2]), Some("ubind"));
+    assert_eq!(name(&lines[4]), Some("packcolor"));
+    // 参数数量不符的语句保持原样
+    assert!(matches!(lines[5], LogicLine::Other(_)));
+    assert_eq!(name(&lines[6]), Some("end"));
+
+    // 区分读取与写入的值
+    assert_eq!(read.outputs(), [&Value::from("x")]);
+    assert_eq!(read.inputs(), [&Value::from("cell1"), &Value::from("0")]);
+    let LogicLine::Instruction(write) = &lines[3] else { unreachable!() };
+    assert!(write.outputs().is_empty());
+    assert_eq!(write.inputs().len(), 3);
+    let LogicLine::Instruction(pack) = &lines[4] else { unreachable!() };
+    assert_eq!(pack.outputs(), [&Value::from("c")]);
+    assert_eq!(pack.inputs().len(), 4);
+    // 子命令既不被读取也不被写入, 作用未知的参数视为既被读取也被写入
+    let LogicLine::Instruction(draw) = &lines[7] else { unreachable!() };
+    assert!(draw.outputs().is_empty());
+    assert_eq!(draw.inputs().len(), 3);
+    let LogicLine::Instruction(ucontrol) = &lines[8] else { unreachable!() };
+    assert_eq!(ucontrol.outputs(), [&Value::from("x"), &Value::from("y")]);
+    assert_eq!(ucontrol.inputs(), [&Value::from("x"), &Value::from("y")]);
+
+    // 编译结果与原本的相同
+    let compiled = CompileMeta::new()
+        .compile(lines)
+        .compile()
+        .unwrap();
+    assert_eq!(compiled, logic_lines);
+}
+
+#[test]
+fn fn_test() {
+    use lalrpop_util::ParseError;
+
+    let parser = TopLevelParser::new();
+
+    let ast = parse!(parser, r#"
+    fn Add[A B] {
+        op $ A + _1;
+    }
+    x = call Add[1 2];
+    take R = Add[3 4];
+    print R;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "jump 3 always 0 0",
+            "op add __fn_Add_res __fn_Add_0 __fn_Add_1",
+            "set @counter __fn_Add_ret",
+            "set __fn_Add_0 1",
+            "set __fn_Add_1 2",
+            "op add __fn_Add_ret @counter 1",
+            "jump 1 always 0 0",
+            "set x __fn_Add_res",
+            "op add __4 3 4",
+            "print __4",
+        ],
+    );
+
+    // 函数体中的标签在内联时会被重命名
+    let ast = parse!(parser, r#"
+    fn F {
+        :x
+        goto :x;
+    }
+    take F;
+    take call F;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "jump 3 always 0 0",
+            "jump 1 always 0 0",
+            "set @counter __fn_F_ret",
+            "jump 3 always 0 0",
+            "op add __fn_F_ret @counter 1",
+            "jump 1 always 0 0",
+        ],
+    );
+
+    // 在定义之前的调用无法检查参数数量
+    assert!(parse!(parser, "print call F[1]; fn F {}").is_ok());
+    assert!(matches!(
+        parse!(parser, "fn F[A] {} print call F[1 2];").unwrap_err(),
+        ParseError::User { error: Error {
+            err: Errors::FnArgsCountMismatch(_, 1, 2),
+            ..
+        } },
+    ));
+    assert!(matches!(
+        parse!(parser, "fn F {} fn F {}").unwrap_err(),
+        ParseError::User { error: Error { err: Errors::FnRedefined(_), .. } },
+    ));
+    // 可能被展开多次的函数定义
+    for src in [
+        "const C = (fn F {}); take C; take C;",
+        "const for i in 0..2 { fn F {} }",
+        "fn G { fn F {} }",
+    ] {
+        assert!(matches!(
+            parse!(parser, src).unwrap_err(),
+            ParseError::User { error: Error { err: Errors::FnInConst(_), .. } },
+        ), "{src}");
+    }
+
+    // 使用setres时, 最终的句柄被写入返回值变量
+    let ast = parse!(parser, r#"
+    fn Add[A B] { setres (op $ A + B;); }
+    print call Add[1 2];
+    fn Id[A] { setres A; }
+    print call Id[3];
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "jump 4 always 0 0",
+            "op add __2 __fn_Add_0 __fn_Add_1",
+            "set __fn_Add_res __2",
+            "set @counter __fn_Add_ret",
+            "set __fn_Add_0 1",
+            "set __fn_Add_1 2",
+            "op add __fn_Add_ret @counter 1",
+            "jump 1 always 0 0",
+            "print __fn_Add_res",
+            "jump 12 always 0 0",
+            "set __fn_Id_res __fn_Id_0",
+            "set @counter __fn_Id_ret",
+            "set __fn_Id_0 3",
+            "op add __fn_Id_ret @counter 1",
+            "jump 10 always 0 0",
+            "print __fn_Id_res",
+        ],
+    );
+
+    // 之后的参数中嵌套调用同一函数时, 之前的参数不会被覆盖
+    let ast = parse!(parser, r#"
+    fn Sub[A B] { op $ A - B; }
+    r = call Sub[a call Sub[b 3]];
+    r = call Sub[100 call Sub[b 3]];
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "jump 3 always 0 0",
+            "op sub __fn_Sub_res __fn_Sub_0 __fn_Sub_1",
+            "set @counter __fn_Sub_ret",
+            "set __2 a",
+            "set __fn_Sub_0 b",
+            "set __fn_Sub_1 3",
+            "op add __fn_Sub_ret @counter 1",
+            "jump 1 always 0 0",
+            "set __fn_Sub_0 __2",
+            "set __fn_Sub_1 __fn_Sub_res",
+            "op add __fn_Sub_ret @counter 1",
+            "jump 1 always 0 0",
+            "set r __fn_Sub_res",
+            "set __fn_Sub_0 b",
+            "set __fn_Sub_1 3",
+            "op add __fn_Sub_ret @counter 1",
+            "jump 1 always 0 0",
+            "set __fn_Sub_0 100",
+            "set __fn_Sub_1 __fn_Sub_res",
+            "op add __fn_Sub_ret @counter 1",
+            "jump 1 always 0 0",
+            "set r __fn_Sub_res",
+        ],
+    );
+}
+
+#[test]
+fn array_test() {
+    let parser = TopLevelParser::new();
+
+    let ast = parse!(parser, r#"
+    array A[4] = cell1[8];
+    const N = 2;
+    array B[N] = bank1;
+    array! C[3] = cell2[i];
+    x = A[1];
+    A[3] = x;
+    y = B[j];
+    op B[1] y + 1;
+    C[k] = C[1];
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "read __1 cell1 9",
+            "set x __1",
+            "write x cell1 11",
+            "read __3 bank1 j",
+            "set y __3",
+            "op add __4 y 1",
+            "write __4 bank1 1",
+            "op add __7 i 1",
+            "read __6 cell2 __7",
+            "jump 0 lessThan k 0",
+            "jump 0 greaterThanEq k 3",
+            "op add __8 i k",
+            "write __6 cell2 __8",
+        ],
+    );
+
+    // 在运行时检查下标时, 越界读取到的是`null`
+    let ast = parse!(parser, r#"
+    array! A[4] = cell1;
+    x = A[i];
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "set __1 null",
+            "jump 4 lessThan i 0",
+            "jump 4 greaterThanEq i 4",
+            "read __1 cell1 i",
+            "set x __1",
+        ],
+    );
+
+    for (src, err) in [
+        ("array A[4] = cell1; print A[4];", CompileError::ArrayIndexOutOfBounds("4".into(), 4)),
+        ("array A[4] = cell1; A[-1] = 2;", CompileError::ArrayIndexOutOfBounds("-1".into(), 4)),
+        ("array A[4] = cell1; print A[1.5];", CompileError::ArrayIndexOutOfBounds("1.5".into(), 4)),
+        ("array A[n] = cell1; print A[0];", CompileError::ArrayLenNotConst("n".into())),
+        ("array A[4] = cell1; print A[1 2];", CompileError::ArrayIndexCount(2)),
+    ] {
+        let meta = CompileMeta::new()
+            .compile_res_self(parse!(parser, src).unwrap());
+        assert_eq!(meta.errors(), [err], "{src}");
+    }
+}
+
+#[test]
+fn record_test() {
+    use lalrpop_util::ParseError;
+
+    let parser = TopLevelParser::new();
+
+    let ast = parse!(parser, r#"
+    record Vec2[x y];
+    record Pos: Vec2 = cell1[4];
+    record Vel[x y] = cell1;
+    record Tmp: Vec2 = bank1[p];
+    Vel.x = 3;
+    op Pos.x Pos.x + Vel.y;
+    Tmp = Pos;
+    print Vec2 Vec2.y Pos Tmp.y;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "write 3 cell1 0",
+            "read __1 cell1 4",
+            "read __2 cell1 1",
+            "op add __0 __1 __2",
+            "write __0 cell1 4",
+            "read __3 cell1 4",
+            "read __4 cell1 5",
+            "write __3 bank1 p",
+            "op add __5 p 1",
+            "write __4 bank1 __5",
+            "print 2",
+            "print 1",
+            "print 4",
+            "op add __7 p 1",
+            "read __6 bank1 __7",
+            "print __6",
+        ],
+    );
+
+    for (src, err) in [
+        ("record A[x] = cell1; print A.y;", CompileError::RecordFieldNotFound("y".into())),
+        ("record A[x]; A.x = 1;", CompileError::RecordNotPlaced("x".into())),
+        (
+            "record A[x] = cell1; record B[y] = cell1[1]; A = B;",
+            CompileError::RecordLayoutMismatch(vec!["x".into()], vec!["y".into()]),
+        ),
+    ] {
+        let meta = CompileMeta::new()
+            .compile_res_self(parse!(parser, src).unwrap());
+        assert_eq!(meta.errors(), [err], "{src}");
+    }
+
+    assert!(matches!(
+        parse!(parser, "record A: B = cell1;").unwrap_err(),
+        ParseError::User { error: Error { err: Errors::RecordLayoutUndefined(_), .. } },
+    ));
+    assert!(matches!(
+        parse!(parser, "record A[x y x];").unwrap_err(),
+        ParseError::User { error: Error { err: Errors::RecordFieldRepeated(_), .. } },
+    ));
+}
+
+#[test]
+fn const_for_test() {
+    let parser = TopLevelParser::new();
+
+    let ast = parse!(parser, r#"
+    const N = 3;
+    const for i in 1..N {
+        print i;
+        const X = (op $ i * 2;);
+        print X;
+    }
+    const for C in [cell1 bank1] {
+        read v C 0;
+    }
+    const for i in 2..1 {
+        print i;
+    }
+    print i X;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "print 1",
+            "print 2",
+            "print 2",
+            "print 4",
+            "read v cell1 0",
+            "read v bank1 0",
+            "print i",
+            "print X",
+        ],
+    );
+
+    // 每次迭代中循环体的标签都会被重命名
+    let ast = parse!(parser, r#"
+    const for i in 0..2 {
+        while x < i {
+            op x x + 1;
+        }
+    }
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "jump 3 greaterThanEq x 0",
+            "op add x x 1",
+            "jump 1 lessThan x 0",
+            "jump 0 greaterThanEq x 1",
+            "op add x x 1",
+            "jump 4 lessThan x 1",
+        ],
+    );
+
+    for (src, err) in [
+        ("const for i in 0..n {}", CompileError::ConstForRangeNotConst("n".into())),
+        ("const for i in 0.5..2 {}", CompileError::ConstForRangeNotConst("0.5".into())),
+        (
+            "const for i in 0..100000000 { print i; }",
+            CompileError::ConstForTooManyIterations(100000000, 10000),
+        ),
+    ] {
+        let meta = CompileMeta::new()
+            .compile_res_self(parse!(parser, src).unwrap());
+        assert_eq!(meta.errors(), [err], "{src}");
+    }
+
+    // 最大迭代次数可以修改, 反向的范围没有迭代
+    let mut meta = CompileMeta::new();
+    meta.set_const_for_max_iterations(2);
+    let meta = meta.compile_res_self(parse!(parser, r#"
+    const for i in 0..2 { print i; }
+    const for i in 3..0 { print i; }
+    const for i in 0..3 { print i; }
+    "#).unwrap());
+    assert_eq!(meta.errors(), [CompileError::ConstForTooManyIterations(3, 2)]);
+}
+
+#[test]
+fn for_test() {
+    let parser = TopLevelParser::new();
+
+    let ast = parse!(parser, r#"
+    for i = 0; i < n; op i i + 1; {
+        if i == 3 { continue; }
+        print i;
+    }
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "set i 0",
+            "jump 0 greaterThanEq i n",
+            "jump 4 notEqual i 3",
+            "jump 5 always 0 0",
+            "print i",
+            "op add i i 1",
+            "jump 2 lessThan i n",
+        ],
+    );
+
+    let ast = parse!(parser, r#"
+    for i in 1..(op $ n + 1;) {
+        break i > 5;
+        print i;
+    }
+    end;
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            "set i 1",
+            "op add __0 n 1",
+            "jump 8 greaterThanEq i __0",
+            "jump 8 greaterThan i 5",
+            "print i",
+            "op add i i 1",
+            "op add __1 n 1",
+            "jump 3 lessThan i __1",
+            "end",
+        ],
+    );
+}
+
+#[test]
+fn const_if_test() {
+    let parser = TopLevelParser::new();
+
+    let ast = parse!(parser, r#"
+    const N = 3;
+    const if N > 2 {
+        print "big";
+    } elif N > 1 {
+        print "mid";
+    } else {
+        print "small";
+    }
+    const if N < 2 {
+        print "small";
+    } elif N < 4 && N != 0 {
+        const Mid = N;
+    }
+    print Mid;
+    const if N == 0 {
+        print "zero";
+    }
+    const if N == 0 || N > 1 {
+        print "nonzero";
+    }
+    "#).unwrap();
+    assert_eq!(
+        CompileMeta::new().compile(ast).compile().unwrap(),
+        vec![
+            r#"print "big""#,
+            "print 3",
+            r#"print "nonzero""#,
+        ],
+    );
+
+    // 需要求出的条件无法在编译期求出时报错, 且不编译任何分支
+    for src in [
+        "const if x > 1 { print 1; } else { print 2; }",
+        "const if 0 > 1 { print 1; } elif x { print 2; }",
+        "const if 1 && x > 1 { print 1; }",
+    ] {
+        let mut meta = CompileMeta::new()
+            .compile_res_self(parse!(parser, src).unwrap());
+        assert_eq!(meta.errors(), [CompileError::ConstIfNotConst], "{src}");
+        assert_eq!(meta.tag_codes_mut().compile().unwrap(), Vec::<String>::new(), "{src}");
+    }
+
+    // 被短路的一侧无需可以求出
+    let meta = CompileMeta::new().compile_res_self(parse!(parser, r#"
+    const if 0 && x > 1 { print 1; }
+    const if 1 || x > 1 { print 2; }
+    "#).unwrap());
+    assert_eq!(meta.errors(), []);
+}
@@ -0,0 +1,163 @@
+//! 绑定到内存元或内存库中一段连续区域的数组
+//!
+//! 数组使用`array Name[LEN] = cell1[OFFSET];`声明, 它是一个值为[`Array`]的const.
+//! 使用`Name[i]`读取元素, 将`Name[i]`作为`set`或`op`的结果时写入元素
+
+use tag_code::TagLine;
+use crate::{
+    Args,
+    CmpTree,
+    CompileError,
+    Compile,
+    CompileMeta,
+    Goto,
+    JumpCmp,
+    LogicLine,
+    Op,
+    TakeHandle,
+    Value,
+    Var,
+};
+
+/// 绑定到内存元或内存库中一段连续区域的数组
+#[derive(Debug, PartialEq, Clone)]
+pub struct Array {
+    /// 被读写的内存元或内存库
+    pub cell: Value,
+    /// 数组在内存中的起始位置
+    pub offset: Value,
+    /// 数组的长度, 需要在编译期可以求出
+    pub len: Value,
+    /// 是否在运行时检查下标, 越界时读取到`null`, 写入被忽略
+    pub guard: bool,
+}
+impl Array {
+    /// 以当前的参数作为下标读取一个元素, 也就是`Name[i]`被take时
+    pub(crate) fn take_handle(self, meta: &mut CompileMeta) -> Var {
+        let index = match meta.get_env_args() {
+            [index] => index.clone(),
+            args => {
+                let count = args.len();
+                meta.report_error(CompileError::ArrayIndexCount(count), []);
+                return "__".into();
+            },
+        };
+        let result = meta.get_tmp_var();
+        self.access(meta, index.into(), |cell, addr| {
+            vec!["read".into(), result.clone(), cell, addr]
+        }, Some(&result));
+        result
+    }
+
+    /// 将`value`的句柄写入下标为`index`的元素
+    pub(crate) fn write(self, meta: &mut CompileMeta, index: Value, value: Var) {
+        self.access(meta, index, |cell, addr| {
+            vec!["write".into(), value, cell, addr]
+        }, None);
+    }
+
+    /// 求出元素的地址并生成读写语句
+    ///
+    /// 常量下标会在编译期检查是否越界, 否则在需要时生成运行时检查,
+    /// 检查失败时跳过读写, 并将`result`设置为`null`
+    fn access(
+        self,
+        meta: &mut CompileMeta,
+        index: Value,
+        f: impl FnOnce(Var, Var) -> Vec<Var>,
+        result: Option<&Var>,
+    ) {
+        let Some(len) = self.len.try_eval_const_num(meta)
+            .map(|(len, _)| len)
+            .filter(|len| len.fract() == 0.0 && *len >= 0.0)
+        else {
+            let len = match self.len {
+                Value::Var(len) | Value::ReprVar(len) => len,
+                len => format!("{len:?}"),
+            };
+            meta.report_error(CompileError::ArrayLenNotConst(len), []);
+            return;
+        };
+        let const_index = index.try_eval_const_num(meta).map(|(i, _)| i);
+        let (index, guard) = match const_index {
+            Some(i) if i.fract() != 0.0 || !(0.0..len).contains(&i) => {
+                let index = Value::num_to_var(i);
+                let len = len as usize;
+                meta.report_error(
+                    CompileError::ArrayIndexOutOfBounds(index, len),
+                    [],
+                );
+                return;
+            },
+            Some(i) => (Value::num_to_var(i), false),
+            None => (index.take_handle(meta), self.guard),
+        };
+
+        let end = guard.then(|| {
+            let end = meta.get_tmp_tag();
+            let out_of_bounds = CmpTree::new_ors([
+                JumpCmp::LessThan(index.clone().into(), "0".into()).into(),
+                JumpCmp::GreaterThanEq(
+                    index.clone().into(),
+                    Value::num_to_var(len).into(),
+                ).into(),
+            ]).unwrap();
+            if let Some(result) = result {
+                LogicLine::Other(Args::Normal(vec![
+                    Value::ReprVar("set".into()),
+                    Value::ReprVar(result.clone()),
+                    Value::ReprVar("null".into()),
+                ])).compile(meta);
+            }
+            Goto(end.clone(), out_of_bounds).compile(meta);
+            end
+        });
+
+        let addr = match (
+            self.offset.try_eval_const_num(meta),
+            const_index,
+        ) {
+            (Some((offset, _)), Some(i)) => Value::num_to_var(offset + i),
+            (Some((0.0, _)), None) => index,
+            _ => {
+                let addr = meta.get_tmp_var();
+                Op::Add(
+                    Value::ReprVar(addr.clone()),
+                    self.offset,
+                    Value::ReprVar(index),
+                ).compile(meta);
+                addr
+            },
+        };
+        let cell = self.cell.take_handle(meta);
+        let line = f(cell, addr).join(" ");
+        meta.push(TagLine::Line(line.into()));
+
+        if let Some(end) = end {
+            let tag = meta.get_tag(end);
+            meta.push(TagLine::TagDown(tag));
+        }
+    }
+}
+
+impl Value {
+    /// 如果是对一个数组元素的访问`Name[i]`, 返回数组与下标
+    pub(crate) fn as_array_element(&self, meta: &CompileMeta) -> Option<(Array, Value)> {
+        let Value::DExp(dexp) = self else { return None };
+        let [
+            LogicLine::SetArgs(Args::Normal(args)),
+            LogicLine::SetResultHandle(Value::Var(name)),
+        ] = &dexp.lines()[..] else { return None };
+        let [index] = &args[..] else { return None };
+        match &meta.get_const_value(name)?.value {
+            Value::Array(array) => Some(((**array).clone(), index.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl From<Array> for Value {
+    fn from(array: Array) -> Self {
+        Self::Array(array.into())
+    }
+}
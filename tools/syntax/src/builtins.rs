@@ -1,10 +1,10 @@
-use std::process;
-
 use crate::*;
 
 #[derive(Clone)]
 pub struct BuiltinFunc {
     name: &'static str,
+    /// 参数的名称, 仅用于展示
+    args: &'static [&'static str],
     func: fn(&Self, &mut CompileMeta) -> Var,
 }
 impl Debug for BuiltinFunc {
@@ -33,6 +33,10 @@ impl BuiltinFunc {
         self.name
     }
 
+    pub fn args(&self) -> &'static [&'static str] {
+        self.args
+    }
+
     pub fn func(&self) -> fn(&Self, &mut CompileMeta) -> Var {
         self.func
     }
@@ -42,6 +46,41 @@ impl BuiltinFunc {
     }
 }
 
+/// 内建函数执行失败时的错误
+#[derive(Debug, PartialEq, Clone)]
+pub enum BuiltinError {
+    ArgsCount(usize),
+    ValueType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    NotAString(Var),
+    /// 无法被解析的参数, 包含参数名与值
+    InvalidValue(&'static str, Var),
+}
+impl BuiltinError {
+    /// 失败时设置的退出码
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::ValueType { .. } => 1,
+            _ => 2,
+        }
+    }
+}
+impl Display for BuiltinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::ArgsCount(argc) => msg!(BuiltinArgsCount, argc),
+            Self::ValueType { expected, found } =>
+                msg!(BuiltinValueType, expected, found),
+            Self::NotAString(var) => msg!(BuiltinNotAString, var),
+            Self::InvalidValue(name, var) =>
+                msg!(BuiltinInvalidValue, name, var),
+        };
+        f.write_str(&msg)
+    }
+}
+
 macro_rules! mutil {
     (@ignore($($i:tt)*) $($t:tt)*) => {
         $($t)*
@@ -54,6 +93,11 @@ macro_rules! mutil {
     };
 }
 
+macro_rules! arg_name {
+    ($var:ident : $taked_var:ident) => { stringify!($taked_var) };
+    ($var:ident) => { stringify!($var) };
+}
+
 macro_rules! build_builtin_funcs {
     {
         $(
@@ -68,14 +112,11 @@ macro_rules! build_builtin_funcs {
             fn $func_name(this: &BuiltinFunc, $meta: &mut CompileMeta) -> Var
             {
                 fn f($meta: &mut CompileMeta)
-                -> Result<Var, (u8, String)> {
+                -> Result<Var, BuiltinError> {
                     $(
                         let args = $meta.get_env_args();
                         let [$($var),*] = args else {
-                            return Err((2, format!(
-                                "args count error: argc := {}",
-                                args.len()
-                            )));
+                            return Err(BuiltinError::ArgsCount(args.len()));
                         };
                         $($(
                             let $taked_var = $meta.get_const_value($var)
@@ -89,16 +130,14 @@ macro_rules! build_builtin_funcs {
                         $meta.set_last_builtin_exit_code(0);
                         var
                     },
-                    Err((code, e)) => {
-                        $meta.log_err(format_args!(
-                            "\
-                            Builtin Function Error:\n\
-                            name: {}, argc: {}\n\
-                            exit_code: {code}\n\
-                            msg: {}\
-                            ",
+                    Err(e) => {
+                        let code = e.exit_code();
+                        let argc = 0$($(+mutil!(@ignore($var) 1))*)?;
+                        $meta.log_err(msg!(
+                            BuiltinError,
                             this.name(),
-                            0$($(+mutil!(@ignore($var) 1))*)?,
+                            argc,
+                            code,
                             e,
                         ));
                         $meta.set_last_builtin_exit_code(code);
@@ -110,6 +149,7 @@ macro_rules! build_builtin_funcs {
         vec![$(
             BuiltinFunc {
                 name: stringify!($vfunc_name),
+                args: &[$($(arg_name!($var $(: $taked_var)?)),*)?],
                 func: $func_name,
             }
         ),*]
@@ -127,6 +167,8 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
             Value::Cmper(_) => "cmper",
             Value::Binder => "binder",
             Value::BuiltinFunc(_) => "builtinfunc",
+            Value::Array(_) => "array",
+            Value::Record(_) => "record",
         }
     }
     macro_rules! check_type {
@@ -135,14 +177,10 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
             if let $pat = value {
                 $body
             } else {
-                return Err((1, format!(
-                    "value type error:\n\
-                    expected: {}\n\
-                    found: {}\
-                    ",
-                    $type,
-                    value_type(value),
-                )))
+                return Err(BuiltinError::ValueType {
+                    expected: $type,
+                    found: value_type(value),
+                })
             }
         }};
     }
@@ -169,10 +207,10 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
             check_type!("var" Value::Var(a) = a.value() => {
                 check_type!("var" Value::Var(b) = b.value() => {
                     if !Value::is_string(a) {
-                        return Err((2, format!("{a} is not a string")));
+                        return Err(BuiltinError::NotAString(a.clone()));
                     }
                     if !Value::is_string(b) {
-                        return Err((2, format!("{b} is not a string")));
+                        return Err(BuiltinError::NotAString(b.clone()));
                     }
                     Ok([&a[..a.len()-1], &b[1..]].concat())
                 })
@@ -253,19 +291,18 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
                 let num_code = match code.parse() {
                     Ok(code) => code,
                     Err(e) => {
-                        meta.log_err(format!(
-                            "Invalid exit code: {code},\nerr: {e}",
-                        ));
+                        meta.log_err(msg!(BuiltinInvalidExitCode, code, e));
                         128
                     },
                 };
-                process::exit(num_code)
+                meta.report_error(CompileError::Exit(num_code), []);
+                Ok("__".into())
             })
         }
 
         /// 以Debug形式显示一个值
         fn debug:Debug(meta) [v:value] {
-            meta.log_info(format!("Value Debug:\n{:#?}", value));
+            meta.log_info(msg!(BuiltinValueDebug, format!("{value:#?}")));
             Ok("__".into())
         }
 
@@ -277,10 +314,10 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
             check_type!("var" Value::Var(start) = start.value() => {
                 check_type!("var" Value::Var(end) = end.value() => {
                     let Ok(start) = start.parse::<usize>() else {
-                        return Err((2, format!("Invalid start value: {start}")))
+                        return Err(BuiltinError::InvalidValue("start", start.clone()))
                     };
                     let Ok(end) = end.parse::<usize>() else {
-                        return Err((2, format!("Invalid end value: {end}")))
+                        return Err(BuiltinError::InvalidValue("end", end.clone()))
                     };
                     meta.slice_env_second_args((start, end));
                     Ok("__".into())
@@ -291,7 +328,7 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
         fn args_handle:ArgsHandle(meta) [i:idx] {
             check_type!("var" Value::Var(idx) = idx.value() => {
                 let Ok(idx) = idx.parse::<usize>() else {
-                    return Err((2, format!("Invalid index value: {idx}")))
+                    return Err(BuiltinError::InvalidValue("index", idx.clone()))
                 };
                 Ok(meta.get_env_second_args()
                     .get(idx)
@@ -312,11 +349,8 @@ pub fn build_builtins() -> Vec<BuiltinFunc> {
 
         fn set_max_expand_depth:SetMaxExpandDepth(meta) [d:depth] {
             check_type!("var" Value::Var(depth) = depth.value() => {
-                let depth: usize = match depth.parse() {
-                    Ok(n) => n,
-                    Err(e) => {
-                        return Err((2, e.to_string()))
-                    },
+                let Ok(depth) = depth.parse::<usize>() else {
+                    return Err(BuiltinError::InvalidValue("depth", depth.clone()))
                 };
                 meta.set_const_expand_max_depth(depth);
                 Ok("__".into())
@@ -0,0 +1,186 @@
+//! 在[`TagCodes`]的直线区域中进行常量传播
+//!
+//! 区域从被跳转到的行或是跳转之后开始, 在区域内记录被设置为已知数字的变量,
+//! 并将其代入之后的`set`, `op`与跳转条件中,
+//! 运算成员全部已知的`op`会被计算为`set`, 条件已知的跳转会被化简
+
+use std::collections::HashMap;
+
+use tag_code::{
+    mdt_logic_split,
+    CompileTagCodesError,
+    InstructionInfo,
+    Jump,
+    TagCodes,
+    TagLine,
+};
+use var_utils::AsVarType;
+
+use crate::{FromMdtArgs, JumpCmp, Op, Value, Var};
+
+/// 对一行进行的改写
+enum Rewrite {
+    Keep,
+    Replace(String),
+    Remove,
+}
+
+/// 对`tag_codes`进行常量传播, 返回改写的行数
+///
+/// 会调用[`build_tagdown`]来改变源码,
+/// 如果存在写入`@counter`等无法静态得知目标的跳转, 那么不会进行任何改写
+///
+/// [`build_tagdown`]: TagCodes::build_tagdown
+pub fn propagate_consts(
+    tag_codes: &mut TagCodes,
+) -> Result<usize, CompileTagCodesError> {
+    tag_codes.build_tagdown().map_err(|(line, tag)| {
+        CompileTagCodesError::DuplicateTag(line, tag)
+    })?;
+    if tag_codes.iter().any(TagLine::is_dynamic_jump) {
+        return Ok(0);
+    }
+
+    let mut known: HashMap<Var, f64> = HashMap::new();
+    let mut count = 0;
+    let mut i = 0;
+    while i < tag_codes.len() {
+        let line = &tag_codes.lines()[i];
+        if line.tag().is_some() {
+            // 被跳转到的行可能从其它区域进入
+            known.clear();
+        }
+        let rewrite = match line {
+            TagLine::Jump(jump) => {
+                let rewrite = rewrite_jump(jump, &known);
+                known.clear();
+                rewrite
+            },
+            TagLine::Line(line) => rewrite_line(line, &mut known),
+            TagLine::TagDown(_) => unreachable!(),
+        };
+        match rewrite {
+            Rewrite::Keep => (),
+            Rewrite::Replace(new) => {
+                match &mut tag_codes.lines_mut()[i] {
+                    TagLine::Jump(jump) => jump.1 = new,
+                    TagLine::Line(line) => **line = new,
+                    TagLine::TagDown(_) => unreachable!(),
+                }
+                count += 1;
+            },
+            Rewrite::Remove => {
+                tag_codes.remove_line(i);
+                count += 1;
+                continue;
+            },
+        }
+        i += 1;
+    }
+    Ok(count)
+}
+
+/// 获取一个参数的已知数字值
+///
+/// 仅有有限的数字视为已知, `null`会被视为NaN,
+/// 但是在游戏中它与数字的比较并不同于NaN, 例如`null == null`成立
+fn known_num(arg: &str, known: &HashMap<Var, f64>) -> Option<f64> {
+    known.get(arg).copied()
+        .or_else(|| arg.as_var_type().as_number().copied())
+        .filter(|num| num.is_finite())
+}
+
+/// 将参数中已知的变量替换为其值, 没有进行替换时返回[`None`]
+fn substitute(
+    args: &[&str],
+    inputs: impl IntoIterator<Item = usize>,
+    known: &HashMap<Var, f64>,
+) -> Option<String> {
+    let mut args = args.iter()
+        .map(|&arg| arg.to_owned())
+        .collect::<Vec<_>>();
+    let mut changed = false;
+    for i in inputs {
+        if let Some(&num) = known.get(&args[i]) {
+            args[i] = Value::num_to_var(num);
+            changed = true;
+        }
+    }
+    changed.then(|| args.join(" "))
+}
+
+fn rewrite_jump(jump: &Jump, known: &HashMap<Var, f64>) -> Rewrite {
+    let Ok(args) = mdt_logic_split(&jump.1) else { return Rewrite::Keep };
+    let Ok(cmp) = JumpCmp::from_mdt_args(&args) else { return Rewrite::Keep };
+    if cmp == JumpCmp::Always {
+        return Rewrite::Keep;
+    }
+    let nums = (known_num(args[1], known), known_num(args[2], known));
+    match nums {
+        (Some(a), Some(b)) if cmp.eval_num(a, b) => {
+            Rewrite::Replace(Jump::new_always(jump.0).1)
+        },
+        (Some(_), Some(_)) => Rewrite::Remove,
+        _ => substitute(&args, 1..3, known)
+            .map_or(Rewrite::Keep, Rewrite::Replace),
+    }
+}
+
+fn rewrite_line(line: &str, known: &mut HashMap<Var, f64>) -> Rewrite {
+    let Ok(args) = mdt_logic_split(line) else {
+        known.clear();
+        return Rewrite::Keep;
+    };
+    match args[..] {
+        ["set", var, value] => {
+            let num = known_num(value, known);
+            let rewrite = substitute(&args, [2], known)
+                .map_or(Rewrite::Keep, Rewrite::Replace);
+            match num {
+                Some(num) => known.insert(var.into(), num),
+                None => known.remove(var),
+            };
+            rewrite
+        },
+        ["op", _, result, a, b] => {
+            let Ok(op) = Op::from_mdt_args(&args[1..]) else {
+                known.remove(result);
+                return Rewrite::Keep;
+            };
+            let b = match op.get_info().arg2 {
+                Some(_) => known_num(b, known),
+                None => Some(0.0),
+            };
+            let num = known_num(a, known).zip(b)
+                .and_then(|(a, b)| op.eval_num(a, b))
+                .filter(|num| num.is_finite());
+            let rewrite = match num {
+                Some(num) => {
+                    let value = Value::num_to_var(num);
+                    Rewrite::Replace(format!("set {result} {value}"))
+                },
+                None => substitute(&args, 3..5, known)
+                    .map_or(Rewrite::Keep, Rewrite::Replace),
+            };
+            match num {
+                Some(num) => known.insert(result.into(), num),
+                None => known.remove(result),
+            };
+            rewrite
+        },
+        ["end" | "stop", ..] => {
+            known.clear();
+            Rewrite::Keep
+        },
+        // 只会读取参数的语句
+        [name, ..] if InstructionInfo::find(name)
+            .is_some_and(InstructionInfo::is_read_only)
+            => Rewrite::Keep,
+        _ => {
+            for arg in args {
+                known.remove(arg);
+            }
+            Rewrite::Keep
+        },
+    }
+}
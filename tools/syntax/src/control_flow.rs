@@ -0,0 +1,296 @@
+//! 从逻辑代码构建出的扁平的跳转与标记中重建控制流
+//!
+//! 识别与语法糖编译结果相同的结构, 并将其重建为对应的[`ControlFlow`]与[`Select`],
+//! 重建得到的语句编译后的布局与原本的跳转相同,
+//! 所以结构内部被其它跳转所使用的标记可以原样保留
+//!
+//! 只有被跳转一次的标记才会被结构所使用
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    CmpTree,
+    ControlFlow,
+    Expand,
+    Goto,
+    JumpCmp,
+    LogicLine,
+    Op,
+    Select,
+    Value,
+    Var,
+    COUNTER,
+};
+
+/// 将扁平的语句中可以识别的跳转重建为`if`, `while`, `do-while`, `gwhile`与`select`
+pub fn rebuild_control_flow(lines: &Expand) -> Expand {
+    let mut refs: HashMap<Var, usize> = HashMap::new();
+    for line in lines.iter() {
+        if let LogicLine::Goto(Goto(lab, _)) = line {
+            *refs.entry(lab.clone()).or_default() += 1;
+        }
+    }
+    let mut rebuilder = Rebuilder { refs, used: HashSet::new() };
+    Expand(rebuilder.rebuild(lines))
+}
+
+struct Rebuilder {
+    /// 每个标记被跳转的次数
+    refs: HashMap<Var, usize>,
+    /// 已经被结构所使用的标记
+    used: HashSet<Var>,
+}
+impl Rebuilder {
+    fn rebuild(&mut self, lines: &[LogicLine]) -> Vec<LogicLine> {
+        let mut res = Vec::with_capacity(lines.len());
+        let mut i = 0;
+        while i < lines.len() {
+            if let Some((line, next)) = self.try_rebuild(lines, i) {
+                res.push(line);
+                i = next;
+                continue;
+            }
+            match &lines[i] {
+                LogicLine::Label(lab) if self.used.contains(lab) => (),
+                line => res.push(line.clone()),
+            }
+            i += 1;
+        }
+        res
+    }
+
+    /// 尝试将从`i`开始的语句重建为一个结构, 返回结构及其之后的位置
+    fn try_rebuild(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+    ) -> Option<(LogicLine, usize)> {
+        match &lines[i] {
+            LogicLine::Goto(Goto(lab, cmp)) => {
+                self.try_gwhile(lines, i, lab, cmp)
+                    .or_else(|| self.try_while(lines, i, lab, cmp))
+                    .or_else(|| self.try_if_else(lines, i, lab, cmp))
+                    .or_else(|| self.try_if(lines, i, lab, cmp))
+            },
+            LogicLine::Label(lab) => self.try_do_while(lines, i, lab),
+            LogicLine::Op(Op::Add(res, a, target))
+                if is_counter(res) && is_counter(a) =>
+            {
+                self.try_select(lines, i, target)
+            },
+            _ => None,
+        }
+    }
+
+    /// 是否是一个只被跳转一次且还未被使用的标记
+    fn is_single(&self, lab: &Var) -> bool {
+        self.refs.get(lab) == Some(&1) && !self.used.contains(lab)
+    }
+
+    /// 获取从`start`开始的连续的标记
+    fn labels_at(lines: &[LogicLine], start: usize) -> impl Iterator<Item = &Var> {
+        lines.get(start..)
+            .unwrap_or_default()
+            .iter()
+            .map_while(LogicLine::as_label)
+    }
+
+    /// 获取从`start`开始的连续的标记中`lab`的位置
+    fn label_at(lines: &[LogicLine], start: usize, lab: &Var) -> Option<usize> {
+        lines.get(start..)?
+            .iter()
+            .take_while(|line| line.is_label())
+            .position(|line| line.as_label() == Some(lab))
+            .map(|n| start + n)
+    }
+
+    /// 获取`lab`在`start`及之后的位置
+    fn find_label(lines: &[LogicLine], start: usize, lab: &Var) -> Option<usize> {
+        lines.get(start..)?
+            .iter()
+            .position(|line| line.as_label() == Some(lab))
+            .map(|n| start + n)
+    }
+
+    /// 获取跳转到`lab`的`Goto`在`start`及之后的位置
+    fn find_goto(lines: &[LogicLine], start: usize, lab: &Var) -> Option<usize> {
+        lines.get(start..)?
+            .iter()
+            .position(|line| {
+                matches!(line, LogicLine::Goto(Goto(to, _)) if to == lab)
+            })
+            .map(|n| start + n)
+    }
+
+    /// 标记这些标记已被使用, 并重建它们之间的语句
+    fn rebuild_body(
+        &mut self,
+        lines: &[LogicLine],
+        labs: &[&Var],
+    ) -> Expand {
+        self.used.extend(labs.iter().copied().cloned());
+        Expand(self.rebuild(lines))
+    }
+
+    /// `goto :to _; :head body :to goto :head cmp;`
+    fn try_gwhile(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+        to: &Var,
+        cmp: &CmpTree,
+    ) -> Option<(LogicLine, usize)> {
+        if !is_always(cmp) || !self.is_single(to) { return None }
+        let to_idx = Self::find_label(lines, i+1, to)?;
+        let (head, tail) = Self::labels_at(lines, i+1)
+            .filter(|head| self.is_single(head))
+            .find_map(|head| {
+                let tail = Self::find_goto(lines, to_idx, head)?;
+                lines[to_idx..tail].iter()
+                    .all(LogicLine::is_label)
+                    .then_some((head, tail))
+            })?;
+        let LogicLine::Goto(Goto(_, cmp)) = &lines[tail] else { unreachable!() };
+        let body = self.rebuild_body(&lines[i+1..tail], &[to, head]);
+        Some((ControlFlow::Gwhile(cmp.clone(), body).into(), tail + 1))
+    }
+
+    /// `goto :end !cmp; :head body goto :head cmp; :end`
+    fn try_while(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+        end: &Var,
+        rev_cmp: &CmpTree,
+    ) -> Option<(LogicLine, usize)> {
+        if !self.is_single(end) { return None }
+        let (head, tail) = Self::labels_at(lines, i+1)
+            .filter(|head| self.is_single(head))
+            .find_map(|head| {
+                let tail = Self::find_goto(lines, i+1, head)?;
+                Self::label_at(lines, tail + 1, end)?;
+                Some((head, tail))
+            })?;
+        let LogicLine::Goto(Goto(_, cmp)) = &lines[tail] else { unreachable!() };
+        if cmp.clone().reverse() != *rev_cmp {
+            return None;
+        }
+        let body = self.rebuild_body(&lines[i+1..tail], &[end, head]);
+        Some((ControlFlow::While(cmp.clone(), body).into(), tail + 1))
+    }
+
+    /// `goto :true cmp; else_body goto :end _; :true body :end`
+    fn try_if_else(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+        true_lab: &Var,
+        cmp: &CmpTree,
+    ) -> Option<(LogicLine, usize)> {
+        if !self.is_single(true_lab) { return None }
+        let true_idx = Self::find_label(lines, i+1, true_lab)?;
+        let skip = true_idx.checked_sub(1)
+            .filter(|&skip| skip > i)?;
+        let LogicLine::Goto(Goto(end, skip_cmp)) = &lines[skip] else {
+            return None;
+        };
+        if !is_always(skip_cmp) || !self.is_single(end) { return None }
+        let end_idx = Self::find_label(lines, true_idx + 1, end)?;
+        let else_body = self.rebuild_body(&lines[i+1..skip], &[true_lab, end]);
+        let body = self.rebuild_body(&lines[true_idx..end_idx], &[]);
+        let line = ControlFlow::If(cmp.clone(), body, Some(else_body));
+        Some((line.into(), end_idx))
+    }
+
+    /// `goto :end !cmp; body :end`
+    fn try_if(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+        end: &Var,
+        rev_cmp: &CmpTree,
+    ) -> Option<(LogicLine, usize)> {
+        if is_always(rev_cmp) || !self.is_single(end) { return None }
+        let end_idx = Self::find_label(lines, i+1, end)?;
+        let body = self.rebuild_body(&lines[i+1..end_idx], &[end]);
+        let line = ControlFlow::If(rev_cmp.clone().reverse(), body, None);
+        Some((line.into(), end_idx))
+    }
+
+    /// `:head body goto :head cmp;`
+    fn try_do_while(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+        head: &Var,
+    ) -> Option<(LogicLine, usize)> {
+        if !self.is_single(head) { return None }
+        let tail = Self::find_goto(lines, i+1, head)?;
+        let LogicLine::Goto(Goto(_, cmp)) = &lines[tail] else { unreachable!() };
+        let body = self.rebuild_body(&lines[i..tail], &[head]);
+        Some((ControlFlow::DoWhile(body, cmp.clone()).into(), tail + 1))
+    }
+
+    /// `op add @counter @counter target;`之后紧跟跳转到各`case`的跳转表
+    ///
+    /// 最后一个`case`到下一个标记为止, 因为`case`之间会继续向下执行,
+    /// 所以其划分并不会改变行为
+    fn try_select(
+        &mut self,
+        lines: &[LogicLine],
+        i: usize,
+        target: &Value,
+    ) -> Option<(LogicLine, usize)> {
+        let table = lines[i+1..].iter()
+            .map_while(|line| match line {
+                LogicLine::Goto(Goto(lab, cmp)) if is_always(cmp) => Some(lab),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if table.len() < 2
+            || table.iter().any(|lab| !self.is_single(lab))
+        {
+            return None;
+        }
+        let mut starts = Vec::with_capacity(table.len());
+        let mut start = i + 1 + table.len();
+        for &lab in &table {
+            start = match starts.last() {
+                None => Self::label_at(lines, start, lab)?,
+                Some(_) => Self::find_label(lines, start, lab)?,
+            };
+            starts.push(start);
+            start += 1;
+        }
+        let end = lines[start..].iter()
+            .position(LogicLine::is_label)
+            .map_or(lines.len(), |n| start + n);
+        let bounds = starts.iter().copied()
+            .zip(starts[1..].iter().copied().chain([end]))
+            .collect::<Vec<_>>();
+
+        self.used.extend(table.into_iter().cloned());
+        let cases = bounds.into_iter()
+            .map(|(start, end)| {
+                let case = self.rebuild(&lines[start..end]);
+                match <[_; 1]>::try_from(case) {
+                    Ok([line]) => line,
+                    Err(case) => Expand(case).into(),
+                }
+            })
+            .collect::<Vec<_>>();
+        let line = Select(target.clone(), Expand(cases));
+        Some((line.into(), end))
+    }
+}
+
+fn is_counter(value: &Value) -> bool {
+    match value {
+        Value::Var(var) | Value::ReprVar(var) => var == COUNTER,
+        _ => false,
+    }
+}
+
+fn is_always(cmp: &CmpTree) -> bool {
+    matches!(cmp, CmpTree::Atom(JumpCmp::Always))
+}
@@ -0,0 +1,93 @@
+//! 参数已被区分的已知逻辑语句
+//!
+//! 从逻辑代码构建时, 参数数量正确的已知语句将被构建为[`Instruction`],
+//! 各参数的作用来自[`InstructionInfo`], 以便于分析其读取与写入了哪些值
+
+use tag_code::{ArgRole, InstructionInfo};
+
+use crate::{
+    Args,
+    Compile,
+    CompileMeta,
+    LogicLine,
+    Value,
+};
+
+/// 一条参数已被区分的已知逻辑语句
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    info: &'static InstructionInfo,
+    args: Vec<Value>,
+}
+impl Instruction {
+    /// 语句名
+    pub fn name(&self) -> &'static str {
+        self.info.name
+    }
+
+    /// 该语句的参数信息
+    pub fn info(&self) -> &'static InstructionInfo {
+        self.info
+    }
+
+    /// 从一行逻辑的各参数构建, 包括语句名
+    ///
+    /// 未知的语句或者参数没有通过[`InstructionInfo::check`]时返回[`None`]
+    ///
+    /// # Examples
+    /// ```
+    /// # use syntax::*;
+    /// let inst = Instruction::from_mdt_args(&["read", "x", "cell1", "0"]).unwrap();
+    /// assert_eq!(inst.name(), "read");
+    /// assert_eq!(inst.args(), ["x".into(), "cell1".into(), "0".into()]);
+    /// assert_eq!(Instruction::from_mdt_args(&["read", "x", "cell1"]), None);
+    /// ```
+    pub fn from_mdt_args(args: &[&str]) -> Option<Self> {
+        let (&name, args) = args.split_first()?;
+        let info = InstructionInfo::find(name)?;
+        info.check(args).ok()?;
+        let args = args.iter().copied().map(Value::from).collect();
+        Some(Self { info, args })
+    }
+
+    /// 除去语句名以外的各参数
+    pub fn args(&self) -> &[Value] {
+        &self.args
+    }
+
+    /// 各参数及其作用
+    pub fn arg_roles(&self) -> impl Iterator<Item = (&Value, ArgRole)> {
+        self.args.iter().enumerate().map(|(i, arg)| {
+            let role = self.info.args.get(i).copied();
+            (arg, role.unwrap_or(ArgRole::Unknown))
+        })
+    }
+
+    /// 被该语句写入的值, 包括作用未知的参数
+    pub fn outputs(&self) -> Vec<&Value> {
+        self.arg_roles()
+            .filter(|(_, role)| matches!(role, ArgRole::Output | ArgRole::Unknown))
+            .map(|(arg, _)| arg)
+            .collect()
+    }
+
+    /// 被该语句读取的值, 包括作用未知的参数
+    pub fn inputs(&self) -> Vec<&Value> {
+        self.arg_roles()
+            .filter(|(_, role)| matches!(role, ArgRole::Input | ArgRole::Unknown))
+            .map(|(arg, _)| arg)
+            .collect()
+    }
+
+    /// 转换为包括语句名的各参数
+    pub fn into_args(self) -> Vec<Value> {
+        let mut args = vec![Value::ReprVar(self.name().into())];
+        args.extend(self.args);
+        args
+    }
+}
+impl Compile for Instruction {
+    fn compile(self, meta: &mut CompileMeta) {
+        LogicLine::Other(Args::Normal(self.into_args())).compile(meta)
+    }
+}
@@ -1,4 +1,9 @@
+mod array;
+mod record;
 mod builtins;
+mod const_prop;
+mod control_flow;
+mod instruction;
 
 use std::{
     num::ParseIntError,
@@ -7,20 +12,32 @@ use std::{
         zip,
         repeat_with,
     },
-    process::exit,
     mem::{self, replace},
     fmt::{Display, Debug},
     convert::identity, borrow::Borrow, hash::Hash,
+    rc::Rc,
 };
-use builtins::{BuiltinFunc, build_builtins};
+pub use array::Array;
+pub use record::Record;
+pub use builtins::{BuiltinFunc, build_builtins};
+pub use const_prop::propagate_consts;
+pub use control_flow::rebuild_control_flow;
+pub use instruction::Instruction;
 use tag_code::{
+    check_instruction,
     Jump,
+    Origin,
     TagCodes,
     TagLine
 };
 use var_utils::{AsVarType,string_unescape};
 use tag_code::mdt_logic_split;
-use utils::counter::Counter;
+use utils::{
+    counter::Counter,
+    diagnostic::{Diagnostic, Source},
+    message::Message,
+    msg,
+};
 
 
 macro_rules! impl_enum_froms {
@@ -109,13 +126,237 @@ pub enum Errors {
     NotALiteralUInteger(String, ParseIntError),
     SetVarNoPatternValue(usize, usize),
     ArgsRepeatChunkByZero,
+    /// 当前解析环境没有设置导入器, 无法处理`import`
+    ImportUnsupported,
+    /// 读取被导入的文件失败, 包含文件路径与错误信息
+    ImportReadFailed(String, String),
+    /// 循环导入, 包含从首个重复文件开始的导入链
+    ImportCycle(Vec<String>),
+    /// 被导入的文件解析失败, 详细错误由导入器记录
+    ImportParseFailed(String),
+    /// 导入了不存在的标准库模块
+    ImportStdNotFound(String),
+    /// 重复定义的函数
+    FnRedefined(Var),
+    /// 在const或者其它函数之中定义的函数, 它可能被展开多次
+    FnInConst(Var),
+    /// 调用函数时传入的参数数量与定义不符, 包含函数名, 参数数量与传入数量
+    FnArgsCountMismatch(Var, usize, usize),
+    /// 记录中重复的字段
+    RecordFieldRepeated(Var),
+    /// 使用了未定义的记录布局
+    RecordLayoutUndefined(Var),
+    /// 测试属性有误
+    TestAttr(TestAttrErrorKind),
+}
+impl Errors {
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        match self {
+            Self::NotALiteralUInteger(..) => Message::NotALiteralUInteger,
+            Self::SetVarNoPatternValue(..) => Message::SetsCountMismatch,
+            Self::ArgsRepeatChunkByZero => Message::ArgsRepeatChunkByZero,
+            Self::ImportUnsupported => Message::ImportUnsupported,
+            Self::ImportReadFailed(..) => Message::ImportReadFailed,
+            Self::ImportCycle(_) => Message::ImportCycle,
+            Self::ImportParseFailed(_) => Message::ImportParseFailed,
+            Self::ImportStdNotFound(_) => Message::ImportStdNotFound,
+            Self::FnRedefined(_) => Message::FnRedefined,
+            Self::FnInConst(_) => Message::FnInConst,
+            Self::FnArgsCountMismatch(..) => Message::FnArgsCountMismatch,
+            Self::RecordFieldRepeated(_) => Message::RecordFieldRepeated,
+            Self::RecordLayoutUndefined(_) => Message::RecordLayoutUndefined,
+            Self::TestAttr(kind) => kind.message_kind(),
+        }
+    }
+}
+impl Display for Errors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::NotALiteralUInteger(str, err) =>
+                msg!(NotALiteralUInteger, format!("{str:?}"), err),
+            Self::SetVarNoPatternValue(var_count, val_count) =>
+                msg!(SetsCountMismatch, var_count, val_count),
+            Self::ArgsRepeatChunkByZero => msg!(ArgsRepeatChunkByZero),
+            Self::ImportUnsupported => msg!(ImportUnsupported),
+            Self::ImportReadFailed(path, err) =>
+                msg!(ImportReadFailed, format!("{path:?}"), err),
+            Self::ImportCycle(chain) => msg!(ImportCycle, chain.join(" -> ")),
+            Self::ImportParseFailed(path) =>
+                msg!(ImportParseFailed, format!("{path:?}")),
+            Self::ImportStdNotFound(name) =>
+                msg!(ImportStdNotFound, format!("{name:?}")),
+            Self::FnRedefined(name) => msg!(FnRedefined, name),
+            Self::FnInConst(name) => msg!(FnInConst, name),
+            Self::FnArgsCountMismatch(name, expected, found) =>
+                msg!(FnArgsCountMismatch, name, expected, found),
+            Self::RecordFieldRepeated(field) =>
+                msg!(RecordFieldRepeated, field),
+            Self::RecordLayoutUndefined(name) =>
+                msg!(RecordLayoutUndefined, name),
+            Self::TestAttr(kind) => return Display::fmt(kind, f),
+        };
+        f.write_str(&msg)
+    }
 }
 
-/// 带有错误前缀, 并且文本为红色的eprintln
-macro_rules! err {
-    ( $fmtter:expr $(, $args:expr)* $(,)? ) => {
-        eprintln!(concat!("\x1b[1;31m", "CompileError:\n", $fmtter, "\x1b[0m"), $($args),*);
-    };
+/// 测试属性中的错误, 测试属性的写法见`parser::find_test_blocks`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestAttrErrorKind {
+    /// 无法解析的属性
+    InvalidAttr(String),
+    /// 属性之后没有紧跟一个块
+    ExpectedBlock,
+    /// 断言没有对应的`#[test]`
+    AssertWithoutTest,
+    /// 块没有闭合
+    UnclosedBlock,
+}
+impl TestAttrErrorKind {
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        match self {
+            Self::InvalidAttr(_) => Message::TestAttrInvalid,
+            Self::ExpectedBlock => Message::TestAttrExpectedBlock,
+            Self::AssertWithoutTest => Message::TestAttrAssertWithoutTest,
+            Self::UnclosedBlock => Message::TestAttrUnclosedBlock,
+        }
+    }
+}
+impl Display for TestAttrErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::InvalidAttr(attr) =>
+                msg!(TestAttrInvalid, format!("{attr:?}")),
+            Self::ExpectedBlock => msg!(TestAttrExpectedBlock),
+            Self::AssertWithoutTest => msg!(TestAttrAssertWithoutTest),
+            Self::UnclosedBlock => msg!(TestAttrUnclosedBlock),
+        };
+        f.write_str(&msg)
+    }
+}
+
+/// 编译期间产生的错误
+#[derive(Debug, PartialEq, Clone)]
+pub enum CompileError {
+    /// 最终未被展开的cmper
+    UnexpandedCmper,
+    /// 在`DExp`的返回句柄处使用了值不为`Var`的const
+    DExpResultNotVar,
+    /// 在`DExp`的外部使用了只能在其内部使用的值
+    OutOfDExp(&'static str),
+    /// 超出了最大展开深度
+    ExpandDepthExceeded(usize),
+    /// 由内建函数`Exit`结束了编译, 附带其退出码
+    Exit(i32),
+    /// 数组的长度不是编译期可以求出的非负整数
+    ArrayLenNotConst(Var),
+    /// 常量下标超出了数组的范围, 附带下标与数组长度
+    ArrayIndexOutOfBounds(Var, usize),
+    /// 访问数组时传入的下标数量不为一
+    ArrayIndexCount(usize),
+    /// 访问了记录中不存在的字段
+    RecordFieldNotFound(Var),
+    /// 写入了仅是布局而未被放置在内存中的记录的字段
+    RecordNotPlaced(Var),
+    /// 复制记录时两侧的字段不同
+    RecordLayoutMismatch(Vec<Var>, Vec<Var>),
+    /// `const for`的范围边界不是编译期可以求出的整数
+    ConstForRangeNotConst(Var),
+    /// `const for`范围的迭代次数与最大迭代次数
+    ConstForTooManyIterations(u64, usize),
+    /// `const if`的条件不是编译期可以求出的
+    ConstIfNotConst,
+    /// 被const的值中出现了原始变量, 这通常是编译器内部的错误
+    ConstReprVar(Var),
+}
+impl CompileError {
+    /// 命令行程序遇到该错误时所使用的退出码
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Self::DExpResultNotVar => 5,
+            Self::UnexpandedCmper
+            | Self::OutOfDExp(_)
+            | Self::ExpandDepthExceeded(_)
+            | Self::ArrayLenNotConst(_)
+            | Self::ArrayIndexOutOfBounds(..)
+            | Self::ArrayIndexCount(_)
+            | Self::RecordFieldNotFound(_)
+            | Self::RecordNotPlaced(_)
+            | Self::RecordLayoutMismatch(..)
+            | Self::ConstForRangeNotConst(_)
+            | Self::ConstForTooManyIterations(..)
+            | Self::ConstIfNotConst
+            | Self::ConstReprVar(_) => 6,
+            Self::Exit(code) => code,
+        }
+    }
+
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        match self {
+            Self::UnexpandedCmper => Message::UnexpandedCmper,
+            Self::DExpResultNotVar => Message::DExpResultNotVar,
+            Self::OutOfDExp(_) => Message::OutOfDExp,
+            Self::ExpandDepthExceeded(_) => Message::ExpandDepthExceeded,
+            Self::Exit(_) => Message::Exit,
+            Self::ArrayLenNotConst(_) => Message::ArrayLenNotConst,
+            Self::ArrayIndexOutOfBounds(..) => Message::ArrayIndexOutOfBounds,
+            Self::ArrayIndexCount(_) => Message::ArrayIndexCount,
+            Self::RecordFieldNotFound(_) => Message::RecordFieldNotFound,
+            Self::RecordNotPlaced(_) => Message::RecordNotPlaced,
+            Self::RecordLayoutMismatch(..) => Message::RecordLayoutMismatch,
+            Self::ConstForRangeNotConst(_) => Message::ConstForRangeNotConst,
+            Self::ConstForTooManyIterations(..) =>
+                Message::ConstForTooManyIterations,
+            Self::ConstIfNotConst => Message::ConstIfNotConst,
+            Self::ConstReprVar(_) => Message::ConstReprVar,
+        }
+    }
+}
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::UnexpandedCmper => msg!(UnexpandedCmper),
+            Self::DExpResultNotVar => msg!(DExpResultNotVar),
+            Self::OutOfDExp(value) => msg!(OutOfDExp, value),
+            Self::ExpandDepthExceeded(depth) =>
+                msg!(ExpandDepthExceeded, depth),
+            Self::Exit(code) => msg!(Exit, code),
+            Self::ArrayLenNotConst(len) => msg!(ArrayLenNotConst, len),
+            Self::ArrayIndexOutOfBounds(index, len) =>
+                msg!(ArrayIndexOutOfBounds, index, len),
+            Self::ArrayIndexCount(count) => msg!(ArrayIndexCount, count),
+            Self::RecordFieldNotFound(field) =>
+                msg!(RecordFieldNotFound, field),
+            Self::RecordNotPlaced(field) => msg!(RecordNotPlaced, field),
+            Self::RecordLayoutMismatch(dst, src) =>
+                msg!(RecordLayoutMismatch, dst.join(" "), src.join(" ")),
+            Self::ConstForRangeNotConst(bound) =>
+                msg!(ConstForRangeNotConst, bound),
+            Self::ConstForTooManyIterations(count, max) =>
+                msg!(ConstForTooManyIterations, count, max),
+            Self::ConstIfNotConst => msg!(ConstIfNotConst),
+            Self::ConstReprVar(var) => msg!(ConstReprVar, var),
+        };
+        f.write_str(&msg)
+    }
+}
+
+/// 在解析期间处理`import`语句的导入器
+pub trait Importer {
+    /// 导入给定路径的文件, 并使用同一个[`Meta`]解析它
+    ///
+    /// 同一个文件应返回相同的[`Import::key`], 编译时以此判断重复的导入,
+    /// 所以已经被导入过的文件不必再次解析, 返回之前的结果即可
+    ///
+    /// 被导入的文件中也可能存在导入, 所以这会被递归的调用
+    fn import(&self, meta: &mut Meta, path: &str) -> Result<Import, Errors>;
+}
+impl Debug for dyn Importer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Importer(..)")
+    }
 }
 
 pub type Var = String;
@@ -169,39 +410,48 @@ pub enum Value {
     /// 本层应该指向的绑定者, 也就是ValueBind的被绑定的值
     Binder,
     BuiltinFunc(BuiltinFunc),
+    /// 绑定到内存中一段连续区域的数组
+    Array(Box<Array>),
+    /// 布局在内存中的记录
+    Record(Box<Record>),
 }
 impl Value {
     pub fn try_eval_const_num_to_var(&self, meta: &CompileMeta) -> Option<Var> {
+        // 仅对复杂数据也就是有效运算后的数据
         if let Some((num, true)) = self.try_eval_const_num(meta) {
-            use std::num::FpCategory as FpC;
-            // 仅对复杂数据也就是有效运算后的数据
-            return match num.classify() {
-                FpC::Nan => "null".into(),
-                FpC::Infinite
-                if num.is_sign_negative() => (i64::MIN+1).to_string(),
-                FpC::Infinite => i64::MAX.to_string(),
-                _ => loop {
-                    let n = num.round() - num;
-                    if let FpC::Zero | FpC::Subnormal = n.classify() {
-                        let rng = i64::MIN as f64..=i64::MAX as f64;
-                        let v = 999999;
-                        if rng.contains(&num) && !(-v..=v).contains(&(num as i64)) {
-                            let num = num.round() as i64;
-                            break if !num.is_negative() {
-                                format!("0x{num:X}")
-                            } else {
-                                let num = -num;
-                                format!("0x-{num:X}")
-                            }
-                        }
-                    }
-                    break num.to_string()
-                },
-            }.into()
+            Some(Self::num_to_var(num))
         } else {
             None
         }
     }
+
+    /// 将编译期计算出的数字转换为逻辑中的字面量
+    pub fn num_to_var(num: f64) -> Var {
+        use std::num::FpCategory as FpC;
+        match num.classify() {
+            FpC::Nan => "null".into(),
+            FpC::Infinite
+            if num.is_sign_negative() => (i64::MIN+1).to_string(),
+            FpC::Infinite => i64::MAX.to_string(),
+            _ => loop {
+                let n = num.round() - num;
+                if let FpC::Zero | FpC::Subnormal = n.classify() {
+                    let rng = i64::MIN as f64..=i64::MAX as f64;
+                    let v = 999999;
+                    if rng.contains(&num) && !(-v..=v).contains(&(num as i64)) {
+                        let num = num.round() as i64;
+                        break if !num.is_negative() {
+                            format!("0x{num:X}")
+                        } else {
+                            let num = -num;
+                            format!("0x-{num:X}")
+                        }
+                    }
+                }
+                break num.to_string()
+            },
+        }
+    }
 }
 impl TakeHandle for Value {
     fn take_handle(self, meta: &mut CompileMeta) -> Var {
@@ -213,7 +463,7 @@ impl TakeHandle for Value {
         match self {
             Self::Var(var) => var.take_handle(meta),
             Self::DExp(dexp) => dexp.take_handle(meta),
-            Self::ResultHandle => meta.dexp_handle().clone(),
+            Self::ResultHandle => meta.dexp_handle(),
             Self::ReprVar(var) => var,
             Self::ValueBind(val_bind) => val_bind.take_handle(meta),
             Self::Binder => {
@@ -221,14 +471,15 @@ impl TakeHandle for Value {
                     .unwrap_or_else(|| "__".into())
             },
             Self::Cmper(cmp) => {
-                err!(
-                    "{}\n最终未被展开的cmper, {:#?}",
-                    meta.err_info().join("\n"),
-                    cmp,
+                meta.report_error(
+                    CompileError::UnexpandedCmper,
+                    [format!("cmper: {cmp:?}")],
                 );
-                exit(6);
+                "__".into()
             }
             Self::BuiltinFunc(func) => func.call(meta),
+            Self::Array(array) => array.take_handle(meta),
+            Self::Record(record) => record.take_handle(meta),
         }
     }
     fn take_handle_with_consted(self, meta: &mut CompileMeta) -> Var {
@@ -238,7 +489,8 @@ impl TakeHandle for Value {
         match self {
             Self::Var(var) => var,
             Self::ReprVar(var) => {
-                panic!("Fail const reprvar {}, meta: {:#?}", var, meta);
+                meta.report_error(CompileError::ConstReprVar(var.clone()), []);
+                var
             },
             other => other.take_handle(meta),
         }
@@ -389,12 +641,17 @@ impl Value {
                     Some(ConstData { value: x @ Self::DExp(_), .. }) => {
                         x.try_eval_const_num(meta)
                     },
+                    // 记录布局的大小
+                    Some(ConstData { value: Self::Record(record), .. })
+                    if record.place.is_none() => {
+                        Some((record.fields.len() as f64, false))
+                    },
                     Some(_) => None?,
                     None => num(name, false),
                 }
             },
             Self::DExp(dexp) if dexp.len() == 1 && dexp.result.is_empty() => {
-                let logic_line = &dexp.first().unwrap();
+                let logic_line = dexp.first().unwrap().unspanned();
                 match logic_line {
                     LogicLine::Op(op) => {
                         op.try_eval_const_num(meta)
@@ -421,7 +678,12 @@ impl Value {
             Value::ValueBind(ValueBind(..)) => None,
             // NOTE: 这不能实现, 否则可能牵扯一些不希望的作用域问题
             Value::ResultHandle => None,
-            Value::BuiltinFunc(_) | Value::DExp(_) | Value::Cmper(_) => None,
+            | Value::BuiltinFunc(_)
+            | Value::DExp(_)
+            | Value::Cmper(_)
+            | Value::Array(_)
+            | Value::Record(_)
+            => None,
         }
     }
 }
@@ -483,22 +745,15 @@ impl TakeHandle for DExp {
         } else if let Some(ConstData { value, .. })
                 = meta.get_const_value(&result) {
             // 对返回句柄使用常量值的处理
-            if !value.is_var() {
-                err!(
-                    concat!(
-                        "{}\n尝试在`DExp`的返回句柄处使用值不为Var的const, ",
-                        "此处仅允许使用`Var`\n",
-                        "值: {:#?}\n",
-                        "名称: {:?}",
-                    ),
-                    meta.err_info().join("\n"),
-                    value,
-                    result
-                );
-                exit(5);
+            if let Some(var) = value.as_var() {
+                result = var.clone()
+            } else {
+                let notes = [
+                    msg!(NameNote, format!("{result:?}")),
+                    msg!(ValueNote, format!("{value:?}")),
+                ];
+                meta.report_error(CompileError::DExpResultNotVar, notes);
             }
-            assert!(value.is_var());
-            result = value.as_var().unwrap().clone()
         }
         assert!(! result.is_empty());
         meta.push_dexp_handle(result);
@@ -521,11 +776,22 @@ impl ValueBind {
 }
 impl TakeHandle for ValueBind {
     fn take_handle(self, meta: &mut CompileMeta) -> Var {
+        if let Some(record) = self.0.as_record(meta) {
+            return record.read_field(meta, &self.1);
+        }
         self.take_unfollow_handle(meta)
             .take_handle(meta)  // 进行通常是全局表的常量表查询
     }
 }
 
+/// 被解析的一个源码文件
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SourceFile {
+    /// 文件路径, 从标准输入读取时为空
+    pub path: Option<String>,
+    pub src: String,
+}
+
 /// 进行`词法&语法`分析时所依赖的元数据
 #[derive(Debug)]
 pub struct Meta {
@@ -535,6 +801,16 @@ pub struct Meta {
     defined_labels: Vec<HashSet<Var>>,
     break_labels: Vec<Option<Var>>,
     continue_labels: Vec<Option<Var>>,
+    importer: Option<Rc<dyn Importer>>,
+    /// 是否将语句包装为[`Spanned`]以记录其来源
+    record_origin: bool,
+    /// 当前正在解析的源码文件, 是`source_files`的下标
+    source_file: usize,
+    source_files: Vec<SourceFile>,
+    /// 已定义的函数及其参数数量
+    fn_params: HashMap<Var, usize>,
+    /// 已声明的记录及其字段
+    record_fields: HashMap<Var, Vec<Var>>,
 }
 impl Default for Meta {
     fn default() -> Self {
@@ -544,6 +820,12 @@ impl Default for Meta {
             defined_labels: vec![HashSet::new()],
             break_labels: Vec::new(),
             continue_labels: Vec::new(),
+            importer: None,
+            record_origin: false,
+            source_file: 0,
+            source_files: Vec::new(),
+            fn_params: HashMap::new(),
+            record_fields: HashMap::new(),
         }
     }
 }
@@ -661,6 +943,70 @@ impl Meta {
         label.as_ref().unwrap()
     }
 
+    /// 设置处理`import`的导入器, 返回之前的导入器
+    pub fn set_importer(&mut self, importer: Option<Rc<dyn Importer>>)
+    -> Option<Rc<dyn Importer>> {
+        replace(&mut self.importer, importer)
+    }
+
+    /// 设置是否记录语句的来源, 见[`Self::spanned`]
+    pub fn set_record_origin(&mut self, record_origin: bool) {
+        self.record_origin = record_origin
+    }
+
+    pub fn record_origin(&self) -> bool {
+        self.record_origin
+    }
+
+    /// 设置之后解析的语句所在的源码文件, 返回之前的文件,
+    /// 解析完成后使用[`Self::restore_source_file`]恢复
+    ///
+    /// 只有在记录来源时才会被加入到[`Self::source_files`]
+    pub fn set_source_file(&mut self, path: Option<String>, src: &str) -> usize {
+        let old = self.source_file;
+        if self.record_origin {
+            self.source_file = self.source_files.len();
+            self.source_files.push(SourceFile { path, src: src.into() });
+        }
+        old
+    }
+
+    pub fn restore_source_file(&mut self, source_file: usize) {
+        self.source_file = source_file
+    }
+
+    /// 所有被记录的源码文件, [`Spanned`]中的文件编号为其下标
+    pub fn source_files(&self) -> &[SourceFile] {
+        &self.source_files
+    }
+
+    /// 在记录来源时将语句包装为[`Spanned`], 否则原样返回
+    ///
+    /// 标记与空语句不会生成代码, 所以不会被包装
+    pub fn spanned(&self, [l, r]: [Location; 2], line: LogicLine) -> LogicLine {
+        if !self.record_origin
+            || matches!(line, LogicLine::Label(_) | LogicLine::Ignore)
+        {
+            return line;
+        }
+        Spanned {
+            file: self.source_file,
+            span: [l, r],
+            line: line.into(),
+        }.into()
+    }
+
+    /// 使用导入器导入一个文件, 并将其内容展开在当前作用域中
+    ///
+    /// 传入的路径为带有引号的字符串
+    pub fn import(&mut self, path: Var) -> Result<LogicLine, Errors> {
+        let Some(importer) = self.importer.clone() else {
+            return Err(Errors::ImportUnsupported);
+        };
+        let path = &path[1..path.len()-1];
+        importer.import(self, path).map(Into::into)
+    }
+
     pub fn push_some_label_to(
         &mut self,
         lines: &mut Vec<LogicLine>,
@@ -711,6 +1057,193 @@ impl Meta {
                 value,
         ]))
     }
+
+    /// 函数`name`用于传递参数, 返回地址与返回值的变量
+    fn fn_var(name: &str, kind: impl Display) -> Var {
+        format!("__fn_{name}_{kind}")
+    }
+
+    /// 构建一个函数定义, 例如`fn Foo[A B] { ... }`
+    ///
+    /// 函数体会在定义处被编译一次并被跳过, 使用[`Self::build_call`]构建的调用
+    /// 会设置返回地址后跳转到函数头部, 执行完后通过`@counter`返回.
+    /// 同时会定义一个同名的const, 所以依旧可以使用`take Foo[1 2]`等将其内联
+    ///
+    /// 参数会被传递到固定的变量中, 在函数体中可以使用参数名或是`_0`等访问,
+    /// 返回句柄同样是一个固定的变量, 所以函数不能被递归调用,
+    /// 函数体中使用`setres`时, 最终的句柄会被写入该变量
+    ///
+    /// 函数只能定义在const与其它函数之外, 否则它的标签会随展开被重命名
+    ///
+    /// - labels: 函数体中定义的标签, 用于内联时重命名
+    pub fn build_fn(
+        &mut self,
+        name: Var,
+        params: Vec<Var>,
+        body: Expand,
+        labels: Vec<Var>,
+    ) -> Result<LogicLine, Errors> {
+        if self.defined_labels.len() > 1 {
+            return Err(Errors::FnInConst(name));
+        }
+        if self.fn_params.insert(name.clone(), params.len()).is_some() {
+            return Err(Errors::FnRedefined(name));
+        }
+        let mut lines: Vec<LogicLine> = params.iter()
+            .enumerate()
+            .map(|(i, param)| {
+                Const::new(param.clone().into(), format!("_{i}").into()).into()
+            })
+            .collect();
+        lines.extend(body.0);
+
+        let param_vars = (0..params.len())
+            .map(|i| Value::ReprVar(Self::fn_var(&name, i)))
+            .collect();
+        let res = Self::fn_var(&name, "res");
+        let mut entry_lines = vec![LogicLine::SetArgs(Args::Normal(param_vars))];
+        entry_lines.extend(lines.iter().cloned());
+        // 句柄被`setres`改变时, 将其写入返回值变量
+        let unchanged = MatchPatAtom::new_unnamed(vec![Value::ReprVar(res.clone())]);
+        let changed = MatchPatAtom::new_unnamed(vec![]);
+        entry_lines.push(Match::new(Args::Normal(vec![Value::ResultHandle]), vec![
+            (MatchPat::Normal(vec![unchanged]), InlineBlock(vec![])),
+            (MatchPat::Normal(vec![changed]), InlineBlock(vec![
+                Self::build_set(Value::ReprVar(res.clone()), Value::ResultHandle),
+            ])),
+        ]).into());
+        let entry = DExp::new(res, entry_lines.into());
+
+        let skip = self.get_tag();
+        Ok(InlineBlock(vec![
+            Const(name.clone().into(), DExp::new_nores(lines.into()).into(), labels)
+                .into(),
+            Goto(skip.clone(), JumpCmp::Always.into()).into(),
+            LogicLine::new_label(Self::fn_var(&name, "head"), self),
+            Take("__".into(), entry.into()).into(),
+            Self::build_set(
+                Value::ReprVar(COUNTER.into()),
+                Value::ReprVar(Self::fn_var(&name, "ret")),
+            ),
+            LogicLine::new_label(skip, self),
+        ]).into())
+    }
+
+    /// 构建一个对[`Self::build_fn`]所定义函数的调用, 例如`call Foo[1 2]`
+    ///
+    /// 调用是一个DExp, 其返回句柄为函数的返回值变量,
+    /// 它会在下一次调用同一函数时被覆盖
+    ///
+    /// 如果函数已经被定义, 那么会检查参数数量
+    pub fn build_call(&self, name: Var, args: Vec<Value>) -> Result<Value, Errors> {
+        if let Some(&count) = self.fn_params.get(&name) {
+            if count != args.len() {
+                return Err(Errors::FnArgsCountMismatch(name, count, args.len()));
+            }
+        }
+        let params = (0..args.len())
+            .map(|i| Self::fn_var(&name, i))
+            .collect();
+        let mut lines: Vec<LogicLine> = vec![CallArgs::new(params, args).into()];
+        lines.extend([
+            Op::Add(
+                Value::ReprVar(Self::fn_var(&name, "ret")),
+                Value::ReprVar(COUNTER.into()),
+                Value::ReprVar("1".into()),
+            ).into(),
+            Goto(Self::fn_var(&name, "head"), JumpCmp::Always.into()).into(),
+        ]);
+        Ok(DExp::new(Self::fn_var(&name, "res"), lines.into()).into())
+    }
+
+    /// 构建一个计数循环, 例如`for i = 0; i < n; op i i + 1; { ... }`
+    ///
+    /// 先执行`init`, 然后在条件满足时重复执行循环体与`step`,
+    /// `continue`会跳转到`step`之前, 所以不会跳过`step`
+    ///
+    /// - ctrl: 循环体的break与continue标签
+    pub fn build_for(
+        &mut self,
+        init: LogicLine,
+        cmp: CmpTree,
+        step: LogicLine,
+        body: LogicLine,
+        ctrl: (Option<Var>, Option<Var>),
+    ) -> LogicLine {
+        let (break_lab, continue_lab) = ctrl;
+        let [end, head] = [self.get_tag(), self.get_tag()];
+        let rev_cmp = cmp.clone().reverse();
+        let mut res = Vec::with_capacity(9);
+
+        res.extend([
+            init,
+            Goto(end.clone(), rev_cmp).into(),
+            LogicLine::new_label(head.clone(), self),
+            body,
+        ]);
+        self.push_some_label_to(&mut res, continue_lab);
+        res.extend([
+            step,
+            Goto(head, cmp).into(),
+            LogicLine::new_label(end, self),
+        ]);
+        self.push_some_label_to(&mut res, break_lab);
+
+        Expand(res).into()
+    }
+
+    /// 构建一个对范围计数的循环, 例如`for i in 0..n { ... }`
+    ///
+    /// 等价于`for i = 0; i < n; op i i + 1; { ... }`,
+    /// 所以`end`会在每次判断条件时被求值
+    pub fn build_for_range(
+        &mut self,
+        var: Var,
+        start: Value,
+        end: Value,
+        body: LogicLine,
+        ctrl: (Option<Var>, Option<Var>),
+    ) -> LogicLine {
+        let init = Self::build_set(Value::Var(var.clone()), start);
+        let cmp = JumpCmp::LessThan(Value::Var(var.clone()), end).into();
+        let step = Op::Add(
+            Value::Var(var.clone()),
+            Value::Var(var),
+            Value::ReprVar("1".into()),
+        ).into();
+        self.build_for(init, cmp, step, body, ctrl)
+    }
+
+    /// 构建一个记录的声明, 例如`record Vec2[x y];`或`record Pos[x y] = cell1[4];`
+    ///
+    /// 没有给出内存元时仅声明一个布局, 之后可以使用[`Self::build_record_of`]
+    /// 以它的字段放置记录
+    pub fn build_record(
+        &mut self,
+        name: Var,
+        fields: Vec<Var>,
+        place: Option<(Value, Value)>,
+    ) -> Result<LogicLine, Errors> {
+        let mut seen = HashSet::new();
+        if let Some(field) = fields.iter().find(|&field| !seen.insert(field)) {
+            return Err(Errors::RecordFieldRepeated(field.clone()));
+        }
+        self.record_fields.insert(name.clone(), fields.clone());
+        Ok(Const::new(name.into(), Record { fields, place }.into()).into())
+    }
+
+    /// 以已声明的记录`layout`的字段放置一个记录, 例如`record Pos: Vec2 = cell1[4];`
+    pub fn build_record_of(
+        &mut self,
+        name: Var,
+        layout: Var,
+        place: (Value, Value),
+    ) -> Result<LogicLine, Errors> {
+        let Some(fields) = self.record_fields.get(&layout) else {
+            return Err(Errors::RecordLayoutUndefined(layout));
+        };
+        self.build_record(name, fields.clone(), place.into())
+    }
 }
 
 pub trait FromMdtArgs
@@ -879,6 +1412,25 @@ impl JumpCmp {
         }
     }
 
+    /// 使用给定的运算成员计算条件是否成立, 没有运算成员的条件会忽略它们
+    ///
+    /// 与逻辑处理器相同, 非严格的相等比较允许`0.000001`的误差
+    pub fn eval_num(&self, a: f64, b: f64) -> bool {
+        let equal = (a - b).abs() < 0.000001;
+        match self {
+            Self::Equal(..) => equal,
+            Self::NotEqual(..) => !equal,
+            Self::StrictEqual(..) => a == b,
+            Self::StrictNotEqual(..) => a != b,
+            Self::LessThan(..) => a < b,
+            Self::LessThanEq(..) => a <= b,
+            Self::GreaterThan(..) => a > b,
+            Self::GreaterThanEq(..) => a >= b,
+            Self::Always => true,
+            Self::NotAlways => false,
+        }
+    }
+
     /// 获取运算符号
     pub fn get_symbol_cmp_str(&self) -> &'static str {
         macro_rules! build_match {
@@ -909,20 +1461,13 @@ impl Display for JumpCmpRParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use JumpCmpRParseError::*;
 
-        match self {
-            ArgsCountError(args) => write!(
-                f,
-                "参数数量错误, 预期3个参数, 得到{}个参数: {:?}",
-                args.len(),
-                args
-            ),
-            UnknownComparer(oper, [a, b]) => write!(
-                f,
-                "未知的比较符: {:?}, 参数为: {:?}",
-                oper,
-                (a, b)
-            ),
-        }
+        let msg = match self {
+            ArgsCountError(args) =>
+                msg!(JumpCmpArgsCount, args.len(), format!("{args:?}")),
+            UnknownComparer(oper, [a, b]) =>
+                msg!(UnknownComparer, format!("{oper:?}"), format!("{:?}", (a, b))),
+        };
+        f.write_str(&msg)
     }
 }
 impl FromMdtArgs for JumpCmp {
@@ -986,20 +1531,13 @@ impl Display for OpRParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use OpRParseError::*;
 
-        match self {
-            ArgsCountError(args) => write!(
-                f,
-                "参数数量错误, 预期4个参数, 得到{}个参数: {:?}",
-                args.len(),
-                args
-            ),
-            UnknownOper(oper, [a, b]) => write!(
-                f,
-                "未知的运算符: {:?}, 参数为: {:?}",
-                oper,
-                (a, b)
-            ),
-        }
+        let msg = match self {
+            ArgsCountError(args) =>
+                msg!(OpArgsCount, args.len(), format!("{args:?}")),
+            UnknownOper(oper, [a, b]) =>
+                msg!(UnknownOperator, format!("{oper:?}"), format!("{:?}", (a, b))),
+        };
+        f.write_str(&msg)
     }
 }
 
@@ -1021,14 +1559,8 @@ impl Display for LogicLineFromTagError {
                 Display::fmt(&e, f),
             Self::OpRParseError(e) =>
                 Display::fmt(&e, f),
-            Self::StringNoStop { str, char_num } => {
-                write!(
-                    f,
-                    "未闭合的字符串, 在第{}个字符处起始, 行:[{}]",
-                    char_num,
-                    str
-                )
-            },
+            Self::StringNoStop { str, char_num } =>
+                f.write_str(&msg!(LogicStringNoStop, char_num, str)),
         }
     }
 }
@@ -1122,12 +1654,14 @@ impl CmpTree {
                 | V::ReprVar(s)
                 => Some(&**s),
                 | V::ResultHandle
-                => Some(&**meta.dexp_handle()),
+                => Some(&**meta.try_get_dexp_handle()?),
                 | V::Binder
                 => Some(meta.get_dexp_expand_binder().map(|s| &**s).unwrap_or("__")),
                 | V::ValueBind(_)
                 | V::Cmper(_)
                 | V::BuiltinFunc(_)
+                | V::Array(_)
+                | V::Record(_)
                 => None,
             }
         }
@@ -1138,7 +1672,7 @@ impl CmpTree {
         }
         fn check_inline_op(dexp: &DExp) -> bool {
             do_return!(! (dexp.result.is_empty() && dexp.len() == 1) => false);
-            do_return!(let LL::Op(op) = &dexp[0] => false);
+            do_return!(let LL::Op(op) = dexp[0].unspanned() => false);
             do_return!(! op.get_result().is_result_handle() => false);
             do_return!(let Some(_) = op.get_cmper() => false);
             true
@@ -1168,7 +1702,9 @@ impl CmpTree {
             }
             V::DExp(dexp) => {
                 do_return!(! check_inline_op(dexp));
-                let LL::Op(op) = dexp.pop().unwrap() else { unreachable!() };
+                let LL::Op(op) = dexp.pop().unwrap().into_unspanned() else {
+                    unreachable!()
+                };
                 let cmper = op.get_cmper().unwrap();
                 let info = op.into_info();
                 let cmp = cmper(info.arg1, info.arg2.unwrap());
@@ -1179,7 +1715,7 @@ impl CmpTree {
                 match meta.get_const_value(name) {
                     Some(ConstData { value: V::DExp(dexp), .. }) => {
                         do_return!(! check_inline_op(dexp));
-                        let op = dexp[0].as_op().unwrap().clone();
+                        let op = dexp[0].unspanned().as_op().unwrap().clone();
                         let cmper = op.get_cmper().unwrap();
                         let info = op.into_info();
                         let cmp = cmper(info.arg1, info.arg2.unwrap());
@@ -1197,6 +1733,32 @@ impl CmpTree {
 
     }
 
+    /// 尝试在编译期求出条件是否成立
+    ///
+    /// 会先进行内联, 条件的两侧需要可以使用[`Value::try_eval_const_num`]求出,
+    /// 与和或会进行短路, 所以被短路的一侧无需可以求出.
+    /// 带有依赖的条件无法求出
+    pub fn try_eval_const(mut self, meta: &mut CompileMeta) -> Option<bool> {
+        self.try_inline(meta);
+        match self {
+            Self::Deps(..) => None,
+            Self::And(a, b) => {
+                Some(a.try_eval_const(meta)? && b.try_eval_const(meta)?)
+            },
+            Self::Or(a, b) => {
+                Some(a.try_eval_const(meta)? || b.try_eval_const(meta)?)
+            },
+            Self::Atom(cmp) => {
+                let Some((a, b)) = cmp.get_values_ref() else {
+                    return Some(cmp.eval_num(0.0, 0.0));
+                };
+                let a = a.try_eval_const_num(meta)?.0;
+                let b = b.try_eval_const_num(meta)?.0;
+                Some(cmp.eval_num(a, b))
+            },
+        }
+    }
+
     /// 构建条件树为goto
     pub fn build(mut self, meta: &mut CompileMeta, do_tag: Var) {
         use CmpTree::*;
@@ -1503,6 +2065,22 @@ impl Op {
 
     /// 在输出值为返回句柄替换符时, 尝试编译期计算它
     pub fn try_eval_const_num(&self, meta: &CompileMeta) -> Option<f64> {
+        let OpInfo { result, arg1, arg2, .. } = self.get_info();
+        result.as_result_handle()?;
+        let (a, b) = (
+            arg1.try_eval_const_num(meta)?.0,
+            match arg2 {
+                Some(value) => value.try_eval_const_num(meta)?.0,
+                None => 0.0,
+            },
+        );
+        self.eval_num(a, b)
+    }
+
+    /// 使用给定的运算成员计算, 单目运算会忽略`b`
+    ///
+    /// 对于无法在编译期计算的运算返回[`None`]
+    pub fn eval_num(&self, a: f64, b: f64) -> Option<f64> {
         use std::num::FpCategory as FpC;
         fn conv(n: f64) -> Option<f64> {
             match n.classify() {
@@ -1514,15 +2092,6 @@ impl Op {
         fn bool_as(x: bool) -> f64 {
             if x { 1. } else { 0. }
         }
-        let OpInfo { result, arg1, arg2, .. } = self.get_info();
-        result.as_result_handle()?;
-        let (a, b) = (
-            arg1.try_eval_const_num(meta)?.0,
-            match arg2 {
-                Some(value) => value.try_eval_const_num(meta)?.0,
-                None => 0.0,
-            },
-        );
         let (a, b) = (conv(a)?, conv(b)?);
         match self {
             Op::Add(..) => a + b,
@@ -1556,8 +2125,9 @@ impl Op {
             Op::GreaterThan(..) => bool_as(a > b),
             Op::GreaterThanEq(..) => bool_as(a >= b),
 
-            Op::Shl(..) => ((a as i64) << b as i64) as f64,
-            Op::Shr(..) => ((a as i64) >> b as i64) as f64,
+            // 与运行时相同, 移位的位数对64取模
+            Op::Shl(..) => (a as i64).wrapping_shl(b as i64 as u32) as f64,
+            Op::Shr(..) => (a as i64).wrapping_shr(b as i64 as u32) as f64,
             Op::Or(..) => ((a as i64) | b as i64) as f64,
             Op::And(..) => ((a as i64) & b as i64) as f64,
             Op::Xor(..) => ((a as i64) ^ b as i64) as f64,
@@ -1576,7 +2146,23 @@ impl Op {
     }
 }
 impl Compile for Op {
-    fn compile(self, meta: &mut CompileMeta) {
+    fn compile(mut self, meta: &mut CompileMeta) {
+        // 结果为数组元素时先运算到临时变量中, 再将其写入数组
+        if let Some((array, index)) = self.get_info().result.as_array_element(meta) {
+            let tmp = meta.get_tmp_var();
+            *self.get_info_mut().result = Value::ReprVar(tmp.clone());
+            self.compile(meta);
+            array.write(meta, index, tmp);
+            return;
+        }
+        // 结果为记录字段时同理
+        if let Some((record, field)) = self.get_info().result.as_record_field(meta) {
+            let tmp = meta.get_tmp_var();
+            *self.get_info_mut().result = Value::ReprVar(tmp.clone());
+            self.compile(meta);
+            record.write_field(meta, &field, tmp);
+            return;
+        }
         let args = self.generate_args(meta);
         meta.tag_codes.push(args.join(" ").into())
     }
@@ -1727,6 +2313,24 @@ impl From<Vec<LogicLine>> for InlineBlock {
 }
 impl_derefs!(impl for InlineBlock => (self: self.0): Vec<LogicLine>);
 
+/// 被导入的文件, 以内联块的形式展开
+///
+/// 在同一个作用域及其子作用域中, 相同`key`的导入只会被展开一次,
+/// 而在互不包含的作用域中会被分别展开
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    /// 用于判断重复导入的文件标识
+    pub key: Var,
+    pub lines: InlineBlock,
+}
+impl Compile for Import {
+    fn compile(self, meta: &mut CompileMeta) {
+        if meta.add_import(self.key) {
+            self.lines.compile(meta)
+        }
+    }
+}
+
 /// 用于`switch`的`select`结构
 /// 编译最后一步会将其填充至每个语句定长
 /// 然后将`self.0`乘以每个语句的长并让`@counter += _`来跳转到目标
@@ -1894,6 +2498,106 @@ impl Select {
     }
 }
 
+/// 结构化的控制语句, 编译结果与对应的语法糖相同
+///
+/// 由[`rebuild_control_flow`]从跳转中重建
+#[derive(Debug, PartialEq, Clone)]
+pub enum ControlFlow {
+    /// `if cmp { body } else { else_body }`
+    If(CmpTree, Expand, Option<Expand>),
+    /// `while cmp { body }`
+    While(CmpTree, Expand),
+    /// `do { body } while cmp;`
+    DoWhile(Expand, CmpTree),
+    /// `gwhile cmp { body }`
+    Gwhile(CmpTree, Expand),
+}
+impl Compile for ControlFlow {
+    fn compile(self, meta: &mut CompileMeta) {
+        let lines: Vec<LogicLine> = match self {
+            Self::If(cmp, body, None) => {
+                let end = meta.get_tmp_tag();
+                vec![
+                    Goto(end.clone(), cmp.reverse()).into(),
+                    body.into(),
+                    LogicLine::Label(end),
+                ]
+            },
+            Self::If(cmp, body, Some(else_body)) => {
+                let [end, true_start] = [meta.get_tmp_tag(), meta.get_tmp_tag()];
+                vec![
+                    Goto(true_start.clone(), cmp).into(),
+                    else_body.into(),
+                    Goto(end.clone(), JumpCmp::Always.into()).into(),
+                    LogicLine::Label(true_start),
+                    body.into(),
+                    LogicLine::Label(end),
+                ]
+            },
+            Self::While(cmp, body) => {
+                let [end, head] = [meta.get_tmp_tag(), meta.get_tmp_tag()];
+                vec![
+                    Goto(end.clone(), cmp.clone().reverse()).into(),
+                    LogicLine::Label(head.clone()),
+                    body.into(),
+                    Goto(head, cmp).into(),
+                    LogicLine::Label(end),
+                ]
+            },
+            Self::DoWhile(body, cmp) => {
+                let head = meta.get_tmp_tag();
+                vec![
+                    LogicLine::Label(head.clone()),
+                    body.into(),
+                    Goto(head, cmp).into(),
+                ]
+            },
+            Self::Gwhile(cmp, body) => {
+                let [to, head] = [meta.get_tmp_tag(), meta.get_tmp_tag()];
+                vec![
+                    Goto(to.clone(), JumpCmp::Always.into()).into(),
+                    LogicLine::Label(head.clone()),
+                    body.into(),
+                    LogicLine::Label(to),
+                    Goto(head, cmp).into(),
+                ]
+            },
+        };
+        Expand(lines).compile(meta)
+    }
+}
+
+/// 记录了来源的语句, 由[`Meta::spanned`]在解析时包装
+///
+/// 编译时会将来源设置到其生成的还没有来源的代码上,
+/// 所以内层语句的来源优先
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spanned {
+    /// 所在的源码文件, 见[`Meta::source_files`]
+    pub file: usize,
+    pub span: [Location; 2],
+    pub line: Box<LogicLine>,
+}
+impl Compile for Spanned {
+    fn compile(self, meta: &mut CompileMeta) {
+        let start = meta.tag_code_count();
+        meta.spans.push((self.file, self.span));
+        self.line.compile(meta);
+        meta.spans.pop();
+        let origin = Origin {
+            file: self.file,
+            span: self.span.into(),
+            expand_stack: meta.const_expand_names().to_vec(),
+        };
+        let lines = meta.tag_codes_mut().lines_mut();
+        for line in lines.get_mut(start..).unwrap_or_default() {
+            if !line.is_tag_down() && line.origin().is_none() {
+                line.set_origin(Some(origin.clone()))
+            }
+        }
+    }
+}
+
 /// 用于switch捕获器捕获目标的枚举
 pub enum SwitchCatch {
     /// 上溢
@@ -2187,6 +2891,59 @@ impl_enum_froms!(impl From for Args {
     Normal => Vec<Value>;
 });
 
+/// 函数调用的传参, 先求出所有参数的句柄, 再将其设置到对应的参数变量中
+///
+/// 句柄不是数字或字符串等常量, 并且之后的参数求值时生成了代码的,
+/// 会在求出后立即被复制到一个临时变量中, 以免被覆盖,
+/// 例如之后的参数中嵌套调用了同一函数
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallArgs {
+    params: Vec<Var>,
+    args: Vec<Value>,
+}
+impl CallArgs {
+    pub fn new(params: Vec<Var>, args: Vec<Value>) -> Self {
+        assert_eq!(params.len(), args.len());
+        Self { params, args }
+    }
+
+    pub fn params(&self) -> &[Var] {
+        &self.params
+    }
+
+    pub fn args(&self) -> &[Value] {
+        &self.args
+    }
+}
+impl Compile for CallArgs {
+    fn compile(self, meta: &mut CompileMeta) {
+        // 每个句柄及其求出后所在的行
+        let mut handles = self.args.into_iter()
+            .map(|arg| (arg.take_handle(meta), meta.tag_code_count()))
+            .collect::<Vec<_>>();
+        let end = meta.tag_code_count();
+        let mut copies = Vec::new();
+        for (handle, index) in &mut handles {
+            if *index == end || !handle.as_var_type().is_var() {
+                continue;
+            }
+            let tmp = meta.get_tmp_var();
+            copies.push((*index, format!("set {tmp} {handle}")));
+            *handle = tmp;
+        }
+        // 从后往前插入, 使得之前的位置不受影响
+        for (index, line) in copies.into_iter().rev() {
+            meta.tag_codes_mut().insert(index, TagLine::Line(line.into()));
+        }
+        for (param, (handle, _)) in zip(self.params, handles) {
+            Meta::build_set(
+                Value::ReprVar(param),
+                Value::ReprVar(handle),
+            ).compile(meta);
+        }
+    }
+}
+
 /// 拿取指定个参数, 并重复块中代码
 #[derive(Debug, PartialEq, Clone)]
 pub struct ArgsRepeat {
@@ -2233,6 +2990,160 @@ impl Compile for ArgsRepeat {
     }
 }
 
+/// `const for`所迭代的值
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConstForIter {
+    /// `A..B`, 从`A`到`B`但不包括`B`的整数
+    Range(Box<Value>, Box<Value>),
+    /// `[a b c]`, 依次迭代每个值
+    List(Vec<Value>),
+}
+
+/// 在编译期展开的循环, 例如`const for i in 0..4 { ... }`
+///
+/// 每次迭代都会在一个新的作用域中将迭代变量const为迭代的值, 然后编译循环体,
+/// 循环体中定义的标签在每次迭代中都会被重命名
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConstFor {
+    var: Var,
+    iter: ConstForIter,
+    block: Expand,
+    labels: Vec<Var>,
+}
+impl ConstFor {
+    pub fn new(
+        var: Var,
+        iter: ConstForIter,
+        block: Expand,
+        labels: Vec<Var>,
+    ) -> Self {
+        Self { var, iter, block, labels }
+    }
+
+    pub fn var(&self) -> &Var {
+        &self.var
+    }
+
+    pub fn iter(&self) -> &ConstForIter {
+        &self.iter
+    }
+
+    pub fn block(&self) -> &Expand {
+        &self.block
+    }
+
+    pub fn labels(&self) -> &[Var] {
+        &self.labels
+    }
+
+    /// 求出范围的边界, 失败时报告错误
+    fn eval_bound(meta: &mut CompileMeta, bound: Value) -> Option<f64> {
+        let num = bound.try_eval_const_num(meta)
+            .map(|(num, _)| num)
+            .filter(|num| num.fract() == 0.0);
+        if num.is_none() {
+            let bound = match bound {
+                Value::Var(bound) | Value::ReprVar(bound) => bound,
+                bound => format!("{bound:?}"),
+            };
+            meta.report_error(CompileError::ConstForRangeNotConst(bound), []);
+        }
+        num
+    }
+}
+impl Compile for ConstFor {
+    fn compile(self, meta: &mut CompileMeta) {
+        let values = match self.iter {
+            ConstForIter::Range(start, end) => {
+                let start = Self::eval_bound(meta, *start);
+                let end = Self::eval_bound(meta, *end);
+                let (Some(start), Some(end)) = (start, end) else { return };
+                let (start, end) = (start as i64, end as i64);
+                let count = if end > start { end.abs_diff(start) } else { 0 };
+                let max = meta.const_for_max_iterations();
+                if count > max as u64 {
+                    meta.report_error(
+                        CompileError::ConstForTooManyIterations(count, max),
+                        [],
+                    );
+                    return;
+                }
+                (start..end)
+                    .map(|i| Value::ReprVar(Value::num_to_var(i as f64)))
+                    .collect()
+            },
+            ConstForIter::List(values) => values,
+        };
+        for value in values {
+            let labels_map = self.labels.iter()
+                .map(|label| {
+                    let tmp_tag = meta.get_tmp_tag();
+                    let name = format!(
+                        "{}_const_for_{}_{}",
+                        tmp_tag,
+                        &self.var,
+                        label,
+                    );
+                    (label.clone(), name)
+                })
+                .collect();
+            meta.const_expand_tag_name_map.push(labels_map);
+            meta.with_block(|meta| {
+                Const::new(self.var.clone().into(), value).compile(meta);
+                self.block.clone().compile(meta);
+            });
+            meta.const_expand_tag_name_map.pop().unwrap();
+        }
+    }
+}
+
+/// 在编译期选择分支的条件编译, 例如`const if N > 2 { ... } else { ... }`
+///
+/// 依次在编译期求出每个条件, 仅编译第一个成立的分支,
+/// 都不成立时编译else分支, 条件无法求出时报错.
+/// 分支并不会产生新的作用域, 所以其中定义的常量在之后依旧可用
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConstIf {
+    cases: Vec<(CmpTree, InlineBlock)>,
+    else_body: Option<InlineBlock>,
+}
+impl ConstIf {
+    pub fn new(
+        cases: Vec<(CmpTree, InlineBlock)>,
+        else_body: Option<InlineBlock>,
+    ) -> Self {
+        Self { cases, else_body }
+    }
+
+    pub fn cases(&self) -> &[(CmpTree, InlineBlock)] {
+        &self.cases
+    }
+
+    pub fn else_body(&self) -> Option<&InlineBlock> {
+        self.else_body.as_ref()
+    }
+}
+impl Compile for ConstIf {
+    fn compile(self, meta: &mut CompileMeta) {
+        for (cmp, body) in self.cases {
+            match cmp.clone().try_eval_const(meta) {
+                Some(true) => return body.compile(meta),
+                Some(false) => (),
+                None => {
+                    meta.report_error(
+                        CompileError::ConstIfNotConst,
+                        [format!("cmp: {cmp:?}")],
+                    );
+                    return;
+                },
+            }
+        }
+        if let Some(else_body) = self.else_body {
+            else_body.compile(meta)
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Match {
     args: Args,
@@ -2375,9 +3286,14 @@ pub enum LogicLine {
     Label(Var),
     Goto(Goto),
     Other(Args),
+    /// 参数已被区分的已知语句
+    Instruction(Instruction),
     Expand(Expand),
     InlineBlock(InlineBlock),
+    Import(Import),
     Select(Select),
+    ControlFlow(ControlFlow),
+    Spanned(Spanned),
     NoOp,
     /// 空语句, 什么也不生成
     Ignore,
@@ -2390,6 +3306,9 @@ pub enum LogicLine {
     SetArgs(Args),
     ArgsRepeat(ArgsRepeat),
     Match(Match),
+    ConstFor(ConstFor),
+    ConstIf(ConstIf),
+    CallArgs(CallArgs),
 }
 impl Compile for LogicLine {
     fn compile(self, meta: &mut CompileMeta) {
@@ -2401,10 +3320,47 @@ impl Compile for LogicLine {
                 let data = TagLine::TagDown(meta.get_tag(lab));
                 meta.push(data)
             },
+            Self::Other(Args::Normal(args))
+            if args.len() == 3
+            && args[0] == Value::ReprVar("set".into())
+            && args[1].as_array_element(meta).is_some()
+            => {
+                let (array, index) = args[1].as_array_element(meta).unwrap();
+                let value = args.into_iter().nth(2).unwrap().take_handle(meta);
+                array.write(meta, index, value);
+            },
+            Self::Other(Args::Normal(args))
+            if args.len() == 3
+            && args[0] == Value::ReprVar("set".into())
+            && args[1].as_record_field(meta).is_some()
+            => {
+                let (record, field) = args[1].as_record_field(meta).unwrap();
+                let value = args.into_iter().nth(2).unwrap().take_handle(meta);
+                record.write_field(meta, &field, value);
+            },
+            Self::Other(Args::Normal(args))
+            if args.len() == 3
+            && args[0] == Value::ReprVar("set".into())
+            && args[1].as_record(meta).is_some()
+            && args[2].as_record(meta).is_some()
+            => {
+                let dst = args[1].as_record(meta).unwrap();
+                let src = args[2].as_record(meta).unwrap();
+                dst.copy_from(meta, src);
+            },
             Self::Other(args) => {
                 let handles: Vec<String> = args.into_taked_args_handle(meta);
-                meta.push(TagLine::Line(handles.join(" ").into()));
+                let line = handles.join(" ");
+                let args = handles.iter().map(String::as_str).collect::<Vec<_>>();
+                if let Err(e) = check_instruction(&args) {
+                    let diagnostic = Diagnostic::warning(e.to_string())
+                        .with_kind(e.message_kind())
+                        .with_note(msg!(LineNote, line));
+                    meta.report(meta.with_current_span(diagnostic));
+                }
+                meta.push(TagLine::Line(line.into()));
             },
+            Self::Instruction(inst) => inst.compile(meta),
             Self::SetResultHandle(value) => {
                 let new_dexp_handle = value.take_handle(meta);
                 meta.set_dexp_handle(new_dexp_handle);
@@ -2430,8 +3386,11 @@ impl Compile for LogicLine {
                     .collect());
             },
             Self::Select(select) => select.compile(meta),
+            Self::ControlFlow(control) => control.compile(meta),
+            Self::Spanned(spanned) => spanned.compile(meta),
             Self::Expand(expand) => expand.compile(meta),
             Self::InlineBlock(block) => block.compile(meta),
+            Self::Import(import) => import.compile(meta),
             Self::Goto(goto) => goto.compile(meta),
             Self::Op(op) => op.compile(meta),
             Self::Const(r#const) => r#const.compile(meta),
@@ -2439,6 +3398,9 @@ impl Compile for LogicLine {
             Self::ConstLeak(r#const) => meta.add_const_value_leak(r#const),
             Self::ArgsRepeat(args_repeat) => args_repeat.compile(meta),
             Self::Match(r#match) => r#match.compile(meta),
+            Self::ConstFor(const_for) => const_for.compile(meta),
+            Self::ConstIf(const_if) => const_if.compile(meta),
+            Self::CallArgs(call_args) => call_args.compile(meta),
             Self::Ignore => (),
         }
     }
@@ -2455,6 +3417,22 @@ impl LogicLine {
         Self::Label(meta.add_defined_label(lab))
     }
 
+    /// 去除[`Spanned`]的包装, 获取实际的语句
+    pub fn unspanned(&self) -> &Self {
+        match self {
+            Self::Spanned(spanned) => spanned.line.unspanned(),
+            line => line,
+        }
+    }
+
+    /// 去除[`Spanned`]的包装, 获取实际的语句
+    pub fn into_unspanned(self) -> Self {
+        match self {
+            Self::Spanned(spanned) => spanned.line.into_unspanned(),
+            line => line,
+        }
+    }
+
     /// Returns `true` if the logic line is [`Op`].
     ///
     /// [`Op`]: LogicLine::Op
@@ -2546,13 +3524,20 @@ impl LogicLine {
 impl_enum_froms!(impl From for LogicLine {
     Op => Op;
     Goto => Goto;
+    Instruction => Instruction;
     Expand => Expand;
     InlineBlock => InlineBlock;
+    Import => Import;
     Select => Select;
+    ControlFlow => ControlFlow;
+    Spanned => Spanned;
     Const => Const;
     Take => Take;
     ArgsRepeat => ArgsRepeat;
     Match => Match;
+    ConstFor => ConstFor;
+    ConstIf => ConstIf;
+    CallArgs => CallArgs;
 });
 impl TryFrom<&TagLine> for LogicLine {
     type Error = LogicLineFromTagError;
@@ -2593,6 +3578,9 @@ impl TryFrom<&TagLine> for LogicLine {
                         .map(Into::into)
                         .map_err(Into::into),
                     _ => {
+                        if let Some(inst) = Instruction::from_mdt_args(&args) {
+                            return Ok(inst.into());
+                        }
                         let mut args_value = Vec::with_capacity(args.len());
                         args_value.extend(args.into_iter().map(Into::into));
                         Ok(Self::Other(Args::Normal(args_value)))
@@ -2666,6 +3654,8 @@ impl ConstData {
 pub struct ExpandEnv {
     leak_vars: Vec<Var>,
     consts: HashMap<Var, ConstData>,
+    /// 在这层中导入的文件
+    imports: HashSet<Var>,
 }
 impl ExpandEnv {
     pub fn new(leak_vars: Vec<Var>, consts: HashMap<Var, ConstData>) -> Self {
@@ -2711,10 +3701,20 @@ pub struct CompileMeta {
     const_expand_tag_name_map: Vec<HashMap<Var, Var>>,
     const_expand_names: Vec<Var>,
     const_expand_max_depth: usize,
+    /// `const for`的范围最多进行的迭代次数
+    const_for_max_iterations: usize,
     value_binds: HashMap<(Var, Var), Var>,
     /// 值绑定全局常量表, 只有值绑定在使用它
     value_bind_global_consts: HashMap<Var, ConstData>,
     last_builtin_exit_code: u8,
+    /// 编译期间产生的诊断信息
+    diagnostics: Vec<Diagnostic>,
+    /// 编译期间产生的致命错误, 出现后不再进行const展开
+    errors: Vec<CompileError>,
+    /// 解析时记录的源码文件, 见[`Meta::source_files`]
+    source_files: Vec<SourceFile>,
+    /// 正在编译的[`Spanned`]的来源, 末尾为最内层
+    spans: Vec<(usize, [Location; 2])>,
 }
 impl Debug for CompileMeta {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -2739,6 +3739,9 @@ impl Debug for CompileMeta {
             .field("value_binds", &self.value_binds)
             .field("value_bind_global_consts", &self.value_bind_global_consts)
             .field("last_builtin_exit_code", &self.last_builtin_exit_code)
+            .field("diagnostics", &self.diagnostics)
+            .field("errors", &self.errors)
+            .field("spans", &self.spans)
             .field("..", &DotDot)
             .finish()
     }
@@ -2767,9 +3770,14 @@ impl CompileMeta {
             const_expand_tag_name_map: Vec::new(),
             const_expand_names: Vec::new(),
             const_expand_max_depth: 500,
+            const_for_max_iterations: 10000,
             value_binds: HashMap::new(),
             value_bind_global_consts: HashMap::new(),
             last_builtin_exit_code: 0,
+            diagnostics: Vec::new(),
+            errors: Vec::new(),
+            source_files: Vec::new(),
+            spans: Vec::new(),
         };
         let builtin = String::from("Builtin");
         for builtin_func in build_builtins() {
@@ -2911,6 +3919,19 @@ impl CompileMeta {
         block_exit(self)
     }
 
+    /// 在当前作用域中记录一个导入,
+    /// 如果当前作用域或外层作用域已经导入过它则返回假
+    pub fn add_import(&mut self, key: Var) -> bool {
+        if self.expand_env.iter().any(|env| env.imports.contains(&key)) {
+            return false;
+        }
+        self.expand_env
+            .last_mut()
+            .unwrap()
+            .imports
+            .insert(key)
+    }
+
     /// 添加一个需泄露的const
     pub fn add_const_value_leak(&mut self, name: Var) {
         self.expand_env
@@ -3038,33 +4059,31 @@ impl CompileMeta {
         self.dexp_result_handles.last()
     }
 
-    /// 获取当前DExp返回句柄
-    pub fn dexp_handle(&self) -> &Var {
-        self.try_get_dexp_handle()
-            .unwrap_or_else(
-                || self.do_out_of_dexp_err("`DExpHandle` (`$`)"))
+    /// 获取当前DExp返回句柄, 没有DExp的话报告错误并返回一个占位句柄
+    pub fn dexp_handle(&mut self) -> Var {
+        match self.try_get_dexp_handle() {
+            Some(handle) => handle.clone(),
+            None => {
+                self.report_error(
+                    CompileError::OutOfDExp("`DExpHandle` (`$`)"),
+                    [],
+                );
+                "__".into()
+            },
+        }
     }
 
     /// 将当前DExp返回句柄替换为新的
-    /// 并将旧的句柄返回
+    /// 并将旧的句柄返回, 没有DExp的话报告错误并返回新的句柄
     pub fn set_dexp_handle(&mut self, new_dexp_handle: Var) -> Var {
         if let Some(ref_) = self.dexp_result_handles.last_mut() {
             replace(ref_, new_dexp_handle)
         } else {
-            self.do_out_of_dexp_err("`setres`")
+            self.report_error(CompileError::OutOfDExp("`setres`"), []);
+            new_dexp_handle
         }
     }
 
-    /// 对在外部使用`DExpHandle`进行报错
-    fn do_out_of_dexp_err(&self, value: &str) -> ! {
-        err!(
-            "{}\n尝试在`DExp`的外部使用{}",
-            self.err_info().join("\n"),
-            value,
-        );
-        exit(6)
-    }
-
     /// 对于一个标记(Label), 进行寻找, 如果是在展开宏中, 则进行替换
     /// 一层层往上找, 如果没找到返回本身
     pub fn get_in_const_label(&self, name: Var) -> Var {
@@ -3076,20 +4095,17 @@ impl CompileMeta {
     /// 进入一层宏展开环境, 并且返回其值
     /// 这个函数会直接调用获取函数将标记映射完毕, 然后返回其值
     /// 如果不是一个宏则直接返回None, 也不会进入无需清理
+    ///
+    /// 如果已经产生了致命错误, 或超出最大展开深度, 同样返回None
     pub fn const_expand_enter(&mut self, name: &Var) -> Option<Value> {
+        if !self.errors.is_empty() { return None; }
         let label_count = self.get_const_value(name)?.labels().len();
         if self.const_expand_names.len() >= self.const_expand_max_depth {
-            self.log_err(format!(
-                    "Stack Expand:\n{}",
-                    self.debug_expand_stack()
-                        .collect::<Vec<_>>()
-                        .join(", "),
-            ));
-            err!(
-                "Maximum recursion depth exceeded ({})",
-                self.const_expand_max_depth,
+            self.report_error(
+                CompileError::ExpandDepthExceeded(self.const_expand_max_depth),
+                [msg!(ExpandingNote, name)],
             );
-            exit(6)
+            return None;
         }
         let mut tmp_tags = Vec::with_capacity(label_count);
         tmp_tags.extend(repeat_with(|| self.get_tmp_tag())
@@ -3217,14 +4233,45 @@ impl CompileMeta {
         self.env_args.pop().unwrap()
     }
 
+    /// 记录一条诊断信息, 并附上当前的展开栈
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        let diagnostic = diagnostic
+            .with_expand_stack(self.debug_expand_stack());
+        self.diagnostics.push(diagnostic)
+    }
+
+    /// 记录一个致命错误, 以及对应的诊断信息
+    pub fn report_error(
+        &mut self,
+        err: CompileError,
+        notes: impl IntoIterator<Item = String>,
+    ) {
+        let diagnostic = match err {
+            CompileError::Exit(_) => Diagnostic::info(err.to_string()),
+            _ => Diagnostic::error(err.to_string()),
+        }.with_kind(err.message_kind());
+        let diagnostic = notes.into_iter()
+            .fold(diagnostic, Diagnostic::with_note);
+        self.report(diagnostic);
+        self.errors.push(err)
+    }
+
+    /// 编译期间产生的致命错误
+    pub fn errors(&self) -> &[CompileError] {
+        &self.errors
+    }
+
+    /// 取出编译期间产生的诊断信息
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        mem::take(&mut self.diagnostics)
+    }
+
     pub fn log_info(&mut self, s: impl std::fmt::Display) {
-        eprintln!("\x1b[1m[I] {}\x1b[0m", s.to_string()
-            .trim_end().replace('\n', "\n    "))
+        self.report(Diagnostic::info(s.to_string().trim_end()))
     }
 
     pub fn log_err(&mut self, s: impl std::fmt::Display) {
-        eprintln!("\x1b[1;91m[E] {}\x1b[0m", s.to_string()
-            .trim_end().replace('\n', "\n    "))
+        self.report(Diagnostic::error(s.to_string().trim_end()))
     }
 
     pub fn debug_expand_stack(&self) -> impl Iterator<Item = String> + '_ {
@@ -3240,7 +4287,7 @@ impl CompileMeta {
         let names = self.debug_expand_stack()
             .collect::<Vec<_>>()
             .join("\n");
-        self.log_info(format!("Expand Stack:\n{names}"))
+        self.log_info(msg!(ExpandStackLog, names))
     }
 
     pub fn last_builtin_exit_code(&self) -> u8 {
@@ -3262,6 +4309,33 @@ impl CompileMeta {
     pub fn set_const_expand_max_depth(&mut self, const_expand_max_depth: usize) {
         self.const_expand_max_depth = const_expand_max_depth;
     }
+
+    pub fn const_for_max_iterations(&self) -> usize {
+        self.const_for_max_iterations
+    }
+
+    pub fn set_const_for_max_iterations(&mut self, const_for_max_iterations: usize) {
+        self.const_for_max_iterations = const_for_max_iterations;
+    }
+
+    /// 设置解析时记录的源码文件, 用于给诊断信息附上所在的源码
+    pub fn set_source_files(&mut self, source_files: Vec<SourceFile>) {
+        self.source_files = source_files;
+    }
+
+    /// 给诊断信息附上最内层正在编译的[`Spanned`]所在的源码与范围,
+    /// 没有记录来源时原样返回
+    pub fn with_current_span(&self, diagnostic: Diagnostic) -> Diagnostic {
+        let Some((file, [start, end])) = self.spans.last().copied() else {
+            return diagnostic;
+        };
+        let diagnostic = diagnostic.with_span(start, end);
+        match self.source_files.get(file) {
+            Some(SourceFile { path, src }) => diagnostic
+                .with_source(Source::new(path.clone(), src.as_str())),
+            None => diagnostic,
+        }
+    }
 }
 
 pub fn line_first_add(lines: &mut Vec<String>, insert: &str) {
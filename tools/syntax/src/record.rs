@@ -0,0 +1,146 @@
+//! 布局在内存元或内存库中的记录
+//!
+//! 记录使用`record Vec2[x y];`声明布局, 每个字段依次占用一个位置,
+//! 使用`record Pos: Vec2 = cell1[4];`或`record Pos[x y] = cell1[4];`
+//! 将记录放置在内存中, 它们都是值为[`Record`]的const.
+//!
+//! 使用`Pos.x`读取字段, 将`Pos.x`作为`set`或`op`的结果时写入字段,
+//! 而`Pos = Other;`会复制整个记录
+
+use tag_code::TagLine;
+use utils::msg;
+use crate::{
+    CompileError,
+    Compile,
+    CompileMeta,
+    Op,
+    TakeHandle,
+    Value,
+    ValueBind,
+    Var,
+};
+
+/// 布局在内存元或内存库中的记录
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    /// 按位置排列的字段, 第`i`个字段位于起始位置加`i`处
+    pub fields: Vec<Var>,
+    /// 记录所在的内存元与起始位置, 为空时仅是一个布局
+    pub place: Option<(Value, Value)>,
+}
+impl Record {
+    /// 直接使用记录时, 布局得到其大小, 被放置的记录得到其起始位置
+    pub(crate) fn take_handle(self, meta: &mut CompileMeta) -> Var {
+        match self.place {
+            Some((_, base)) => base.take_handle(meta),
+            None => Value::num_to_var(self.fields.len() as f64),
+        }
+    }
+
+    /// 读取一个字段, 也就是`Pos.x`被take时
+    ///
+    /// 对于布局, 得到的是字段的偏移
+    pub(crate) fn read_field(self, meta: &mut CompileMeta, field: &Var) -> Var {
+        let Some(offset) = self.offset(meta, field) else {
+            return "__".into();
+        };
+        let Some((cell, base)) = self.place else {
+            return Value::num_to_var(offset as f64);
+        };
+        let result = meta.get_tmp_var();
+        let addr = Self::addr(meta, base, offset);
+        let cell = cell.take_handle(meta);
+        meta.push(TagLine::Line(format!("read {result} {cell} {addr}").into()));
+        result
+    }
+
+    /// 将`value`的句柄写入一个字段
+    pub(crate) fn write_field(
+        self,
+        meta: &mut CompileMeta,
+        field: &Var,
+        value: Var,
+    ) {
+        let Some(offset) = self.offset(meta, field) else { return };
+        let Some((cell, base)) = self.place else {
+            meta.report_error(CompileError::RecordNotPlaced(field.clone()), []);
+            return;
+        };
+        let addr = Self::addr(meta, base, offset);
+        let cell = cell.take_handle(meta);
+        meta.push(TagLine::Line(format!("write {value} {cell} {addr}").into()));
+    }
+
+    /// 将`src`的每个字段复制到自身
+    ///
+    /// 会先读取所有字段再进行写入, 所以两者在内存中重叠时也能正确复制
+    pub(crate) fn copy_from(self, meta: &mut CompileMeta, src: Record) {
+        if self.fields != src.fields {
+            meta.report_error(CompileError::RecordLayoutMismatch(
+                self.fields,
+                src.fields,
+            ), []);
+            return;
+        }
+        let values = src.fields.iter()
+            .map(|field| src.clone().read_field(meta, field))
+            .collect::<Vec<_>>();
+        for (field, value) in self.fields.iter().zip(values) {
+            self.clone().write_field(meta, field, value);
+        }
+    }
+
+    /// 字段在记录中的偏移, 不存在时报告错误
+    fn offset(&self, meta: &mut CompileMeta, field: &Var) -> Option<usize> {
+        let offset = self.fields.iter().position(|f| f == field);
+        if offset.is_none() {
+            meta.report_error(
+                CompileError::RecordFieldNotFound(field.clone()),
+                [msg!(FieldsNote, self.fields.join(" "))],
+            );
+        }
+        offset
+    }
+
+    /// 求出字段的地址, 起始位置为常量时在编译期算出
+    fn addr(meta: &mut CompileMeta, base: Value, offset: usize) -> Var {
+        match base.try_eval_const_num(meta) {
+            Some((base, _)) => Value::num_to_var(base + offset as f64),
+            None if offset == 0 => base.take_handle(meta),
+            None => {
+                let addr = meta.get_tmp_var();
+                Op::Add(
+                    Value::ReprVar(addr.clone()),
+                    base,
+                    Value::ReprVar(offset.to_string()),
+                ).compile(meta);
+                addr
+            },
+        }
+    }
+}
+
+impl Value {
+    /// 如果是一个值为记录的const, 返回该记录
+    pub(crate) fn as_record(&self, meta: &CompileMeta) -> Option<Record> {
+        let Value::Var(name) = self else { return None };
+        match &meta.get_const_value(name)?.value {
+            Value::Record(record) => Some((**record).clone()),
+            _ => None,
+        }
+    }
+
+    /// 如果是对一个记录字段的访问`Pos.x`, 返回记录与字段名
+    pub(crate) fn as_record_field(&self, meta: &CompileMeta) -> Option<(Record, Var)> {
+        let Value::ValueBind(ValueBind(binder, field)) = self else {
+            return None
+        };
+        Some((binder.as_record(meta)?, field.clone()))
+    }
+}
+
+impl From<Record> for Value {
+    fn from(record: Record) -> Self {
+        Self::Record(record.into())
+    }
+}
@@ -0,0 +1,246 @@
+//! 已知逻辑语句的参数信息
+//!
+//! 用于分析一行逻辑读取与写入了哪些变量, 以及检查语句名与参数数量
+
+use std::fmt::Display;
+
+use utils::{message::Message, msg};
+
+/// 逻辑语句中一个参数的作用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgRole {
+    /// 被读取的值
+    Input,
+    /// 被写入的变量
+    Output,
+    /// 根据子命令可能被读取也可能被写入, 未知的语句的参数也视为此类
+    Unknown,
+    /// 子命令或选项等固定的名称, 而不是变量
+    Keyword,
+}
+
+/// 一条已知逻辑语句的参数信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionInfo {
+    pub name: &'static str,
+    /// 除去语句名以外各参数的作用, 其数量为最多可用的参数数量
+    pub args: &'static [ArgRole],
+    /// 没有子命令时最少需要的参数数量, 为空时需要所有参数
+    pub min_args: Option<usize>,
+    /// 第一个参数可用的子命令及其之后需要的参数数量, 为空时不检查子命令
+    pub subcommands: &'static [(&'static str, usize)],
+}
+impl InstructionInfo {
+    /// 按照语句名查找
+    pub fn find(name: &str) -> Option<&'static Self> {
+        INSTRUCTIONS.iter().find(|info| info.name == name)
+    }
+
+    /// 检查除去语句名以外的参数是否与该语句相符
+    pub fn check(&self, args: &[&str]) -> Result<(), InstructionError> {
+        let name = self.name;
+        let min = if self.subcommands.is_empty() {
+            self.min_args.unwrap_or(self.args.len())
+        } else {
+            let sub = args.first().copied().unwrap_or_default();
+            let Some(&(_, count)) = self.subcommands.iter()
+                .find(|&&(name, _)| name == sub)
+            else {
+                return Err(InstructionError::UnknownSubCommand(name, sub.into()));
+            };
+            count + 1
+        };
+        if args.len() < min {
+            return Err(InstructionError::TooFewArgs(name, min, args.len()));
+        }
+        if args.len() > self.args.len() {
+            let max = self.args.len();
+            return Err(InstructionError::TooManyArgs(name, max, args.len()));
+        }
+        Ok(())
+    }
+
+    /// 是否只会读取参数, 即所有参数都是[`ArgRole::Input`]或[`ArgRole::Keyword`]
+    pub fn is_read_only(&self) -> bool {
+        self.args.iter()
+            .all(|role| matches!(role, ArgRole::Input | ArgRole::Keyword))
+    }
+}
+
+/// 检查一行逻辑的语句名, 子命令与参数数量
+///
+/// # Examples
+/// ```
+/// # use tag_code::*;
+/// assert_eq!(check_instruction(&["ucontrol", "move", "x", "y"]), Ok(()));
+/// assert_eq!(
+///     check_instruction(&["sensr", "a", "b", "@x"]),
+///     Err(InstructionError::Unknown("sensr".into())),
+/// );
+/// assert_eq!(
+///     check_instruction(&["ucontrol", "move", "x"]),
+///     Err(InstructionError::TooFewArgs("ucontrol", 3, 2)),
+/// );
+/// ```
+pub fn check_instruction(args: &[&str]) -> Result<(), InstructionError> {
+    let Some((name, args)) = args.split_first() else { return Ok(()) };
+    InstructionInfo::find(name)
+        .ok_or_else(|| InstructionError::Unknown(name.to_string()))?
+        .check(args)
+}
+
+/// 检查逻辑语句时发现的问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstructionError {
+    /// 未知的语句名
+    Unknown(String),
+    /// 语句名, 未知的子命令
+    UnknownSubCommand(&'static str, String),
+    /// 语句名, 最少需要的参数数量, 实际的参数数量
+    TooFewArgs(&'static str, usize, usize),
+    /// 语句名, 最多可用的参数数量, 实际的参数数量
+    TooManyArgs(&'static str, usize, usize),
+}
+impl InstructionError {
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        match self {
+            Self::Unknown(_) => Message::UnknownInstruction,
+            Self::UnknownSubCommand(..) => Message::UnknownSubCommand,
+            Self::TooFewArgs(..) => Message::TooFewArgs,
+            Self::TooManyArgs(..) => Message::TooManyArgs,
+        }
+    }
+}
+impl Display for InstructionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            Self::Unknown(name) =>
+                msg!(UnknownInstruction, format!("{name:?}")),
+            Self::UnknownSubCommand(name, sub) =>
+                msg!(UnknownSubCommand, name, format!("{sub:?}")),
+            Self::TooFewArgs(name, min, found) =>
+                msg!(TooFewArgs, name, min, found),
+            Self::TooManyArgs(name, max, found) =>
+                msg!(TooManyArgs, name, max, found),
+        };
+        f.write_str(&msg)
+    }
+}
+
+macro_rules! instructions {
+    (@min) => { None };
+    (@min $min:literal) => { Some($min) };
+    ($(
+        $name:literal [$($role:ident),* $(,)?]
+        $(min $min:literal)?
+        $({ $($sub:literal $count:literal),* $(,)? })?
+    ),* $(,)?) => {
+        &[$(
+            InstructionInfo {
+                name: $name,
+                args: &[$(ArgRole::$role),*],
+                min_args: instructions!(@min $($min)?),
+                subcommands: &[$($(($sub, $count)),*)?],
+            },
+        )*]
+    };
+}
+
+/// 所有已知的逻辑语句
+pub const INSTRUCTIONS: &[InstructionInfo] = instructions![
+    "read"          [Output, Input, Input],
+    "write"         [Input, Input, Input],
+    "draw"          [Keyword, Input, Input, Input, Input, Input, Input] {
+        "clear" 3, "color" 4, "col" 1, "stroke" 1, "line" 4, "rect" 4,
+        "lineRect" 4, "poly" 5, "linePoly" 5, "triangle" 6, "image" 5,
+        "print" 3, "translate" 2, "scale" 2, "rotate" 1, "reset" 0,
+    },
+    "print"         [Input],
+    "printchar"     [Input],
+    "format"        [Input],
+    "drawflush"     [Input],
+    "printflush"    [Input],
+    "getlink"       [Output, Input],
+    "control"       [Keyword, Input, Input, Input, Input, Input] {
+        "enabled" 2, "shoot" 4, "shootp" 3, "config" 2, "color" 2,
+    },
+    "radar"         [Keyword, Keyword, Keyword, Keyword, Input, Input, Output],
+    "sensor"        [Output, Input, Input],
+    "set"           [Output, Input],
+    "op"            [Keyword, Output, Input, Input] {
+        "add" 3, "sub" 3, "mul" 3, "div" 3, "idiv" 3, "mod" 3, "emod" 3,
+        "pow" 3, "equal" 3, "notEqual" 3, "land" 3, "lessThan" 3,
+        "lessThanEq" 3, "greaterThan" 3, "greaterThanEq" 3, "strictEqual" 3,
+        "shl" 3, "shr" 3, "ushr" 3, "or" 3, "and" 3, "xor" 3, "max" 3,
+        "min" 3, "angle" 3, "angleDiff" 3, "len" 3, "noise" 3, "logn" 3,
+        "not" 2, "abs" 2, "sign" 2, "log" 2, "log10" 2, "floor" 2,
+        "ceil" 2, "round" 2, "sqrt" 2, "rand" 2, "sin" 2, "cos" 2, "tan" 2,
+        "asin" 2, "acos" 2, "atan" 2,
+    },
+    "select"        [Output, Keyword, Input, Input, Input, Input],
+    "lookup"        [Keyword, Output, Input] {
+        "block" 2, "unit" 2, "item" 2, "liquid" 2, "team" 2,
+    },
+    "packcolor"     [Output, Input, Input, Input, Input],
+    "unpackcolor"   [Output, Output, Output, Output, Input],
+    "wait"          [Input],
+    "stop"          [],
+    "end"           [],
+    "noop"          [],
+    "jump"          [Keyword, Keyword, Input, Input] min 2,
+    "ubind"         [Input],
+    "ucontrol"      [Keyword, Unknown, Unknown, Unknown, Unknown, Unknown] {
+        "idle" 0, "stop" 0, "move" 2, "approach" 3, "pathfind" 2,
+        "autoPathfind" 0, "boost" 1, "target" 3, "targetp" 2,
+        "itemDrop" 2, "itemTake" 3, "payDrop" 0, "payTake" 1,
+        "payEnter" 0, "mine" 2, "flag" 1, "build" 5, "getBlock" 5,
+        "within" 4, "unbind" 0, "deconstruct" 2,
+    },
+    "uradar"        [Keyword, Keyword, Keyword, Keyword, Input, Input, Output],
+    "ulocate"       [Keyword, Keyword, Input, Input, Output, Output, Output, Output] {
+        "ore" 7, "building" 7, "spawn" 7, "damaged" 7,
+    },
+    // 世界处理器的语句, 参数的作用未做区分
+    "getblock"      [Unknown, Unknown, Unknown, Unknown] {
+        "floor" 3, "ore" 3, "block" 3, "building" 3,
+    },
+    "setblock"      [Unknown, Unknown, Unknown, Unknown, Unknown, Unknown] {
+        "floor" 3, "ore" 3, "block" 5,
+    },
+    "spawn"         [Unknown, Unknown, Unknown, Unknown, Unknown, Unknown],
+    "status"        [Unknown, Unknown, Unknown, Unknown] min 3,
+    "spawnwave"     [Unknown, Unknown, Unknown],
+    "setrule"       [Unknown, Unknown, Unknown, Unknown, Unknown, Unknown] min 2,
+    "message"       [Unknown, Unknown, Unknown] min 1,
+    "cutscene"      [Unknown, Unknown, Unknown, Unknown, Unknown] {
+        "pan" 3, "zoom" 1, "stop" 0,
+    },
+    "effect"        [Unknown, Unknown, Unknown, Unknown, Unknown, Unknown] min 3,
+    "explosion"     [Unknown, Unknown, Unknown, Unknown, Unknown, Unknown, Unknown, Unknown, Unknown],
+    "setrate"       [Unknown],
+    "fetch"         [Unknown, Unknown, Unknown, Unknown, Unknown] min 3,
+    "sync"          [Unknown],
+    "getflag"       [Unknown, Unknown],
+    "setflag"       [Unknown, Unknown],
+    "setprop"       [Unknown, Unknown, Unknown],
+    "playsound"     [Unknown, Unknown, Unknown, Unknown, Unknown, Unknown, Unknown, Unknown] min 5,
+    "weathersense"  [Unknown, Unknown],
+    "weatherset"    [Unknown, Unknown],
+    "clientdata"    [Unknown, Unknown, Unknown],
+    "localeprint"   [Unknown],
+    "makemarker"    [Unknown, Unknown, Unknown, Unknown, Unknown] min 4,
+    "setmarker"     [Unknown, Unknown, Unknown, Unknown, Unknown] min 3,
+];
+
+/// 获取一行逻辑中除去语句名以外各参数的作用
+///
+/// 未知的语句以及超出已知数量的参数为[`ArgRole::Unknown`]
+pub fn arg_roles(args: &[&str]) -> Vec<ArgRole> {
+    let Some((name, args)) = args.split_first() else { return vec![] };
+    let known = InstructionInfo::find(name)
+        .map_or(&[][..], |info| info.args);
+    (0..args.len())
+        .map(|i| known.get(i).copied().unwrap_or(ArgRole::Unknown))
+        .collect()
+}
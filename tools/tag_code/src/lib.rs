@@ -11,10 +11,17 @@ use std::{
     str::FromStr,
     num::ParseIntError,
     fmt::Display,
-    panic::catch_unwind,
-    process::exit,
 };
 
+use utils::{message::Message, msg};
+
+mod instructions;
+mod liveness;
+mod peephole;
+
+pub use instructions::*;
+pub use peephole::*;
+
 pub type Tag = usize;
 pub type TagsTable = Vec<usize>;
 pub const UNINIT_TAG_TARGET: usize = usize::MAX;
@@ -22,7 +29,7 @@ pub const UNINIT_TAG_TARGET: usize = usize::MAX;
 /// 带有错误前缀, 并且文本为红色的eprintln
 macro_rules! err {
     ( $fmtter:expr $(, $args:expr)* $(,)? ) => {
-        eprintln!(concat!("\x1b[1;31m", "TagCodeError: ", $fmtter, "\x1b[0m"), $($args),*);
+        eprintln!(concat!("\x1b[1;31m", "TagCodeError: ", $fmtter, "\x1b[0m"), $($args),*)
     };
 }
 
@@ -32,11 +39,23 @@ pub trait Compile {
     fn compile(&self, tags_table: &TagsTable) -> String;
 }
 
+/// 一行代码在源码中的来源
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Origin {
+    /// 来源文件的编号, 由生成代码的一方决定其含义
+    pub file: usize,
+    /// 在来源文件中的字节范围
+    pub span: (usize, usize),
+    /// 生成该行时所处的const展开, 由外到内
+    pub expand_stack: Vec<String>,
+}
+
 /// 带有`Tag`信息的封装一个数据
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct TagBox<T> {
     tag: Option<Tag>,
     data: T,
+    origin: Option<Box<Origin>>,
 }
 impl<T> Deref for TagBox<T> {
     type Target = T;
@@ -52,7 +71,7 @@ impl<T> DerefMut for TagBox<T> {
 }
 impl<T> TagBox<T> {
     pub fn new(tag: Option<Tag>, data: T) -> Self {
-        Self { tag, data }
+        Self { tag, data, origin: None }
     }
 
     pub fn tag(&self) -> Option<Tag> {
@@ -67,6 +86,14 @@ impl<T> TagBox<T> {
         &self.data
     }
 
+    pub fn origin(&self) -> Option<&Origin> {
+        self.origin.as_deref()
+    }
+
+    pub fn set_origin(&mut self, origin: Option<Origin>) {
+        self.origin = origin.map(Box::new)
+    }
+
     pub fn into_inner(self) -> (Option<Tag>, T) {
         (self.tag, self.data)
     }
@@ -77,7 +104,7 @@ impl<T> TagBox<T> {
 }
 impl<T> From<(Option<Tag>, T)> for TagBox<T> {
     fn from((tag, data): (Option<Tag>, T)) -> Self {
-        Self { tag, data }
+        Self::new(tag, data)
     }
 }
 impl<T> From<(Tag, T)> for TagBox<T> {
@@ -93,14 +120,7 @@ impl<T> From<T> for TagBox<T> {
 
 macro_rules! panic_tag_out_of_range {
     ($id:expr, $tags_table:expr $(,)?) => {
-        err!(
-            concat!(
-                "进行了越界的跳转标签构建, ",
-                "你可以查看是否在尾部编写了空的被跳转标签\n",
-                "标签id: {}, 目标行表:\n",
-                "id \t-> target\n",
-                "{}",
-            ),
+        let (id, table) = (
             $id,
             $tags_table.iter()
                 .enumerate()
@@ -112,9 +132,10 @@ macro_rules! panic_tag_out_of_range {
                     })
                 })
                 .collect::<Vec<_>>()
-                .join("\n")
+                .join("\n"),
         );
-        panic!("显式恐慌");
+        err!("{}", msg!(TagOutOfRangeBuild, id, table));
+        panic!("{}", msg!(ExplicitPanic));
     };
 }
 
@@ -147,7 +168,9 @@ impl Jump {
     /// [`is_literal_always_jump`]: Self::is_literal_always_jump
     pub fn is_always_jump(&self) -> bool {
         let jump_body = self.1.as_str();
-        let jump_args = mdt_logic_split_unwraped(jump_body);
+        let Ok(jump_args) = mdt_logic_split(jump_body) else {
+            return false;
+        };
         match jump_args[..] {
             [
                 | "equal"
@@ -165,13 +188,19 @@ impl Jump {
     /// 不考虑运算成员, 仅考虑字面上是否是一个无条件跳转
     pub fn is_literal_always_jump(&self) -> bool {
         let jump_body = self.1.as_str();
-        let jump_args = mdt_logic_split_unwraped(jump_body);
-        matches!(jump_args[..], ["always", ..])
+        mdt_logic_split(jump_body)
+            .is_ok_and(|jump_args| matches!(jump_args[..], ["always", ..]))
+    }
+
+    /// 跳转目标是否在行表中
+    pub fn check_target(&self, tags_table: &TagsTable) -> bool {
+        tags_table.get(self.0)
+            .is_some_and(|&target| target != UNINIT_TAG_TARGET)
     }
 
     /// 校验跳转目标是否在行表中, 如果不在则进行恐慌
     pub fn check_target_unwrap(&self, tags_table: &TagsTable) {
-        if self.0 >= tags_table.len() || tags_table[self.0] == UNINIT_TAG_TARGET {
+        if !self.check_target(tags_table) {
             panic_tag_out_of_range!(self.0, tags_table);
         }
     }
@@ -340,6 +369,32 @@ impl TagLine {
         }
     }
 
+    /// 获取[`Line`]或者[`Jump`]在源码中的来源, [`TagDown`]没有来源
+    ///
+    /// [`Line`]: `Self::Line`
+    /// [`Jump`]: `Self::Jump`
+    /// [`TagDown`]: `Self::TagDown`
+    pub fn origin(&self) -> Option<&Origin> {
+        match self {
+            Self::Jump(jump) => jump.origin(),
+            Self::Line(line) => line.origin(),
+            Self::TagDown(_) => None,
+        }
+    }
+
+    /// 设置[`Line`]或者[`Jump`]在源码中的来源, 对[`TagDown`]无效
+    ///
+    /// [`Line`]: `Self::Line`
+    /// [`Jump`]: `Self::Jump`
+    /// [`TagDown`]: `Self::TagDown`
+    pub fn set_origin(&mut self, origin: Option<Origin>) {
+        match self {
+            Self::Jump(jump) => jump.set_origin(origin),
+            Self::Line(line) => line.set_origin(origin),
+            Self::TagDown(_) => (),
+        }
+    }
+
     pub fn as_line_mut(&mut self) -> Option<&mut TagBox<String>> {
         if let Self::Line(v) = self {
             Some(v)
@@ -348,6 +403,28 @@ impl TagLine {
         }
     }
 
+    /// 是否是一个无法静态得知目标的跳转,
+    /// 例如写入`@counter`或是以普通行形式给出的`jump`
+    pub fn is_dynamic_jump(&self) -> bool {
+        let Self::Line(line) = self else { return false };
+        let Ok(args) = mdt_logic_split(line) else { return false };
+        match args[..] {
+            ["jump", ..] => true,
+            [
+                | "print"
+                | "printflush"
+                | "write"
+                | "wait"
+                | "draw"
+                | "drawflush"
+                | "control"
+                , ..
+            ] => false,
+            [_, ref args @ ..] => args.contains(&"@counter"),
+            [] => false,
+        }
+    }
+
     pub fn as_tag_down(&self) -> Option<&Tag> {
         if let Self::TagDown(v) = self {
             Some(v)
@@ -458,6 +535,66 @@ impl Compile for TagLine {
 pub enum ParseTagCodesError {
     ParseIntError(ParseIntError),
 }
+impl ParseTagCodesError {
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        match self {
+            Self::ParseIntError(_) => Message::InvalidJumpTarget,
+        }
+    }
+}
+impl Display for ParseTagCodesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParseIntError(e) =>
+                f.write_str(&msg!(InvalidJumpTarget, e)),
+        }
+    }
+}
+
+/// 编译[`TagCodes`]时的错误, 包含出错的行下标及`Tag`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CompileTagCodesError {
+    /// 重复的标记
+    DuplicateTag(usize, Tag),
+    /// 跳转到了不存在的标记, 通常是在尾部编写了空的被跳转标记
+    TagOutOfRange(usize, Tag),
+}
+impl CompileTagCodesError {
+    pub fn line(&self) -> usize {
+        match *self {
+            | Self::DuplicateTag(line, _)
+            | Self::TagOutOfRange(line, _)
+            => line,
+        }
+    }
+
+    pub fn tag(&self) -> Tag {
+        match *self {
+            | Self::DuplicateTag(_, tag)
+            | Self::TagOutOfRange(_, tag)
+            => tag,
+        }
+    }
+
+    /// 该错误对应的信息种类
+    pub fn message_kind(&self) -> Message {
+        match self {
+            Self::DuplicateTag(..) => Message::DuplicateTag,
+            Self::TagOutOfRange(..) => Message::TagOutOfRange,
+        }
+    }
+}
+impl Display for CompileTagCodesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateTag(_, tag) =>
+                f.write_str(&msg!(DuplicateTag, tag)),
+            Self::TagOutOfRange(_, tag) =>
+                f.write_str(&msg!(TagOutOfRange, tag)),
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct TagCodes {
@@ -560,6 +697,30 @@ impl TagCodes {
         self.lines.pop()
     }
 
+    /// 删除一行, 需要已经进行了[`build_tagdown`]
+    ///
+    /// 如果该行带有`Tag`, 那么跳转到它的将跳转到其下一行,
+    /// 最后一行的下一行为第一行
+    ///
+    /// [`build_tagdown`]: `TagCodes::build_tagdown`
+    pub fn remove_line(&mut self, index: usize) -> TagLine {
+        let line = self.lines.remove(index);
+        let Some(tag) = line.tag() else { return line };
+        if self.lines.is_empty() { return line }
+        let next = if index < self.lines.len() { index } else { 0 };
+        match *self.lines[next].tag_mut() {
+            ref mut next_tag @ None => *next_tag = Some(tag),
+            Some(next_tag) => {
+                for jump in self.lines.iter_mut().filter_map(TagLine::as_jump_mut) {
+                    if jump.0 == tag {
+                        jump.0 = next_tag
+                    }
+                }
+            },
+        }
+        line
+    }
+
     /// 直接在指定位置插入语句, 慎用!
     pub fn insert(&mut self, index: usize, line: TagLine) {
         self.lines.insert(index, line)
@@ -666,11 +827,13 @@ impl TagCodes {
             let tag_refs: Vec<&mut Tag> = match &mut line {
                 TagLine::Jump(TagBox {
                     tag: Some(tag),
-                    data: Jump(j_dst, ..)
+                    data: Jump(j_dst, ..),
+                    ..
                 }) => vec![tag, j_dst],
                 TagLine::Jump(TagBox {
                     tag: None,
-                    data: Jump(j_dst, ..)
+                    data: Jump(j_dst, ..),
+                    ..
                 }) => vec![j_dst],
                 TagLine::Line(TagBox {
                     tag: Some(tag),
@@ -721,11 +884,13 @@ impl TagCodes {
             loop {
                 let line @ &TagLine::Jump(TagBox {
                     tag: self_tag,
-                    data: ref jump @ Jump(target_tag, _)
+                    data: ref jump @ Jump(target_tag, _),
+                    ..
                 }) = &lines[line_idx] else { break };
                 assert!(line.as_tag_down().is_none());
                 if Some(target_tag) == self_tag || ! jump.is_always_jump() { break }
-                jump.check_target_unwrap(tags_table);
+                // 越界的跳转留给编译时报告
+                if !jump.check_target(tags_table) { break }
                 if add_history(&mut tag_target_history, target_tag) {
                     // 如果是环路, 那么不要去处理
                     line_idx = init_line_idx;
@@ -745,14 +910,141 @@ impl TagCodes {
         }
     }
 
-    /// 编译为逻辑行码
-    /// 如果有重复的`Tag`, 返回其行下标及重复`Tag`
+    /// 删除无法被执行到的行, 返回被删除的行数
     /// 会调用[`build_tagdown`]来改变源码
     ///
+    /// 从第一行开始沿着顺序执行与跳转进行可达性分析,
+    /// 加上一个字面量的`@counter`跳转, 例如`op add @counter @counter 2`,
+    /// 会先被转换为普通的无条件跳转.
+    /// 如果还存在其它写入`@counter`的行, 那么无法得知其跳转目标,
+    /// 此时不会删除任何行
+    ///
+    /// [`build_tagdown`]: `TagCodes::build_tagdown`
+    pub fn eliminate_dead_code(&mut self) -> Result<usize, CompileTagCodesError> {
+        self.build_tagdown().map_err(|(line, tag)| {
+            CompileTagCodesError::DuplicateTag(line, tag)
+        })?;
+        self.static_counter_to_jump();
+        if self.lines.is_empty()
+            || self.lines.iter().any(TagLine::is_dynamic_jump)
+        {
+            return Ok(0);
+        }
+
+        // 跳转链中被跳过的无条件跳转同样视为不可达
+        let mut tags_table = self.build_tags_table();
+        self.follow_always_jump_chain(&mut tags_table);
+        let mut reachable = vec![false; self.lines.len()];
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            if replace(&mut reachable[i], true) { continue }
+            stack.extend(self.successors(&tags_table, i));
+        }
+
+        // 跳转链中的行可能被删除, 所以直接跳转到跳转链的终点
+        let targets: Vec<Option<Tag>> = self.lines.iter().map(|line| {
+            let jump = line.as_jump()
+                .filter(|jump| jump.check_target(&tags_table))?;
+            self.lines[tags_table[jump.0]].tag()
+        }).collect();
+        for (line, target) in self.lines.iter_mut().zip(targets) {
+            if let (Some(jump), Some(target)) = (line.as_jump_mut(), target) {
+                jump.0 = target
+            }
+        }
+
+        let len = self.lines.len();
+        let mut reachable = reachable.into_iter();
+        self.lines.retain(|_| reachable.next().unwrap());
+        Ok(len - self.lines.len())
+    }
+
+    /// 将目标可以静态得知的`@counter`跳转转换为普通的无条件跳转
+    ///
+    /// 包括加上一个字面量, 加上一个由两字面量相乘所得的变量,
+    /// 以及设置为一个字面量
+    fn static_counter_to_jump(&mut self) {
+        let uint = |s: &str| s.parse::<f64>().ok()
+            .filter(|n| n.fract() == 0.0 && *n >= 0.0)
+            .map(|n| n as usize);
+        let mut next_tag = self.lines.iter()
+            .flat_map(|line| [line.tag(), line.as_jump().map(|jump| jump.0)])
+            .flatten()
+            .max()
+            .map_or(0, |tag| tag + 1);
+
+        for i in 0..self.lines.len() {
+            let TagLine::Line(line) = &self.lines[i] else { continue };
+            let Ok(args) = mdt_logic_split(line) else { continue };
+            let offset = match args[..] {
+                ["op", "add", "@counter", "@counter", n]
+                | ["op", "add", "@counter", n, "@counter"] => {
+                    uint(n).or_else(|| {
+                        // 由`select`生成的`op mul tmp n len`
+                        let prev = self.lines.get(i.checked_sub(1)?)?;
+                        let prev = mdt_logic_split(prev.as_line()?).ok()?;
+                        // 被跳转到时无法得知tmp的值
+                        if line.tag().is_some() { return None }
+                        match prev[..] {
+                            ["op", "mul", tmp, a, b] if tmp == n => {
+                                uint(a)?.checked_mul(uint(b)?)
+                            },
+                            _ => None,
+                        }
+                    }).map(|n| i + 1 + n)
+                },
+                ["set", "@counter", n] => uint(n),
+                _ => None,
+            };
+            let Some(mut target) = offset else { continue };
+            if target >= self.lines.len() {
+                target = 0
+            }
+            let tag = *self.lines[target].tag_mut().get_or_insert_with(|| {
+                next_tag += 1;
+                next_tag - 1
+            });
+            let self_tag = self.lines[i].tag();
+            let mut jump = TagBox::new(self_tag, Jump::new_always(tag));
+            jump.set_origin(self.lines[i].origin().cloned());
+            self.lines[i] = jump.into();
+        }
+    }
+
+    /// 获取执行完一行后可能执行的各行, 需要已经进行了[`build_tagdown`]
+    ///
+    /// 执行完最后一行后回到第一行, 越界的跳转留给编译时报告
+    ///
     /// [`build_tagdown`]: `TagCodes::build_tagdown`
-    pub fn compile(&mut self) -> Result<Vec<String>, (usize, Tag)> {
-        self.build_tagdown()?; // 构建为行内跳转标记, 而不是`TagDown`
+    fn successors(&self, tags_table: &TagsTable, index: usize) -> Vec<usize> {
+        if self.lines.is_empty() { return vec![] }
+        let next = (index + 1) % self.lines.len();
+        match &self.lines[index] {
+            TagLine::Jump(jump) => {
+                let mut successors = Vec::with_capacity(2);
+                if jump.check_target(tags_table) {
+                    successors.push(tags_table[jump.0])
+                }
+                if !jump.is_always_jump() {
+                    successors.push(next)
+                }
+                successors
+            },
+            TagLine::Line(line) => {
+                match mdt_logic_split(line).unwrap_or_default()[..] {
+                    ["stop", ..] => vec![],
+                    ["end", ..] => vec![0],
+                    _ => vec![next],
+                }
+            },
+            TagLine::TagDown(_) => vec![next],
+        }
+    }
 
+    /// 构建各`Tag`到其所在行的表, 需要已经进行了[`build_tagdown`]
+    ///
+    /// [`build_tagdown`]: `TagCodes::build_tagdown`
+    fn build_tags_table(&self) -> TagsTable {
         let mut tags_table: TagsTable = TagsTable::new();
 
         for (num, code) in self.lines.iter().enumerate() {
@@ -768,33 +1060,31 @@ impl TagCodes {
             }
         }
 
+        tags_table
+    }
+
+    /// 编译为逻辑行码
+    /// 如果有重复的`Tag`或跳转到了不存在的`Tag`, 返回其行下标及`Tag`
+    /// 会调用[`build_tagdown`]来改变源码
+    ///
+    /// [`build_tagdown`]: `TagCodes::build_tagdown`
+    pub fn compile(&mut self) -> Result<Vec<String>, CompileTagCodesError> {
+        // 构建为行内跳转标记, 而不是`TagDown`
+        self.build_tagdown().map_err(|(line, tag)| {
+            CompileTagCodesError::DuplicateTag(line, tag)
+        })?;
+
+        let mut tags_table = self.build_tags_table();
         self.follow_always_jump_chain(&mut tags_table);
 
         let mut logic_lines = Vec::with_capacity(self.lines.len());
-        for line in &self.lines {
-            match catch_unwind(|| line.compile(&tags_table)) {
-                Ok(line) => logic_lines.push(line),
-                Err(_e) => {
-                    err!(
-                        concat!(
-                            "构建行时出现了恐慌, 全部须构建的行:\n{}\n",
-                            "已经构建完毕的行:\n{}\n",
-                            "恐慌的行:\n\t{}\n",
-                        ),
-                        self.lines()
-                            .iter()
-                            .map(|x| format!("\t{}", x))
-                            .collect::<Vec<_>>()
-                            .join("\n"),
-                        logic_lines.iter()
-                            .map(|x| format!("\t{}", x))
-                            .collect::<Vec<_>>()
-                            .join("\n"),
-                        line,
-                    );
-                    exit(8);
-                },
-            };
+        for (i, line) in self.lines.iter().enumerate() {
+            if let TagLine::Jump(jump) = line {
+                if !jump.check_target(&tags_table) {
+                    return Err(CompileTagCodesError::TagOutOfRange(i, jump.0));
+                }
+            }
+            logic_lines.push(line.compile(&tags_table));
         }
         Ok(logic_lines)
     }
@@ -912,6 +1202,12 @@ fn take_jump_body(line: &str) -> String {
         .collect()
 }
 
+/// 是否是一个编译器生成的临时变量, 例如`__0`
+pub fn is_tmp_var(name: &str) -> bool {
+    name.strip_prefix("__")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
 /// 按照Mindustry中的规则进行切分
 /// 也就是空白忽略, 字符串会被保留完整
 /// 如果出现未闭合字符串则会返回其所在字符数(从1开始)
@@ -956,13 +1252,6 @@ pub fn mdt_logic_split(s: &str) -> Result<Vec<&str>, usize> {
     }
     Ok(res)
 }
-#[must_use]
-pub fn mdt_logic_split_unwraped(s: &str) -> Vec<&str> {
-    mdt_logic_split(s).unwrap_or_else(|err_char_cl| {
-        err!("未闭合的字符串, 在第 {} 个字符处, 行: {}", err_char_cl, s);
-        exit(8)
-    })
-}
 
 #[cfg(test)]
 mod tests {
@@ -1538,4 +1827,272 @@ mod tests {
             assert_eq!(mdt_logic_split(src).unwrap_err(), char_num);
         }
     }
+
+    #[test]
+    fn eliminate_dead_code_test() {
+        let mut lines = tag_lines! {
+            ["a"];
+            [jump 0 "always 0 0"];
+            ["dead"];
+            [:1 "b"];
+            [:0 "c"];
+            [jump 1 "lessThan c 2"];
+            ["end"];
+            ["dead"];
+        };
+        assert_eq!(lines.eliminate_dead_code(), Ok(2));
+        assert_eq!(lines.compile().unwrap(), [
+            "a",
+            "jump 3 always 0 0",
+            "b",
+            "c",
+            "jump 2 lessThan c 2",
+            "end",
+        ]);
+
+        // 跳转链中被跳过的跳转
+        let mut lines = tag_lines! {
+            [jump 0 "always 0 0"];
+            [:0 jump 1 "always 0 0"];
+            ["dead"];
+            [:1 "a"];
+        };
+        assert_eq!(lines.eliminate_dead_code(), Ok(2));
+        assert_eq!(lines.compile().unwrap(), [
+            "jump 1 always 0 0",
+            "a",
+        ]);
+
+        // 静态的`@counter`跳转
+        let mut lines = tag_lines! {
+            ["op mul __0 1 2"];
+            ["op add @counter @counter __0"];
+            ["a"];
+            [jump 0 "always 0 0"];
+            ["b"];
+            [:0];
+            ["set @counter 0"];
+            ["dead"];
+        };
+        assert_eq!(lines.eliminate_dead_code(), Ok(3));
+        assert_eq!(lines.compile().unwrap(), [
+            "op mul __0 1 2",
+            "jump 2 always 0 0",
+            "b",
+            "jump 0 always 0 0",
+        ]);
+
+        // 动态的`@counter`写入时不进行删除
+        let mut lines = tag_lines! {
+            ["op add @counter @counter n"];
+            [jump 0 "always 0 0"];
+            ["a"];
+            [:0];
+            ["print @counter"];
+        };
+        assert_eq!(lines.eliminate_dead_code(), Ok(0));
+        assert_eq!(lines.count_no_tag(), 4);
+
+        // 没有任何行
+        let mut lines = TagCodes::new();
+        assert_eq!(lines.eliminate_dead_code(), Ok(0));
+        assert!(lines.compile().unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_instruction_test() {
+        assert_eq!(check_instruction(&[]), Ok(()));
+        assert_eq!(check_instruction(&["print", "1"]), Ok(()));
+        assert_eq!(check_instruction(&["jump", "0", "always"]), Ok(()));
+        assert_eq!(check_instruction(&["draw", "color", "1", "2", "3", "4"]), Ok(()));
+        assert_eq!(
+            check_instruction(&["draw", "colour", "1", "2", "3"]),
+            Err(InstructionError::UnknownSubCommand("draw", "colour".into())),
+        );
+        assert_eq!(
+            check_instruction(&["ucontrol"]),
+            Err(InstructionError::UnknownSubCommand("ucontrol", "".into())),
+        );
+        assert_eq!(
+            check_instruction(&["sensor", "a", "b"]),
+            Err(InstructionError::TooFewArgs("sensor", 3, 2)),
+        );
+        assert_eq!(
+            check_instruction(&["print", "1", "2"]),
+            Err(InstructionError::TooManyArgs("print", 1, 2)),
+        );
+    }
+
+    #[test]
+    fn origin_test() {
+        let origin = Origin { file: 1, span: (2, 3), ..Default::default() };
+        let mut lines = tag_lines! {
+            ["set @counter 2"];
+            ["dead"];
+            ["a"];
+        };
+        lines.lines_mut()[0].set_origin(Some(origin.clone()));
+        assert_eq!(lines.eliminate_dead_code(), Ok(1));
+        // 被转换为跳转的行保留了来源
+        assert!(lines.lines()[0].is_jump());
+        assert_eq!(lines.lines()[0].origin(), Some(&origin));
+        assert_eq!(lines.lines()[1].origin(), None);
+
+        let mut tag_down = TagLine::TagDown(0);
+        tag_down.set_origin(Some(origin));
+        assert_eq!(tag_down.origin(), None);
+    }
+
+    #[test]
+    fn peephole_test() {
+        let mut lines = tag_lines! {
+            ["set a b"];
+            ["set b a"];
+            ["set c c"];
+            ["op add x a b"];
+            ["op add x x 0"];
+            ["set y 2"];
+            ["op mul y 1 y"];
+            [jump 0 "lessThan x 1"];
+            [:0 "set __0 x"];
+            ["print __0"];
+            ["set __1 5"];
+            [jump 1 "lessThan __1 y"];
+            ["set __2 @time"];
+            ["print __2"];
+            ["set __3 1"];
+            ["read __3 cell1 0"];
+            [:1 "end"];
+        };
+        assert_eq!(Peephole::default().run(&mut lines), Ok(7));
+        assert_eq!(lines.compile().unwrap(), [
+            "set a b",
+            "op add x a b",
+            "set y 2",
+            "print x",
+            "jump 9 lessThan 5 y",
+            "set __2 @time",
+            "print __2",
+            "set __3 1",
+            "read __3 cell1 0",
+            "end",
+        ]);
+
+        // 不知道是否为数字时保留, 它会将`null`转换为`0`
+        let mut lines = tag_lines! {
+            ["op add x x 0"];
+            ["set y null"];
+            ["op mul y y 1"];
+            ["set z a"];
+            ["op sub z z 0"];
+            ["op add w a 1"];
+            ["read w cell1 0"];
+            ["op div w w 1"];
+            ["set v 1"];
+            [:0 "op add v v 0"];
+            [jump 0 "lessThan v 3"];
+        };
+        assert_eq!(Peephole::default().run(&mut lines), Ok(0));
+
+        // 删除被跳转到的行
+        let mut lines = tag_lines! {
+            [:0 jump 1 "always 0 0"];
+            [:1 "a"];
+            [jump 0 "always 0 0"];
+        };
+        assert_eq!(Peephole::default().run(&mut lines), Ok(2));
+        assert_eq!(lines.compile().unwrap(), ["a"]);
+
+        // 动态的`@counter`写入时不进行改写
+        let mut lines = tag_lines! {
+            ["op add @counter @counter n"];
+            ["set a a"];
+        };
+        assert_eq!(Peephole::default().run(&mut lines), Ok(0));
+        assert_eq!(Peephole::new().run(&mut tag_lines! { ["set a a"] }), Ok(0));
+    }
+
+    #[test]
+    fn reuse_tmp_vars_test() {
+        let mut lines = tag_lines! {
+            ["op add __0 a 1"];
+            ["print __0"];
+            ["op add __1 b 2"];
+            ["op mul __2 __1 __1"];
+            ["print __2"];
+            ["set __3 1"];
+            ["op add __4 __3 1"];
+            ["print __4"];
+            ["print __3"];
+        };
+        assert_eq!(lines.count_vars(), 7);
+        assert_eq!(lines.reuse_tmp_vars(), Ok(3));
+        assert_eq!(lines.count_vars(), 4);
+        assert_eq!(lines.compile().unwrap(), [
+            "op add __0 a 1",
+            "print __0",
+            "op add __0 b 2",
+            "op mul __0 __0 __0",
+            "print __0",
+            "set __0 1",
+            "op add __1 __0 1",
+            "print __1",
+            "print __0",
+        ]);
+
+        // 跨越跳转存活的临时变量
+        let mut lines = tag_lines! {
+            ["set __0 0"];
+            [:0 "op add __1 a 1"];
+            ["op add __0 __0 __1"];
+            [jump 0 "lessThan __0 10"];
+            ["print __0"];
+        };
+        assert_eq!(lines.reuse_tmp_vars(), Ok(0));
+
+        // 未知读写的参数与其它临时变量冲突
+        let mut lines = tag_lines! {
+            ["set __0 1"];
+            ["ucontrol getBlock x y __1 __2 0"];
+            ["print __0"];
+            ["print __1"];
+        };
+        assert_eq!(lines.reuse_tmp_vars(), Ok(0));
+
+        // 动态的`@counter`写入时不进行改写
+        let mut lines = tag_lines! {
+            ["op add @counter @counter n"];
+            ["set __0 1"];
+            ["set __1 2"];
+        };
+        assert_eq!(lines.reuse_tmp_vars(), Ok(0));
+    }
+
+    #[test]
+    fn compile_error_test() {
+        assert_eq!(
+            tag_lines! {
+                ["a"];
+                [jump 3 "always 0 0"];
+            }.compile(),
+            Err(CompileTagCodesError::TagOutOfRange(1, 3)),
+        );
+        assert_eq!(
+            tag_lines! {
+                [:0 jump 1 "always 0 0"];
+                [jump 2 "always 0 0"];
+                [:1 "b"];
+            }.compile(),
+            Err(CompileTagCodesError::TagOutOfRange(1, 2)),
+        );
+        assert_eq!(
+            tag_lines! {
+                [:0];
+                ["a"];
+                [:0];
+                ["b"];
+            }.compile(),
+            Err(CompileTagCodesError::DuplicateTag(2, 0)),
+        );
+    }
 }
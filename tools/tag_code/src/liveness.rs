@@ -0,0 +1,180 @@
+//! 基于活跃性分析复用编译器生成的临时变量
+//!
+//! 每个DExp都会获取一个新的`__N`临时变量, 而它们大多只存活很短的范围,
+//! 生存范围不重叠的临时变量可以使用同一个名称, 以减少处理器中的变量数
+
+use std::{
+    collections::{HashMap, HashSet},
+    iter::zip,
+};
+
+use var_utils::AsVarType;
+
+use crate::{
+    arg_roles,
+    is_tmp_var,
+    mdt_logic_split,
+    ArgRole,
+    CompileTagCodesError,
+    TagCodes,
+    TagLine,
+};
+
+/// 获取一行的各参数及其作用, 对于跳转为其条件部分
+fn line_args(line: &TagLine) -> Option<(Vec<&str>, Vec<ArgRole>)> {
+    let (args, roles) = match line {
+        TagLine::Jump(jump) => {
+            let args = mdt_logic_split(&jump.1).ok()?;
+            let roles = vec![ArgRole::Input; args.len().saturating_sub(1)];
+            (args, roles)
+        },
+        TagLine::Line(line) => {
+            let args = mdt_logic_split(line).ok()?;
+            let roles = arg_roles(&args);
+            (args, roles)
+        },
+        TagLine::TagDown(_) => return None,
+    };
+    // 语句名或跳转条件不是变量
+    let roles = [ArgRole::Keyword].into_iter()
+        .chain(roles)
+        .take(args.len())
+        .collect();
+    Some((args, roles))
+}
+
+/// 是否是一个变量, 而不是字面量或者`@`开头的内建变量
+fn is_var(arg: &str) -> bool {
+    !arg.starts_with(['@', '"'])
+        && !matches!(arg, "null" | "true" | "false")
+        && !arg.as_var_type().is_number()
+}
+
+impl TagCodes {
+    /// 复用生存范围不重叠的`__N`临时变量, 返回减少的临时变量数量
+    /// 会调用[`build_tagdown`]来改变源码
+    ///
+    /// 如果存在写入`@counter`等无法静态得知目标的跳转, 那么不会进行任何改写
+    ///
+    /// [`build_tagdown`]: `TagCodes::build_tagdown`
+    pub fn reuse_tmp_vars(&mut self) -> Result<usize, CompileTagCodesError> {
+        self.build_tagdown().map_err(|(line, tag)| {
+            CompileTagCodesError::DuplicateTag(line, tag)
+        })?;
+        if self.lines.iter().any(TagLine::is_dynamic_jump) {
+            return Ok(0);
+        }
+        let Some(lines) = self.lines.iter()
+            .map(line_args)
+            .collect::<Option<Vec<_>>>()
+        else { return Ok(0) };
+
+        // 为临时变量编号, 并获取每行读取与写入的临时变量
+        let mut tmps: Vec<&str> = Vec::new();
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+        let mut uses = vec![Vec::new(); lines.len()];
+        let mut defs = vec![Vec::new(); lines.len()];
+        for (i, (args, roles)) in lines.iter().enumerate() {
+            for (&arg, &role) in zip(args, roles) {
+                if !is_tmp_var(arg) { continue }
+                let id = *ids.entry(arg).or_insert_with(|| {
+                    tmps.push(arg);
+                    tmps.len() - 1
+                });
+                match role {
+                    ArgRole::Output => defs[i].push(id),
+                    ArgRole::Input => uses[i].push(id),
+                    // 未知读写的参数需要同时视为读取与写入
+                    ArgRole::Unknown => {
+                        uses[i].push(id);
+                        defs[i].push(id);
+                    },
+                    ArgRole::Keyword => (),
+                }
+            }
+        }
+        if tmps.is_empty() {
+            return Ok(0);
+        }
+
+        let tags_table = self.build_tags_table();
+        let successors = (0..lines.len())
+            .map(|i| self.successors(&tags_table, i))
+            .collect::<Vec<_>>();
+        let mut live_in: Vec<HashSet<usize>> = vec![HashSet::new(); lines.len()];
+        let mut live_out: Vec<HashSet<usize>> = vec![HashSet::new(); lines.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..lines.len()).rev() {
+                let out = successors[i].iter()
+                    .flat_map(|&next| live_in[next].iter().copied())
+                    .collect::<HashSet<_>>();
+                let mut live = out.iter()
+                    .filter(|tmp| !defs[i].contains(tmp))
+                    .copied()
+                    .collect::<HashSet<_>>();
+                live.extend(&uses[i]);
+                if live != live_in[i] {
+                    live_in[i] = live;
+                    changed = true;
+                }
+                live_out[i] = out;
+            }
+        }
+
+        // 被写入时与其它活跃的或同时被写入的临时变量冲突
+        let mut conflicts = vec![HashSet::new(); tmps.len()];
+        for (defs, live_out) in zip(&defs, &live_out) {
+            for &def in defs {
+                for &other in live_out.iter().chain(defs) {
+                    if other != def {
+                        conflicts[def].insert(other);
+                        conflicts[other].insert(def);
+                    }
+                }
+            }
+        }
+        let mut colors: Vec<usize> = Vec::with_capacity(tmps.len());
+        for conflicts in &conflicts {
+            let used = conflicts.iter()
+                .filter_map(|&other| colors.get(other))
+                .collect::<HashSet<_>>();
+            colors.push((0..).find(|color| !used.contains(color)).unwrap());
+        }
+        let count = colors.iter().max().map_or(0, |max| max + 1);
+        let saved = tmps.len() - count;
+
+        let names: HashMap<String, String> = zip(&tmps, &colors)
+            .map(|(&tmp, color)| (tmp.to_owned(), format!("__{color}")))
+            .collect();
+        for line in &mut self.lines {
+            let body = match line {
+                TagLine::Jump(jump) => &mut jump.1,
+                TagLine::Line(line) => &mut **line,
+                TagLine::TagDown(_) => continue,
+            };
+            let args = mdt_logic_split(body).unwrap();
+            if !args.iter().any(|arg| names.contains_key(*arg)) {
+                continue;
+            }
+            let new = args.iter()
+                .map(|&arg| names.get(arg).map_or(arg, String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ");
+            *body = new;
+        }
+        Ok(saved)
+    }
+
+    /// 获取程序中使用的不同变量的数量, 不包括字面量与`@`开头的内建变量
+    pub fn count_vars(&self) -> usize {
+        self.lines.iter()
+            .filter_map(line_args)
+            .flat_map(|(args, roles)| zip(args, roles))
+            .filter(|&(arg, role)| role != ArgRole::Keyword && is_var(arg))
+            .map(|(arg, _)| arg)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
@@ -0,0 +1,301 @@
+//! 对已构建[`TagDown`]的行进行的窥孔优化
+//!
+//! 每条规则实现[`PeepholeRule`], 由[`Peephole`]在每一行上依次尝试,
+//! 直到没有规则可以再进行改写
+//!
+//! [`TagDown`]: crate::TagLine::TagDown
+
+use var_utils::AsVarType;
+
+use crate::{
+    arg_roles,
+    is_tmp_var,
+    mdt_logic_split,
+    ArgRole,
+    CompileTagCodesError,
+    InstructionInfo,
+    TagCodes,
+    TagLine,
+};
+
+/// 一条窥孔优化规则
+pub trait PeepholeRule {
+    /// 规则的名称
+    fn name(&self) -> &'static str;
+
+    /// 尝试改写从`index`开始的行, 返回是否进行了改写
+    ///
+    /// 调用时已经没有[`TagDown`], 也没有目标无法静态得知的跳转,
+    /// 删除行时需要使用[`TagCodes::remove_line`]来保留跳转到它的`Tag`,
+    /// 且不能改变被跳转到的行的行为
+    ///
+    /// [`TagDown`]: crate::TagLine::TagDown
+    fn rewrite(&self, codes: &mut TagCodes, index: usize) -> bool;
+}
+
+/// 一组窥孔优化规则
+pub struct Peephole {
+    rules: Vec<Box<dyn PeepholeRule>>,
+}
+impl Default for Peephole {
+    /// 带有此模块中所有规则
+    fn default() -> Self {
+        Self::new()
+            .with_rule(RedundantSet)
+            .with_rule(JumpToNext)
+            .with_rule(OpIdentity)
+            .with_rule(SingleUseTemp)
+    }
+}
+impl std::fmt::Debug for Peephole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.rules.iter().map(|rule| rule.name()))
+            .finish()
+    }
+}
+impl Peephole {
+    /// 不带有任何规则
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// 添加一条规则, 规则按照添加的顺序尝试
+    pub fn with_rule(mut self, rule: impl PeepholeRule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    /// 对行进行优化, 返回改写的次数
+    /// 会调用[`build_tagdown`]来改变源码
+    ///
+    /// 如果存在写入`@counter`等无法静态得知目标的跳转,
+    /// 那么删除行会改变其跳转目标, 此时不会进行任何改写
+    ///
+    /// [`build_tagdown`]: `TagCodes::build_tagdown`
+    pub fn run(&self, codes: &mut TagCodes) -> Result<usize, CompileTagCodesError> {
+        codes.build_tagdown().map_err(|(line, tag)| {
+            CompileTagCodesError::DuplicateTag(line, tag)
+        })?;
+        if codes.iter().any(TagLine::is_dynamic_jump) {
+            return Ok(0);
+        }
+
+        let mut count = 0;
+        let mut i = 0;
+        while i < codes.len() {
+            if self.rules.iter().any(|rule| rule.rewrite(codes, i)) {
+                count += 1;
+                // 改写后上一行可能与新的行组成新的模式
+                i = i.saturating_sub(1);
+            } else {
+                i += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// 获取一个普通行的各参数
+fn line_args(line: &TagLine) -> Option<Vec<&str>> {
+    mdt_logic_split(line.as_line()?).ok()
+}
+
+/// 获取下一行, 如果下一行会被跳转到则返回[`None`]
+fn next_untagged(codes: &TagCodes, index: usize) -> Option<&TagLine> {
+    codes.lines().get(index + 1).filter(|line| line.tag().is_none())
+}
+
+/// 删除`set a a`, 以及`set a b`之后的`set b a`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RedundantSet;
+impl PeepholeRule for RedundantSet {
+    fn name(&self) -> &'static str {
+        "redundant_set"
+    }
+
+    fn rewrite(&self, codes: &mut TagCodes, index: usize) -> bool {
+        let Some(args) = line_args(&codes.lines()[index]) else { return false };
+        let remove = match args[..] {
+            ["set", a, b] if a == b => index,
+            ["set", a, b] => {
+                let Some(next) = next_untagged(codes, index)
+                    .and_then(line_args)
+                else { return false };
+                if next[..] != ["set", b, a] { return false }
+                index + 1
+            },
+            _ => return false,
+        };
+        codes.remove_line(remove);
+        true
+    }
+}
+
+/// 删除跳转到下一行的跳转, 跳转条件没有副作用所以也包括条件跳转
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JumpToNext;
+impl PeepholeRule for JumpToNext {
+    fn name(&self) -> &'static str {
+        "jump_to_next"
+    }
+
+    fn rewrite(&self, codes: &mut TagCodes, index: usize) -> bool {
+        let lines = codes.lines();
+        let Some(jump) = lines[index].as_jump() else { return false };
+        // 最后一行的下一行为第一行
+        let next = (index + 1) % lines.len();
+        if next == index || lines[next].tag() != Some(jump.0) {
+            return false;
+        }
+        codes.remove_line(index);
+        true
+    }
+}
+
+/// 删除加减零与乘除一的运算, 例如`op add x x 0`
+///
+/// 这样的运算会将`null`等非数字的值转换为数字, 所以仅在同一直线区域中
+/// 之前的行已经将数字写入了`x`时才删除, 见[`OpIdentity::is_number_before`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpIdentity;
+impl OpIdentity {
+    /// 在`index`之前的同一直线区域中, 最近一个写入`x`的行是否写入了数字
+    ///
+    /// 写入的行为`op`或者`set`一个非`null`的数字字面量时成立,
+    /// 遇到被跳转到的行或者无法得知是否写入时不成立
+    fn is_number_before(codes: &TagCodes, index: usize, x: &str) -> bool {
+        let lines = codes.lines();
+        if lines[index].tag().is_some() { return false }
+        for line in lines[..index].iter().rev() {
+            if let Some(args) = line_args(line) {
+                match args[..] {
+                    ["op", _, res, ..] if res == x => return true,
+                    ["set", res, value] if res == x => {
+                        return value != "null"
+                            && value.as_var_type().is_number();
+                    },
+                    _ => (),
+                }
+                let written = args.iter().skip(1)
+                    .zip(arg_roles(&args))
+                    .any(|(&arg, role)| {
+                        arg == x && matches!(role, ArgRole::Output | ArgRole::Unknown)
+                    });
+                if written { return false }
+            }
+            if line.tag().is_some() { return false }
+        }
+        false
+    }
+}
+impl PeepholeRule for OpIdentity {
+    fn name(&self) -> &'static str {
+        "op_identity"
+    }
+
+    fn rewrite(&self, codes: &mut TagCodes, index: usize) -> bool {
+        let Some(args) = line_args(&codes.lines()[index]) else { return false };
+        let is_identity = match args[..] {
+            | ["op", "add" | "sub", x, a, "0"]
+            | ["op", "add", x, "0", a]
+            | ["op", "mul" | "div", x, a, "1"]
+            | ["op", "mul", x, "1", a]
+            => x == a && Self::is_number_before(codes, index, x),
+            _ => false,
+        };
+        if is_identity {
+            codes.remove_line(index);
+        }
+        is_identity
+    }
+}
+
+/// 将只在下一行被读取一次的`set __N value`中的值直接代入下一行
+///
+/// 仅处理参数的读写位置已知的语句,
+/// 并且不代入在两行之间可能发生变化的值, 例如`@time`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SingleUseTemp;
+impl SingleUseTemp {
+    /// 会随着执行而变化的内建变量
+    const VOLATILE_VARS: &'static [&'static str] = &[
+        "@counter",
+        "@time",
+        "@tick",
+        "@second",
+        "@minute",
+        "@unit",
+        "@waveNumber",
+        "@waveTime",
+    ];
+
+    /// 获取一行的各参数, 以及其中作为输入的参数下标
+    ///
+    /// 跳转的参数为其条件部分, 未知读写位置的语句返回[`None`]
+    fn args_and_inputs(line: &TagLine) -> Option<(Vec<&str>, std::ops::Range<usize>)> {
+        if let Some(jump) = line.as_jump() {
+            let args = mdt_logic_split(&jump.1).ok()?;
+            let len = args.len();
+            return Some((args, 1..len));
+        }
+        let args = line_args(line)?;
+        let inputs = match args[..] {
+            ["set", ..] => 2..3,
+            ["op", ..] => 3..5,
+            [name, ..] if InstructionInfo::find(name)
+                .is_some_and(InstructionInfo::is_read_only)
+                => 1..args.len(),
+            _ => return None,
+        };
+        Some((args, inputs))
+    }
+}
+impl PeepholeRule for SingleUseTemp {
+    fn name(&self) -> &'static str {
+        "single_use_temp"
+    }
+
+    fn rewrite(&self, codes: &mut TagCodes, index: usize) -> bool {
+        let Some(args) = line_args(&codes.lines()[index]) else { return false };
+        let ["set", temp, value] = args[..] else { return false };
+        if !is_tmp_var(temp)
+            || temp == value
+            || Self::VOLATILE_VARS.contains(&value)
+        {
+            return false;
+        }
+        let Some((next, inputs)) = next_untagged(codes, index)
+            .and_then(Self::args_and_inputs)
+        else { return false };
+        let uses = next.iter()
+            .enumerate()
+            .filter(|&(_, &arg)| arg == temp)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let &[used] = &uses[..] else { return false };
+        if !inputs.contains(&used) { return false }
+
+        // 除了这两行以外不能在其它地方被使用
+        let total = codes.iter()
+            .filter_map(|line| match line {
+                TagLine::Jump(jump) => mdt_logic_split(&jump.1).ok(),
+                line => line_args(line),
+            })
+            .flatten()
+            .filter(|&arg| arg == temp)
+            .count();
+        if total != 2 { return false }
+
+        let mut next = next;
+        next[used] = value;
+        let next = next.join(" ");
+        match &mut codes.lines_mut()[index + 1] {
+            TagLine::Jump(jump) => jump.1 = next,
+            TagLine::Line(line) => **line = next,
+            TagLine::TagDown(_) => unreachable!(),
+        }
+        codes.remove_line(index);
+        true
+    }
+}
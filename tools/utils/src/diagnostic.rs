@@ -0,0 +1,456 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Write},
+    rc::Rc,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{msg, message::Message};
+
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+/// 诊断信息的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MessageFormat {
+    /// 带有颜色与源码片段的文本
+    #[default]
+    Human,
+    /// 每行一个JSON对象
+    Json,
+}
+impl MessageFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(Self::Human),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// 获取当前诊断信息的输出格式
+pub fn message_format() -> MessageFormat {
+    if JSON_FORMAT.load(Ordering::Relaxed) {
+        MessageFormat::Json
+    } else {
+        MessageFormat::Human
+    }
+}
+
+/// 设置之后[`Diagnostic::emit`]所使用的输出格式
+pub fn set_message_format(format: MessageFormat) {
+    JSON_FORMAT.store(format == MessageFormat::Json, Ordering::Relaxed)
+}
+
+/// 诊断信息的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Info => "info",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Self::Error => "\x1b[1;31m",
+            Self::Warning => "\x1b[1;33m",
+            Self::Info => "\x1b[1;36m",
+        }
+    }
+}
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// 一条诊断信息, 可以渲染为带有源码片段与下划线的形式
+/// # Examples
+/// ```
+/// # use utils::diagnostic::{Diagnostic, Source};
+/// let src = "print a b c\n";
+/// let diag = Diagnostic::error("unexpected token")
+///     .with_span(8, 9)
+///     .with_source(Source::new(Some("a.mdtlbl".into()), src))
+///     .with_note("expected: [\";\"]");
+/// let out = diag.render(false);
+/// assert_eq!(out, "\
+/// error: unexpected token
+///  --> a.mdtlbl:1:9
+///   |
+/// 1 | print a b c
+///   |         ^
+///   = expected: [\";\"]
+/// ");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// 在源码中的字节范围
+    pub span: Option<(usize, usize)>,
+    /// 范围所在的源码
+    pub source: Option<Source>,
+    /// 附加在末尾的说明, 例如预期的令牌
+    pub notes: Vec<String>,
+    /// 产生诊断时的const展开栈, 由外至内
+    pub expand_stack: Vec<String>,
+    /// 信息的种类, 以便使用者区分不同的错误, 为空时表示未分类的信息
+    pub kind: Option<Message>,
+}
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            span: None,
+            source: None,
+            notes: Vec::new(),
+            expand_stack: Vec::new(),
+            kind: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    pub fn info(message: impl Into<String>) -> Self {
+        Self::new(Severity::Info, message)
+    }
+
+    pub fn with_span(mut self, start: usize, end: usize) -> Self {
+        self.span = Some((start, end));
+        self
+    }
+
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_kind(mut self, kind: Message) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_expand_stack<I>(mut self, stack: I) -> Self
+    where I: IntoIterator,
+          I::Item: Into<String>,
+    {
+        self.expand_stack = stack.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// 渲染为类似rustc的形式, 带有源码片段及下划线
+    ///
+    /// 如果没有给定源码或范围, 那么将不显示源码片段
+    pub fn render(&self, color: bool) -> String {
+        let source = self.source.as_ref();
+        let paint = |style: &str, s: &str| if color {
+            format!("{style}{s}\x1b[0m")
+        } else {
+            s.into()
+        };
+        const GUTTER: &str = "\x1b[1;34m";
+
+        let mut lines = self.message.lines();
+        let mut out = format!(
+            "{}{}\n",
+            paint(self.severity.color(), self.severity.name()),
+            paint("\x1b[1m", &format!(": {}", lines.next().unwrap_or_default())),
+        );
+
+        let snippet = source.zip(self.span).and_then(|(source, (start, end))| {
+            let [[line, column], [end_line, end_column]]
+                = get_locations(&source.src, [start, end]);
+            if line == 0 {
+                // 范围超出了源码
+                return None;
+            }
+            let line_src = source.src.lines().nth(line.saturating_sub(1))
+                .unwrap_or_default();
+            let width = if end_line == line && end_column > column {
+                end_column - column
+            } else {
+                line_src.chars().count().saturating_sub(column - 1).max(1)
+            };
+            (source, line, column, line_src, width).into()
+        });
+        let gutter_width = snippet.as_ref()
+            .map_or(1, |&(_, line, ..)| line.to_string().len());
+        let pad = " ".repeat(gutter_width);
+
+        if let Some((source, line, column, line_src, width)) = snippet {
+            out.push_str(&format!(
+                "{pad}{} {}:{line}:{column}\n",
+                paint(GUTTER, "-->"),
+                source.path.as_deref().unwrap_or("<stdin>"),
+            ));
+            out.push_str(&format!("{pad} {}\n", paint(GUTTER, "|")));
+            let src_line = format!(
+                "{} {line_src}",
+                paint(GUTTER, &format!("{line} |")),
+            );
+            out.push_str(src_line.trim_end());
+            out.push('\n');
+            out.push_str(&format!(
+                "{pad} {} {}{}\n",
+                paint(GUTTER, "|"),
+                " ".repeat(column - 1),
+                paint(self.severity.color(), &"^".repeat(width)),
+            ));
+        } else if let Some(path) = source.and_then(|s| s.path.as_deref()) {
+            out.push_str(&format!("{pad}{} {path}\n", paint(GUTTER, "-->")));
+        }
+
+        // 消息的后续行也作为说明显示
+        let mut notes: Vec<String> = lines
+            .map(Into::into)
+            .chain(self.notes.iter().cloned())
+            .collect();
+        if !self.expand_stack.is_empty() {
+            let stack = self.expand_stack.join(" -> ");
+            notes.push(msg!(ExpandStackNote, stack));
+        }
+        for note in notes {
+            let mut note_lines = note.lines();
+            out.push_str(&format!(
+                "{pad} {} {}\n",
+                paint(GUTTER, "="),
+                note_lines.next().unwrap_or_default(),
+            ));
+            for note_line in note_lines {
+                out.push_str(&format!("{pad}   {note_line}\n"));
+            }
+        }
+        out
+    }
+
+    /// 转换为单行的JSON对象
+    ///
+    /// 范围的行列号从源码中计算, 如果没有给定源码或范围则为`null`,
+    /// 信息的种类使用[`Message::name`]
+    ///
+    /// # Examples
+    /// ```
+    /// # use utils::{diagnostic::{Diagnostic, Source}, message::Message};
+    /// let diag = Diagnostic::error("unexpected \"c\"")
+    ///     .with_span(10, 11)
+    ///     .with_source(Source::new(Some("a.mdtlbl".into()), "print a b c"))
+    ///     .with_expand_stack(["A"])
+    ///     .with_kind(Message::UnexpectedToken);
+    /// let json = diag.to_json();
+    /// assert_eq!(json, concat!(
+    ///     r#"{"severity":"error","message":"unexpected \"c\"","#,
+    ///     r#""file":"a.mdtlbl","#,
+    ///     r#""span":{"start":10,"end":11,"line":1,"column":11,"end_line":1,"end_column":12},"#,
+    ///     r#""notes":[],"expand_stack":["A"],"kind":"UnexpectedToken"}"#,
+    /// ));
+    /// ```
+    pub fn to_json(&self) -> String {
+        let source = self.source.as_ref();
+        let mut out = format!(
+            "{{\"severity\":{},\"message\":{},\"file\":",
+            json_str(self.severity.name()),
+            json_str(&self.message),
+        );
+        match source.and_then(|source| source.path.as_deref()) {
+            Some(path) => out.push_str(&json_str(path)),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"span\":");
+        match self.span {
+            Some((start, end)) => {
+                let [[line, column], [end_line, end_column]] = source
+                    .map_or([[0, 0]; 2], |source| {
+                        get_locations(&source.src, [start, end])
+                    });
+                let loc = |n: usize| if n == 0 {
+                    "null".into()
+                } else {
+                    n.to_string()
+                };
+                write!(
+                    out,
+                    "{{\"start\":{start},\"end\":{end},\
+                    \"line\":{},\"column\":{},\
+                    \"end_line\":{},\"end_column\":{}}}",
+                    loc(line),
+                    loc(column),
+                    loc(end_line),
+                    loc(end_column),
+                ).unwrap();
+            },
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"notes\":");
+        out.push_str(&json_array(&self.notes));
+        out.push_str(",\"expand_stack\":");
+        out.push_str(&json_array(&self.expand_stack));
+        out.push_str(",\"kind\":");
+        match self.kind {
+            Some(kind) => out.push_str(&json_str(kind.name())),
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        out
+    }
+
+    /// 按照当前的[`MessageFormat`]输出到标准错误
+    pub fn emit(&self) {
+        match message_format() {
+            MessageFormat::Human => eprint!("{}", self.render(true)),
+            MessageFormat::Json => eprintln!("{}", self.to_json()),
+        }
+    }
+}
+
+/// 将字符串转义为JSON字符串字面量
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if ch.is_control() => {
+                write!(out, "\\u{:04x}", ch as u32).unwrap();
+            },
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_array(items: &[String]) -> String {
+    let items = items.iter()
+        .map(|item| json_str(item))
+        .collect::<Vec<_>>();
+    format!("[{}]", items.join(","))
+}
+
+/// 诊断所指向的源码, 多条诊断可以共享同一份源码
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    /// 源码所在的文件, 为空时视作标准输入
+    pub path: Option<String>,
+    pub src: Rc<str>,
+}
+impl Source {
+    pub fn new(path: Option<String>, src: impl Into<Rc<str>>) -> Self {
+        Self { path, src: src.into() }
+    }
+}
+
+/// 给定位置与源码, 返回行列号, 行列都从1开始<br/>
+/// 如果没有找到, 则返回`[0, 0]`
+pub fn get_locations<const N: usize>(src: &str, indexs: [usize; N]) -> [[usize; 2]; N] {
+    const CR: char = '\n';
+
+    let mut index_maps: HashMap<usize, Vec<usize>> = HashMap::with_capacity(indexs.len());
+    for (i, loc) in indexs.into_iter().enumerate() {
+        index_maps.entry(loc).or_default().push(i)
+    }
+    let mut res = [[0, 0]; N];
+    let [mut line, mut column] = [1, 1];
+    macro_rules! set {
+        ($i:expr) => {
+            if let Some(idxs) = index_maps.get(&$i) {
+                for &idx in idxs {
+                    res[idx] = [line, column]
+                }
+            };
+        };
+    }
+    for (i, ch) in src.char_indices() {
+        set!(i);
+        if ch == CR {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    set!(src.len());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_test() {
+        crate::locale::set_locale(crate::locale::Locale::EnUS);
+        let src = "a\nb\nc\nd\ne\nf\ng\nh\ni\nprint x y\n";
+        let source = Source::new(None, src);
+        let diag = Diagnostic::warning("multi\nline")
+            .with_span(src.find('x').unwrap(), src.len())
+            .with_source(source.clone())
+            .with_expand_stack(["A", "B"]);
+        assert_eq!(diag.render(false), "\
+warning: multi
+  --> <stdin>:10:7
+   |
+10 | print x y
+   |       ^^^
+   = line
+   = expand stack: A -> B
+");
+
+        let diag = Diagnostic::error("eof")
+            .with_span(src.len(), src.len())
+            .with_source(source.clone());
+        assert_eq!(diag.render(false), "\
+error: eof
+  --> <stdin>:11:1
+   |
+11 |
+   | ^
+");
+
+        let diag = Diagnostic::warning("a\tb\u{1}")
+            .with_note("c\\d\ne");
+        assert_eq!(
+            diag.to_json(),
+            r#"{"severity":"warning","message":"a\tb\u0001","file":null,"span":null,"notes":["c\\d\ne"],"expand_stack":[],"kind":null}"#,
+        );
+        let diag = diag.with_span(100, 101).with_source(source);
+        assert_eq!(
+            diag.to_json(),
+            r#"{"severity":"warning","message":"a\tb\u0001","file":null,"span":{"start":100,"end":101,"line":null,"column":null,"end_line":null,"end_column":null},"notes":["c\\d\ne"],"expand_stack":[],"kind":null}"#,
+        );
+
+        let diag = Diagnostic::info("no source").with_span(0, 1);
+        assert_eq!(diag.render(false), "info: no source\n");
+        let diag = diag
+            .with_span(100, 101)
+            .with_source(Source::new(Some("a".into()), src));
+        assert_eq!(
+            diag.render(false),
+            "info: no source\n --> a\n",
+        );
+    }
+}
@@ -1,3 +1,6 @@
 //! 一系列通用工具
 
 pub mod counter;
+pub mod diagnostic;
+pub mod locale;
+pub mod message;
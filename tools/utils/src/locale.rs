@@ -0,0 +1,77 @@
+//! 用户可见信息所使用的语言
+//!
+//! 语言可以由[`set_locale`]设置,
+//! 否则在第一次使用时从环境变量`LC_ALL`, `LC_MESSAGES`, `LANG`中选择
+
+use std::{
+    env::var,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+const UNINIT: u8 = u8::MAX;
+static LOCALE: AtomicU8 = AtomicU8::new(UNINIT);
+
+/// 信息所使用的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    #[default]
+    ZhCN,
+    EnUS,
+}
+impl Locale {
+    pub const ALL: [Self; 2] = [Self::ZhCN, Self::EnUS];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ZhCN => "zh_CN",
+            Self::EnUS => "en_US",
+        }
+    }
+
+    /// 从例如`zh_CN.UTF-8`的名称中识别, 只关心其语言部分
+    ///
+    /// # Examples
+    /// ```
+    /// # use utils::locale::Locale;
+    /// assert_eq!(Locale::from_name("zh_CN.UTF-8"), Some(Locale::ZhCN));
+    /// assert_eq!(Locale::from_name("en_US"), Some(Locale::EnUS));
+    /// assert_eq!(Locale::from_name("en"), Some(Locale::EnUS));
+    /// assert_eq!(Locale::from_name("C"), Some(Locale::EnUS));
+    /// assert_eq!(Locale::from_name("fr_FR"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        let lang = name.split(['_', '-', '.', '@']).next()?;
+        match &*lang.to_ascii_lowercase() {
+            "zh" => Some(Self::ZhCN),
+            "en" | "c" | "posix" => Some(Self::EnUS),
+            _ => None,
+        }
+    }
+
+    /// 从环境变量中选择, 如果均未设置或无法识别, 那么使用默认语言
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"].into_iter()
+            .filter_map(|name| var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::from_name(&value))
+            .unwrap_or_default()
+    }
+
+    fn from_u8(n: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(n)).copied()
+    }
+}
+
+/// 获取当前使用的语言
+pub fn locale() -> Locale {
+    Locale::from_u8(LOCALE.load(Ordering::Relaxed)).unwrap_or_else(|| {
+        let locale = Locale::from_env();
+        set_locale(locale);
+        locale
+    })
+}
+
+/// 设置之后所有信息使用的语言
+pub fn set_locale(locale: Locale) {
+    LOCALE.store(locale as u8, Ordering::Relaxed)
+}
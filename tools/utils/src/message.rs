@@ -0,0 +1,562 @@
+//! 用户可见信息的文本表
+//!
+//! 每种信息对应[`Message`]中的一项, 并为每种语言给出一个模板,
+//! 模板中的`{0}`, `{1}`等会被替换为对应下标的参数, `{{`与`}}`表示花括号本身
+//!
+//! 添加一种语言时, 只需要在[`Locale`]中添加它, 然后在这个表中为各项添加模板,
+//! 没有给出模板的项将使用默认语言的模板
+
+use std::fmt::Display;
+
+use crate::locale::{locale, Locale};
+
+macro_rules! messages {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident { $($lang:ident: $text:literal),+ $(,)? }
+    )*) => {
+        /// 用户可见信息的种类
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Message {
+            $($(#[$meta])* $name,)*
+        }
+        impl Message {
+            /// 这种信息的名称, 与其在[`Message`]中的名称相同
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Self::$name => stringify!($name),)*
+                }
+            }
+
+            fn find_template(self, locale: Locale) -> Option<&'static str> {
+                match (self, locale) {
+                    $($(
+                        (Self::$name, Locale::$lang) => Some($text),
+                    )+)*
+                    #[allow(unreachable_patterns)]
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+messages! {
+    // 语法与编译
+    NotALiteralUInteger {
+        ZhCN: "{0} 不是一个有效的无符号整数, 错误: {1}",
+        EnUS: "{0} is not a valid unsigned integer, error: {1}",
+    }
+    SetsCountMismatch {
+        ZhCN: "sets两侧值数量不匹配, {0} != {1}",
+        EnUS: "sets count mismatch on both sides, {0} != {1}",
+    }
+    ArgsRepeatChunkByZero {
+        ZhCN: "重复块的迭代数不能为0",
+        EnUS: "the repeat count of a repeat block cannot be 0",
+    }
+    ImportUnsupported {
+        ZhCN: "当前环境不支持导入",
+        EnUS: "import is not supported in the current environment",
+    }
+    ImportReadFailed {
+        ZhCN: "读取被导入的文件 {0} 失败, 错误: {1}",
+        EnUS: "failed to read imported file {0}, error: {1}",
+    }
+    ImportCycle {
+        ZhCN: "循环导入: {0}",
+        EnUS: "import cycle: {0}",
+    }
+    ImportParseFailed {
+        ZhCN: "解析被导入的文件 {0} 失败",
+        EnUS: "failed to parse imported file {0}",
+    }
+    ImportStdNotFound {
+        ZhCN: "标准库中不存在模块 {0}",
+        EnUS: "no module {0} in the std library",
+    }
+    FnRedefined {
+        ZhCN: "重复定义的函数 {0}",
+        EnUS: "function {0} is already defined",
+    }
+    FnInConst {
+        ZhCN: "函数 {0} 不能定义在const或者其它函数之中",
+        EnUS: "function {0} cannot be defined inside a const or another function",
+    }
+    FnArgsCountMismatch {
+        ZhCN: "函数 {0} 需要 {1} 个参数, 但传入了 {2} 个",
+        EnUS: "function {0} takes {1} arguments, but {2} were given",
+    }
+    RecordFieldRepeated {
+        ZhCN: "记录中重复的字段 {0}",
+        EnUS: "field {0} is repeated in the record",
+    }
+    RecordLayoutUndefined {
+        ZhCN: "未定义的记录布局 {0}",
+        EnUS: "record layout {0} is not defined",
+    }
+    UnexpandedCmper {
+        ZhCN: "最终未被展开的cmper",
+        EnUS: "cmper is never expanded",
+    }
+    DExpResultNotVar {
+        ZhCN: "尝试在`DExp`的返回句柄处使用值不为Var的const, 此处仅允许使用`Var`",
+        EnUS: "a const whose value is not a Var is used as \
+               the result handle of `DExp`, only `Var` is allowed here",
+    }
+    OutOfDExp {
+        ZhCN: "尝试在`DExp`的外部使用{0}",
+        EnUS: "{0} is used outside of `DExp`",
+    }
+    ExpandDepthExceeded {
+        ZhCN: "超出最大展开深度 ({0})",
+        EnUS: "maximum expand depth exceeded ({0})",
+    }
+    Exit {
+        ZhCN: "编译被内建函数`Exit`结束, 退出码: {0}",
+        EnUS: "compilation exited by builtin `Exit` with code {0}",
+    }
+    ArrayLenNotConst {
+        ZhCN: "数组的长度 {0} 不是编译期可以求出的非负整数",
+        EnUS: "array length {0} is not a non-negative integer known at compile time",
+    }
+    ArrayIndexOutOfBounds {
+        ZhCN: "数组下标 {0} 超出了范围 0..{1}",
+        EnUS: "array index {0} is out of range 0..{1}",
+    }
+    ArrayIndexCount {
+        ZhCN: "访问数组需要一个下标, 但传入了 {0} 个",
+        EnUS: "array access takes one index, but {0} were given",
+    }
+    RecordFieldNotFound {
+        ZhCN: "记录中不存在字段 {0}",
+        EnUS: "no field {0} in the record",
+    }
+    RecordNotPlaced {
+        ZhCN: "记录布局未被放置在内存中, 无法写入字段 {0}",
+        EnUS: "cannot write field {0} of a record layout that is not placed in memory",
+    }
+    RecordLayoutMismatch {
+        ZhCN: "记录的字段不同, 无法复制: [{0}] <- [{1}]",
+        EnUS: "cannot copy between records with different fields: [{0}] <- [{1}]",
+    }
+    ConstForRangeNotConst {
+        ZhCN: "`const for`的范围边界 {0} 不是编译期可以求出的整数",
+        EnUS: "`const for` range bound {0} is not an integer known at compile time",
+    }
+    ConstForTooManyIterations {
+        ZhCN: "`const for`的范围有 {0} 次迭代, 超出了最大迭代次数 ({1})",
+        EnUS: "`const for` range has {0} iterations, exceeding the maximum ({1})",
+    }
+    ConstIfNotConst {
+        ZhCN: "`const if`的条件不是编译期可以求出的",
+        EnUS: "`const if` condition is not known at compile time",
+    }
+    ConstReprVar {
+        ZhCN: "被const的值中出现了原始变量 {0}",
+        EnUS: "raw variable {0} found in a consted value",
+    }
+    JumpCmpArgsCount {
+        ZhCN: "参数数量错误, 预期3个参数, 得到{0}个参数: {1}",
+        EnUS: "args count error, expected 3 args, found {0} args: {1}",
+    }
+    UnknownComparer {
+        ZhCN: "未知的比较符: {0}, 参数为: {1}",
+        EnUS: "unknown comparer: {0}, args: {1}",
+    }
+    OpArgsCount {
+        ZhCN: "参数数量错误, 预期4个参数, 得到{0}个参数: {1}",
+        EnUS: "args count error, expected 4 args, found {0} args: {1}",
+    }
+    UnknownOperator {
+        ZhCN: "未知的运算符: {0}, 参数为: {1}",
+        EnUS: "unknown operator: {0}, args: {1}",
+    }
+    LogicStringNoStop {
+        ZhCN: "未闭合的字符串, 在第{0}个字符处起始, 行:[{1}]",
+        EnUS: "unclosed string starting at char {0}, line:[{1}]",
+    }
+    BuiltinArgsCount {
+        ZhCN: "参数数量错误: argc := {0}",
+        EnUS: "args count error: argc := {0}",
+    }
+    BuiltinValueType {
+        ZhCN: "值类型错误:\n预期: {0}\n得到: {1}",
+        EnUS: "value type error:\nexpected: {0}\nfound: {1}",
+    }
+    BuiltinNotAString {
+        ZhCN: "{0} 不是一个字符串",
+        EnUS: "{0} is not a string",
+    }
+    BuiltinInvalidValue {
+        ZhCN: "无效的{0}值: {1}",
+        EnUS: "Invalid {0} value: {1}",
+    }
+    BuiltinError {
+        ZhCN: "内建函数错误:\n名称: {0}, 参数数: {1}\n退出码: {2}\n信息: {3}",
+        EnUS: "Builtin Function Error:\nname: {0}, argc: {1}\nexit_code: {2}\nmsg: {3}",
+    }
+    BuiltinInvalidExitCode {
+        ZhCN: "无效的退出码: {0},\n错误: {1}",
+        EnUS: "Invalid exit code: {0},\nerr: {1}",
+    }
+    BuiltinValueDebug {
+        ZhCN: "值调试:\n{0}",
+        EnUS: "Value Debug:\n{0}",
+    }
+    ExpandStackLog {
+        ZhCN: "展开栈:\n{0}",
+        EnUS: "Expand Stack:\n{0}",
+    }
+
+    // 诊断信息的附注
+    LineNote {
+        ZhCN: "行: {0}",
+        EnUS: "line: {0}",
+    }
+    NameNote {
+        ZhCN: "名称: {0}",
+        EnUS: "name: {0}",
+    }
+    ValueNote {
+        ZhCN: "值: {0}",
+        EnUS: "value: {0}",
+    }
+    FieldsNote {
+        ZhCN: "字段: {0}",
+        EnUS: "fields: {0}",
+    }
+    ExpandingNote {
+        ZhCN: "展开: {0}",
+        EnUS: "expanding: {0}",
+    }
+    ExpandStackNote {
+        ZhCN: "展开栈: {0}",
+        EnUS: "expand stack: {0}",
+    }
+    ImportedByNote {
+        ZhCN: "由 {0} 导入",
+        EnUS: "imported by {0}",
+    }
+    ExpectedNote {
+        ZhCN: "预期: [{0}]",
+        EnUS: "expected: [{0}]",
+    }
+
+    // 解析
+    UnexpectedToken {
+        ZhCN: "找到不应出现的令牌: {0}",
+        EnUS: "unexpected token: {0}",
+    }
+    ExtraToken {
+        ZhCN: "找到多余的令牌: {0}",
+        EnUS: "extra token: {0}",
+    }
+    InvalidToken {
+        ZhCN: "找到无效的令牌: {0}",
+        EnUS: "invalid token: {0}",
+    }
+    UnexpectedEof {
+        ZhCN: "意外的结束",
+        EnUS: "unexpected end of file",
+    }
+    TestAttrInvalid {
+        ZhCN: "无法解析的测试属性: {0}",
+        EnUS: "invalid test attribute: {0}",
+    }
+    TestAttrExpectedBlock {
+        ZhCN: "测试属性之后需要紧跟一个块",
+        EnUS: "expected a block after test attributes",
+    }
+    TestAttrAssertWithoutTest {
+        ZhCN: "断言属性缺少对应的 #[test]",
+        EnUS: "assert attribute without #[test]",
+    }
+    TestAttrUnclosedBlock {
+        ZhCN: "测试块没有闭合",
+        EnUS: "unclosed test block",
+    }
+
+    // 标记码与逻辑语句
+    DuplicateTag {
+        ZhCN: "重复的标记: {0}",
+        EnUS: "duplicate tag: {0}",
+    }
+    TagOutOfRange {
+        ZhCN: "跳转到了不存在的标记: {0}",
+        EnUS: "jump to a nonexistent tag: {0}",
+    }
+    TagOutOfRangeBuild {
+        ZhCN: "进行了越界的跳转标签构建, 你可以查看是否在尾部编写了空的被跳转标签\n\
+               标签id: {0}, 目标行表:\n\
+               id \t-> target\n\
+               {1}",
+        EnUS: "jump to a tag out of range, check for an empty jump target label at the end\n\
+               tag id: {0}, target table:\n\
+               id \t-> target\n\
+               {1}",
+    }
+    ExplicitPanic {
+        ZhCN: "显式恐慌",
+        EnUS: "explicit panic",
+    }
+    InvalidJumpTarget {
+        ZhCN: "无效的跳转目标: {0}",
+        EnUS: "invalid jump target: {0}",
+    }
+    UnknownInstruction {
+        ZhCN: "未知的语句: {0}",
+        EnUS: "unknown instruction: {0}",
+    }
+    UnknownSubCommand {
+        ZhCN: "语句 {0} 未知的子命令: {1}",
+        EnUS: "unknown sub-command of {0}: {1}",
+    }
+    TooFewArgs {
+        ZhCN: "语句 {0} 至少需要{1}个参数, 但是只有{2}个",
+        EnUS: "{0} requires at least {1} arguments, found {2}",
+    }
+    TooManyArgs {
+        ZhCN: "语句 {0} 最多使用{1}个参数, 但是有{2}个",
+        EnUS: "{0} uses at most {1} arguments, found {2}",
+    }
+
+    // 模拟器
+    EmulatorUnclosedString {
+        ZhCN: "未闭合的字符串, 开始于第{0}个字符",
+        EnUS: "unclosed string starting at char {0}",
+    }
+    EmulatorUnknownOper {
+        ZhCN: "未知的运算: {0}",
+        EnUS: "unknown operator: {0}",
+    }
+    EmulatorUnknownCmp {
+        ZhCN: "未知的比较: {0}",
+        EnUS: "unknown comparer: {0}",
+    }
+
+    // 编译器
+    Unknown {
+        ZhCN: "<未知>",
+        EnUS: "<unknown>",
+    }
+    VarsReused {
+        ZhCN: "程序共使用{0}个变量, 复用临时变量减少了{1}个",
+        EnUS: "the program uses {0} variables, reusing temporary variables saved {1}",
+    }
+    InstructionLimitExceeded {
+        ZhCN: "生成了{0}条语句, 超出了{1}条的限制",
+        EnUS: "generated {0} instructions, exceeding the limit of {1}",
+    }
+    LogicLineBuildFailed {
+        ZhCN: "在构建第{0}行时出错: {1}",
+        EnUS: "error building line {0}: {1}",
+    }
+    SizeReportTotal {
+        ZhCN: "共生成了{0}条语句",
+        EnUS: "generated {0} instructions in total",
+    }
+    SizeReportRest {
+        ZhCN: "{0} 其余{1}项",
+        EnUS: "{0} {1} other entries",
+    }
+
+    // 测试
+    TestCompileFailed {
+        ZhCN: "编译失败",
+        EnUS: "compile failed",
+    }
+    TestLogicInvalid {
+        ZhCN: "无法运行第{0}行逻辑代码: {1}",
+        EnUS: "cannot run logic line {0}: {1}",
+    }
+    TestStepsExceeded {
+        ZhCN: "在{0}条语句内没有结束",
+        EnUS: "did not end within {0} steps",
+    }
+    TestPrintNotString {
+        ZhCN: "断言 print 的期望值需要是一个字符串, 而不是 {0}",
+        EnUS: "expected value of print must be a string, not {0}",
+    }
+    TestPrintAssertFailed {
+        ZhCN: "断言 `print = {0}` 失败, 实际打印了 {1}",
+        EnUS: "assertion `print = {0}` failed, printed {1}",
+    }
+    TestAssertFailed {
+        ZhCN: "断言 `{0} = {1}` 失败, 实际为 {2}",
+        EnUS: "assertion `{0} = {1}` failed, found {2}",
+    }
+    TestsRunning {
+        ZhCN: "在 {1} 中运行{0}个测试",
+        EnUS: "running {0} tests in {1}",
+    }
+    TestsResult {
+        ZhCN: "\n测试结果: {0}. {1}个通过; {2}个失败",
+        EnUS: "\ntest result: {0}. {1} passed; {2} failed",
+    }
+
+    // 命令行
+    OptionRequiresPath {
+        ZhCN: "选项 {0} 需要一个路径",
+        EnUS: "option {0} requires a PATH",
+    }
+    OptionGivenTwice {
+        ZhCN: "选项 {0} 被给定了多次",
+        EnUS: "option {0} is given more than once",
+    }
+    OptionRequiresNumber {
+        ZhCN: "选项 {0} 需要一个语句数",
+        EnUS: "option {0} requires a number N",
+    }
+    OptionRequiresSteps {
+        ZhCN: "选项 {0} 需要一个语句数",
+        EnUS: "option {0} requires a number of STEPS",
+    }
+    OptionRequiresLocale {
+        ZhCN: "选项 {0} 需要一个语言",
+        EnUS: "option {0} requires a LOCALE",
+    }
+    UnknownLocale {
+        ZhCN: "未知的语言: {0}",
+        EnUS: "unknown locale: {0}",
+    }
+    UnknownMessageFormat {
+        ZhCN: "未知的信息格式: {0}",
+        EnUS: "unknown message format: {0}",
+    }
+    UnknownOptionOrMode {
+        ZhCN: "未知的选项或模式: {0}",
+        EnUS: "unknown option or mode: {0}",
+    }
+    UnknownOption {
+        ZhCN: "未知的选项: {0}",
+        EnUS: "unknown option: {0}",
+    }
+    NoMode {
+        ZhCN: "缺少模式",
+        EnUS: "no MODE",
+    }
+    UnknownMode {
+        ZhCN: "未知的模式: {0}",
+        EnUS: "mode {0} no pattern",
+    }
+    RunRequiresLogicOutput {
+        ZhCN: "选项 --run 需要最后一个模式输出逻辑代码",
+        EnUS: "option --run requires the last mode to output MdtLogicCode",
+    }
+    SourceMapRequiresOutput {
+        ZhCN: "选项 --source-map 需要 --output, 并且不能与 --run 一同使用",
+        EnUS: "option --source-map requires --output and conflicts with --run",
+    }
+    SourceMapRequiresLogicMode {
+        ZhCN: "选项 --source-map 需要最后一个模式为 c",
+        EnUS: "option --source-map requires the last mode to be c",
+    }
+    SameOutputPath {
+        ZhCN: "输入 {0} 与 {1} 的输出路径均为 {2}",
+        EnUS: "inputs {0} and {1} have the same output path {2}",
+    }
+    CreateOutputDirFailed {
+        ZhCN: "创建输出目录 {0} 失败: {1}",
+        EnUS: "create output directory {0} error: {1}",
+    }
+    LogicParseFailed {
+        ZhCN: "在解析第{0}行逻辑代码时出错: {1}",
+        EnUS: "error parsing logic line {0}: {1}",
+    }
+    ReadStdinFailed {
+        ZhCN: "从标准输入读取失败: {0}",
+        EnUS: "read from stdin error: {0}",
+    }
+    ReadFileFailed {
+        ZhCN: "从 {0} 读取失败: {1}",
+        EnUS: "read from {0} error: {1}",
+    }
+    WriteFileFailed {
+        ZhCN: "写入到 {0} 失败: {1}",
+        EnUS: "write to {0} error: {1}",
+    }
+}
+
+impl Message {
+    /// 获取这种信息在给定语言中的模板, 没有时使用默认语言的模板
+    pub fn template(self, locale: Locale) -> &'static str {
+        self.find_template(locale)
+            .or_else(|| self.find_template(Locale::default()))
+            .unwrap_or_default()
+    }
+
+    /// 使用当前语言的模板与参数生成信息, 一般使用[`msg!`]
+    ///
+    /// [`msg!`]: crate::msg
+    pub fn format(self, args: &[&dyn Display]) -> String {
+        format_template(self.template(locale()), args)
+    }
+}
+
+/// 将模板中的`{N}`替换为第N个参数, 不存在的参数将保持原样
+fn format_template(template: &str, args: &[&dyn Display]) -> String {
+    let mut res = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        res.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            res.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        let arg = rest.find('}')
+            .filter(|_| rest.starts_with('{'))
+            .and_then(|end| {
+                let arg = args.get(rest[1..end].parse::<usize>().ok()?)?;
+                Some((arg, end))
+            });
+        match arg {
+            Some((arg, end)) => {
+                res.push_str(&arg.to_string());
+                rest = &rest[end+1..];
+            },
+            None => {
+                res.push_str(&rest[..1]);
+                rest = &rest[1..];
+            },
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// 使用当前语言生成一条[`Message`]信息, 参数依次对应模板中的`{0}`, `{1}`等
+///
+/// # Examples
+/// ```
+/// # use utils::{msg, locale::{Locale, set_locale}};
+/// set_locale(Locale::EnUS);
+/// assert_eq!(msg!(ArrayIndexOutOfBounds, 5, 3),
+///            "array index 5 is out of range 0..3");
+/// set_locale(Locale::ZhCN);
+/// assert_eq!(msg!(ArrayIndexOutOfBounds, 5, 3),
+///            "数组下标 5 超出了范围 0..3");
+/// ```
+#[macro_export]
+macro_rules! msg {
+    ($name:ident $(, $arg:expr)* $(,)?) => {
+        $crate::message::Message::$name.format(&[
+            $(&$arg as &dyn ::std::fmt::Display),*
+        ])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_template_test() {
+        let args: [&dyn Display; 2] = [&1, &"a"];
+        assert_eq!(format_template("{1} {0}", &args), "a 1");
+        assert_eq!(format_template("{{0}} {0}}}", &args), "{0} 1}");
+        assert_eq!(format_template("{2} {x} {", &args), "{2} {x} {");
+    }
+}
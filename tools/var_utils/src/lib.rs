@@ -14,15 +14,16 @@ pub fn is_ident(s: &str) -> bool {
 }
 
 pub const VAR_KEYWORDS: &[&str] = {&[
-    "_", "abs", "acos", "add", "always", "and", "angle",
-    "asin", "atan", "break", "case", "ceil", "const", "continue",
-    "cos", "div", "do", "elif", "else", "equal", "floor",
-    "goto", "greaterThan", "greaterThanEq", "gwhile", "idiv", "if", "inline",
-    "land", "len", "lessThan", "lessThanEq", "lnot", "log", "match", "max",
-    "min", "mod", "mul", "noise", "noop", "not", "notEqual",
-    "op", "or", "pow", "print", "rand", "select", "set",
-    "setres", "shl", "shr", "sin", "skip", "sqrt", "strictEqual",
-    "strictNotEqual", "sub", "switch", "take", "tan", "while", "xor",
+    "_", "abs", "acos", "add", "always", "and", "angle", "array",
+    "asin", "atan", "break", "call", "case", "ceil", "const", "continue",
+    "cos", "div", "do", "elif", "else", "equal", "floor", "fn", "for",
+    "goto", "greaterThan", "greaterThanEq", "gwhile", "idiv", "if", "import", "in",
+    "inline", "land", "len", "lessThan", "lessThanEq", "lnot", "log",
+    "match", "max", "min", "mod", "mul", "noise", "noop",
+    "not", "notEqual", "op", "or", "pow", "print", "rand", "record",
+    "select", "set", "setres", "shl", "shr", "sin", "skip",
+    "sqrt", "strictEqual", "strictNotEqual", "sub", "switch", "take", "tan",
+    "while", "xor",
 ]};
 
 /// 判断是否是一个标识符(包括数字)关键字